@@ -1,31 +1,274 @@
 #![allow(dead_code)]
 #![allow(unused_assignments)]
-mod builtin_handlers;
+pub mod arith;
+pub mod audit;
+pub mod bindings;
+pub mod brace;
+pub(crate) mod builtin_handlers;
+pub mod ccenv;
+pub mod env;
 pub mod executable;
+pub mod expand;
+pub mod fds;
+pub mod glob;
+pub mod hooks;
+pub mod jobs;
+pub mod list;
+pub mod options;
+pub mod resolve;
+pub mod resources;
+pub mod select;
+pub mod subst;
+use crate::error::{warn, ShellError};
+use crate::history;
 use builtin_handlers::*;
+use env::ShellEnv;
 use executable::Executable;
-use std::fs::{File, OpenOptions};
-use std::process::{Command, Stdio};
+use expand::{Assignment, Word, WordPart};
+use fds::SavedFds;
+use jobs::Jobs;
+use list::CommandList;
+use resources::CpuTimes;
+use select::Select;
+use serde::Serialize;
+use serde_json::json;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::Instant;
 
 pub struct Cmdline {
     // piped stages
     pub stages: Vec<Cmd>,
+
+    // Set by a leading `time` keyword
+    pub time: Option<TimeFormat>,
+
+    // Followed by `&`: started without waiting for it to finish
+    pub background: bool,
+
+    // The pipeline as written, for the jobs table
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    // real/user/sys as `0m0.000s`
+    Default,
+    // `time -p`: seconds with two decimals
+    Posix,
+}
+
+impl Cmdline {
+    /// Parses the pipeline `text`, a part of the command line `input` that
+    /// syntax errors point into. `next` is the token after the pipeline.
+    pub fn parse(input: &str, text: &str, next: &str) -> Result<Self, ShellError> {
+        let offset = |s: &str| s.as_ptr() as usize - input.as_ptr() as usize;
+        let source = text.trim().to_string();
+        let (time, text) = strip_time_keyword(text);
+        if text.trim().is_empty() {
+            // `time` on its own times nothing
+            return Ok(Self {
+                stages: Vec::new(),
+                time,
+                background: false,
+                text: String::new(),
+            });
+        }
+
+        let parts = split_pipeline(text);
+        let last = parts.len() - 1;
+        let mut stages = Vec::new();
+        for (i, stage) in parts.iter().enumerate() {
+            let next_token = if i == last { next } else { "|" };
+            if stage.trim().is_empty() {
+                // Where the `|` after this stage is, or the end of the pipeline
+                let end = offset(stage) + stage.len();
+                let message = if i == last && next == "newline" {
+                    "syntax error: unexpected end of file".to_string()
+                } else {
+                    format!("syntax error near unexpected token `{}'", next_token)
+                };
+                return Err(ShellError::syntax(message, input, end));
+            }
+            stages.push(Cmd::parse(input, stage, next_token)?);
+        }
+        Ok(Self {
+            stages,
+            time,
+            background: false,
+            text: source,
+        })
+    }
+
+    pub fn execute(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        let Some(format) = self.time else {
+            return self.execute_untimed(env);
+        };
+        // Builtins run in the shell itself, so its own CPU time counts too
+        let start = Instant::now();
+        let self_before = CpuTimes::of(libc::RUSAGE_SELF);
+        let children_before = env.child_times;
+        let result = self.execute_untimed(env);
+        let real = start.elapsed();
+        let mut cpu = CpuTimes::of(libc::RUSAGE_SELF) - self_before;
+        cpu += env.child_times - children_before;
+        match format {
+            TimeFormat::Default => errln!(
+                "\nreal\t{}\nuser\t{}\nsys\t{}",
+                resources::format_minutes(real),
+                resources::format_minutes(cpu.user),
+                resources::format_minutes(cpu.sys)
+            ),
+            TimeFormat::Posix => errln!(
+                "real {:.2}\nuser {:.2}\nsys {:.2}",
+                real.as_secs_f64(),
+                cpu.user.as_secs_f64(),
+                cpu.sys.as_secs_f64()
+            ),
+        }
+        result
+    }
+
+    fn execute_untimed(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        match self.stages.as_mut_slice() {
+            [] => return Ok(()),
+            [cmd] => return cmd.handle_execs(env),
+            _ => {}
+        }
+        env.last_status = self.run_pipeline(env)?;
+        Ok(())
+    }
+
+    // Forks one child per stage, connected by pipes. The status of the
+    // pipeline is that of its last stage, which with `lastpipe` runs in the
+    // shell so that e.g. `read` there sets variables of the shell.
+    fn run_pipeline(&mut self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let last = self.stages.len() - 1;
+        let mut stdin: Option<OwnedFd> = None;
+        let mut pids = Vec::new();
+        let mut last_status = None;
+        for (i, stage) in self.stages.iter_mut().enumerate() {
+            if i == last && env.options.lastpipe {
+                if let Some(stdin) = stdin.take() {
+                    last_status = Some(stage.run_reading(stdin, env));
+                }
+                break;
+            }
+            let (next_stdin, stdout) = if i < last {
+                let (read, write) = pipe().map_err(|e| ShellError::io("pipe", e))?;
+                (Some(read), Some(write))
+            } else {
+                (None, None)
+            };
+            match unsafe { libc::fork() } {
+                -1 => return Err(ShellError::io("fork", std::io::Error::last_os_error())),
+                0 => {
+                    // Child: wire the pipe ends to stdin/stdout and run the stage
+                    drop(next_stdin);
+                    unsafe {
+                        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+                        if let Some(fd) = &stdin {
+                            libc::dup2(fd.as_raw_fd(), 0);
+                        }
+                        if let Some(fd) = &stdout {
+                            libc::dup2(fd.as_raw_fd(), 1);
+                        }
+                    }
+                    drop(stdin);
+                    drop(stdout);
+                    let status = stage.run_in_child(env);
+                    let _ = std::io::stdout().flush();
+                    unsafe { libc::_exit(status) }
+                }
+                pid => pids.push(pid),
+            }
+            stdin = next_stdin;
+        }
+
+        let mut status = 0;
+        for pid in pids {
+            let times;
+            (status, times) = wait_pid(pid);
+            env.child_times += times;
+        }
+        last_status.unwrap_or(Ok(status))
+    }
+
+    /// Describes the parsed and expanded command line, for `--dry-run`.
+    pub fn describe(&mut self, env: &mut ShellEnv) -> Result<serde_json::Value, ShellError> {
+        let mut stages = Vec::new();
+        for stage in &mut self.stages {
+            stage.expand(env)?;
+            let mut assignments = Vec::new();
+            for a in &stage.assignments {
+                assignments.push(json!({
+                    "name": a.name,
+                    "value": expand::expand_word_string(&a.value, env)
+                        .map_err(ShellError::Expansion)?,
+                }));
+            }
+            if let Some(list) = &mut stage.subshell {
+                stages.push(json!({
+                    "subshell": list.describe(env)?,
+                    "redirections": stage.redirects,
+                }));
+                continue;
+            }
+            if let Some(select) = &mut stage.select {
+                stages.push(json!({
+                    "select": select.describe(env)?,
+                    "redirections": stage.redirects,
+                }));
+                continue;
+            }
+            stages.push(json!({
+                "assignments": assignments,
+                "args": stage.operands(),
+                "builtin": !matches!(stage.exec, Executable::Unknown(_)),
+                "redirections": stage.redirects,
+            }));
+        }
+        Ok(json!({ "stages": stages, "time": self.time }))
+    }
 }
 
 pub struct Cmd {
+    // Words as parsed, expanded into `args` before execution
+    pub words: Vec<Word>,
+
+    // `NAME=value` words preceding the command name
+    pub assignments: Vec<Assignment>,
+
     // The command to execute (corresponds to existing Command enum)
     pub exec: Executable,
 
     // Arguments for the command
     pub args: Vec<String>,
 
-    // Redirection information
-    pub stdout_redirect: Option<RedirectInfo>,
-    pub stderr_redirect: Option<RedirectInfo>,
-    pub stdin_redirect: Option<RedirectInfo>,
+    // For each argument, the assignment it is when the command is `declare`
+    // and its word an assignment, expanded once as such
+    pub declared: Vec<Option<expand::ExpandedAssignment>>,
+
+    // Redirections, applied in order
+    pub redirects: Vec<RedirectInfo>,
+
+    // For `( list )`: the list, run in a forked copy of the shell. The
+    // words are then only the redirections after the `)`.
+    pub subshell: Option<CommandList>,
+
+    // For `select ... done`, run in the shell itself, likewise followed
+    // only by redirections
+    pub select: Option<Select>,
 }
 
+#[derive(Serialize)]
 pub struct RedirectInfo {
+    // file name, or the descriptor word of `>&` and `<&` (`-` closes)
     pub path: String,
     // descriptor number (default: 1 for stdout)
     pub fd: u32,
@@ -33,6 +276,30 @@ pub struct RedirectInfo {
     pub mode: RedirectMode,
 }
 
+impl RedirectInfo {
+    /// Whether this is `n>&-` or `n<&-`, closing the descriptor.
+    pub fn closes(&self) -> bool {
+        matches!(self.mode, RedirectMode::DupOutput | RedirectMode::DupInput) && self.path == "-"
+    }
+}
+
+impl fmt::Display for RedirectInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.mode {
+            RedirectMode::Write => ">",
+            RedirectMode::ForceWrite => ">|",
+            RedirectMode::Append => ">>",
+            RedirectMode::Read => "<",
+            RedirectMode::ReadWrite => "<>",
+            RedirectMode::DupOutput => ">&",
+            RedirectMode::DupInput => "<&",
+        };
+        write!(f, "{}{}{}", self.fd, op, self.path)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RedirectMode {
     // > - Write to file (create or truncate)
     Write,
@@ -42,279 +309,1221 @@ pub enum RedirectMode {
     Append,
     // < - Read from file
     Read,
+    // <> - Open for reading and writing
+    ReadWrite,
+    // >&n - Duplicate an output descriptor, or close it with >&-
+    DupOutput,
+    // <&n - Duplicate an input descriptor, or close it with <&-
+    DupInput,
 }
 
 impl Cmd {
-    pub fn new(input: &str) -> Self {
-        let args = Cmd::parse_args(input);
-        let (stdout_redirect, stderr_redirect, stdin_redirect) =
-            Cmd::ensure_redirect(&args).unwrap();
-        let exec = if let Some(cmd_name) = args.first() {
+    /// Parses a pipeline stage `text`: a simple command, or a subshell or
+    /// `select` followed by redirections. `next` is the token after the stage;
+    /// syntax errors point into the command line `input`.
+    pub fn parse(input: &str, text: &str, next: &str) -> Result<Self, ShellError> {
+        let offset = |s: &str| s.as_ptr() as usize - input.as_ptr() as usize;
+        let mut rest = text;
+        let mut subshell = None;
+        let mut select = None;
+        let trimmed = text.trim_start();
+        if trimmed.starts_with('(') {
+            // The scan for unclosed parentheses ensures there is a `)`
+            let close = unquoted_chars(trimmed)
+                .into_iter()
+                .find(|&(_, c)| c == ')')
+                .map_or(trimmed.len(), |(i, _)| i);
+            let body = &trimmed[1..close];
+            if body.trim().is_empty() {
+                let message = "syntax error near unexpected token `)'";
+                return Err(ShellError::syntax(message, input, offset(trimmed) + close));
+            }
+            subshell = Some(CommandList::parse(input, body)?);
+            rest = trimmed.get(close + 1..).unwrap_or_default();
+        } else if let Some(&(0, Some(end))) =
+            select_regions(trimmed, &scan_unquoted(trimmed)).first()
+        {
+            // The scan for unclosed `select`s ensures there is a `done`
+            select = Some(Select::parse(input, &trimmed[..end])?);
+            rest = &trimmed[end..];
+        }
+
+        let words = Cmd::parse_args(rest);
+        check_redirects(input, rest, &words, next)?;
+        if subshell.is_some() || select.is_some() {
+            // Only redirections may follow the `)` or `done`
+            let mut i = 0;
+            while let Some(word) = words.get(i) {
+                if word.as_operator().is_some() {
+                    i += 2;
+                    continue;
+                }
+                let token = rest.split_whitespace().nth(i).unwrap_or(rest.trim());
+                let column = offset(rest) + rest.find(token).unwrap_or(0);
+                let message = format!("syntax error near unexpected token `{}'", token);
+                return Err(ShellError::syntax(message, input, column));
+            }
+        }
+
+        Ok(Self {
+            words,
+            assignments: Vec::new(),
+            exec: Executable::Unknown("".to_string()),
+            args: Vec::new(),
+            declared: Vec::new(),
+            redirects: Vec::new(),
+            subshell,
+            select,
+        })
+    }
+
+    /// Expands the parsed words into assignments, arguments and redirections.
+    /// Only the operators found by parsing redirect, not words that expand
+    /// to one, such as `">"` or `$op`.
+    pub fn expand(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        let words = self.words.clone();
+        self.expand_from(&words, env)
+    }
+
+    // Expands `words`: this command's words, possibly with its process
+    // substitutions already started
+    fn expand_from(&mut self, words: &[Word], env: &mut ShellEnv) -> Result<(), ShellError> {
+        let mut words = words.iter().peekable();
+        self.assignments.clear();
+        while let Some(assignment) = words.peek().and_then(|w| w.as_assignment()) {
+            self.assignments.push(assignment);
+            words.next();
+        }
+        env.subst_status = None;
+        self.args.clear();
+        self.declared.clear();
+        self.redirects.clear();
+        while let Some(word) = words.next() {
+            let Some(op) = word.as_operator() else {
+                if let Some(assignment) = word.as_assignment().filter(|_| declares(&self.args)) {
+                    let assignment = expand::expand_assignment(&assignment, env)
+                        .map_err(ShellError::Expansion)?;
+                    self.args.push(assignment.to_string());
+                    self.declared.push(Some(assignment));
+                    continue;
+                }
+                let fields = expand::expand_word(word, env).map_err(ShellError::Expansion)?;
+                self.args.extend(fields);
+                self.declared.resize_with(self.args.len(), || None);
+                continue;
+            };
+            // Parsing made sure that an operator has a target
+            let target = match words.next() {
+                Some(target) => expand::expand_word(target, env).map_err(ShellError::Expansion)?,
+                None => Vec::new(),
+            };
+            let [path] = <[String; 1]>::try_from(target)
+                .map_err(|_| ShellError::Redirect(format!("{}: ambiguous redirect", op)))?;
+            self.redirects.extend(Cmd::parse_redirect(op, path)?);
+        }
+        self.check_restricted(env)?;
+        self.exec = if let Some(cmd_name) = self.operands().first() {
             Executable::from(cmd_name.clone())
         } else {
             Executable::Unknown("".to_string())
         };
-        Self {
-            exec,
-            args,
-            stdout_redirect,
-            stderr_redirect,
-            stdin_redirect,
+        Ok(())
+    }
+
+    // A restricted shell may not open files for writing, though output
+    // may still be moved to another descriptor, as in `>&2`
+    fn check_restricted(&self, env: &ShellEnv) -> Result<(), ShellError> {
+        if !env.options.restricted {
+            return Ok(());
+        }
+        let writing = self.redirects.iter().find(|redirect| {
+            matches!(
+                redirect.mode,
+                RedirectMode::Write
+                    | RedirectMode::ForceWrite
+                    | RedirectMode::Append
+                    | RedirectMode::ReadWrite
+            )
+        });
+        match writing {
+            Some(redirect) => Err(ShellError::restricted(
+                &redirect.path,
+                Some("cannot redirect output"),
+            )),
+            None => Ok(()),
         }
     }
-    pub fn handle_execs(&self) -> Result<(), Box<dyn std::error::Error>> {
-        match self.exec {
-            Executable::Cd => handle_cd(&self.args),
-            Executable::Exit => handle_exit(&self.args),
-            Executable::Echo => handle_echo(self)?,
-            Executable::Pwd => handle_pwd(),
-            Executable::Type => handle_type(&self.args),
-            Executable::Unknown(_) => self.cmd_exec()?,
+
+    /// The arguments, the redirections being kept apart in `redirects`.
+    pub fn operands(&self) -> Vec<String> {
+        self.args.clone()
+    }
+
+    // With `set -x`, prints the expanded command prefixed by $PS4. The
+    // assignments are given as performed.
+    fn trace(&self, assignments: &[String], env: &ShellEnv) {
+        if !env.options.xtrace {
+            return;
         }
+        let ps4 = expand::expand_inline(&env.get("PS4").unwrap_or_else(|| "+ ".into()), env);
+        let mut words = assignments.to_vec();
+        words.extend(self.operands().iter().map(|arg| shell_quote(arg)));
+        errln!("{}{}", ps4, words.join(" "));
+    }
+
+    // Performs the assignments before a command, returning them as shown
+    // by xtrace. Errors are reported and make the status 1.
+    fn assign(&self, env: &mut ShellEnv) -> (Vec<String>, i32) {
+        let mut shown = Vec::new();
+        let mut status = 0;
+        for assignment in &self.assignments {
+            match expand::assign(assignment, env) {
+                Ok(assigned) => shown.push(assigned),
+                Err(e) => {
+                    ShellError::Expansion(e).report();
+                    status = 1;
+                }
+            }
+        }
+        (shown, status)
+    }
+
+    pub fn handle_execs(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        // Helpers of process substitutions are reaped once the command is done
+        let (words, _substitutions) = subst::start(&self.words, env)?;
+        self.expand_from(&words, env)?;
+        if self.subshell.is_some() {
+            return self.run_subshell(env);
+        }
+        if self.select.is_some() {
+            return self.run_select(env);
+        }
+        self.run(env)
+    }
+
+    // Runs `( list )` in a forked copy of the shell, so that nothing the
+    // list changes, such as variables or the working directory, outlives it
+    fn run_subshell(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        let _ = std::io::stdout().flush();
+        match unsafe { libc::fork() } {
+            -1 => Err(ShellError::io("fork", std::io::Error::last_os_error())),
+            0 => {
+                unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+                env.jobs = Jobs::default();
+                let status = self.exec_subshell(env);
+                let _ = std::io::stdout().flush();
+                unsafe { libc::_exit(status) }
+            }
+            pid => {
+                let (status, times) = wait_pid(pid);
+                env.child_times += times;
+                env.last_status = status;
+                Ok(())
+            }
+        }
+    }
+
+    // The child's side of a subshell: applies the redirections after the
+    // `)` and runs the list, returning its status
+    fn exec_subshell(&mut self, env: &mut ShellEnv) -> i32 {
+        if let Err(e) = fds::redirect_all(&self.redirects, &env.cwd) {
+            ShellError::Redirect(e).report();
+            return 1;
+        }
+        if let Some(list) = &mut self.subshell {
+            list.execute(env);
+        }
+        env.last_status
+    }
+
+    // Runs `select ... done` with the redirections after the `done`
+    fn run_select(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        let _saved = SavedFds::apply(&self.redirects, &env.cwd).map_err(ShellError::Redirect)?;
+        match &mut self.select {
+            Some(select) => select.run(env),
+            None => Ok(()),
+        }
+    }
+
+    // Runs the last stage of a pipeline in the shell itself, reading from
+    // `stdin`, for `shopt -s lastpipe`
+    fn run_reading(&mut self, stdin: OwnedFd, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let input = RedirectInfo {
+            fd: 0,
+            path: stdin.as_raw_fd().to_string(),
+            mode: RedirectMode::DupInput,
+        };
+        let _saved = SavedFds::apply(&[input], &env.cwd).map_err(ShellError::Redirect)?;
+        drop(stdin);
+        self.handle_execs(env)?;
+        Ok(env.last_status)
+    }
+
+    // Runs a pipeline stage in a forked child and returns its exit status.
+    // External commands replace the child process instead of forking again.
+    fn run_in_child(&mut self, env: &mut ShellEnv) -> i32 {
+        match self.exec_in_child(env) {
+            Ok(status) => status,
+            Err(e) => {
+                e.report();
+                e.status()
+            }
+        }
+    }
+
+    fn exec_in_child(&mut self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let (words, _substitutions) = subst::start(&self.words, env)?;
+        self.expand_from(&words, env)?;
+        if self.subshell.is_some() {
+            // Already in a child of its own
+            return Ok(self.exec_subshell(env));
+        }
+        if self.select.is_some() {
+            self.run_select(env)?;
+            return Ok(env.last_status);
+        }
+        // With an audit log, the command is waited for so that its status
+        // can be recorded, rather than replacing the child
+        if let (Executable::Unknown(_), false, None) =
+            (&self.exec, self.operands().is_empty(), &env.audit)
+        {
+            fds::redirect_all(&self.redirects, &env.cwd).map_err(ShellError::Redirect)?;
+            // The child is about to be replaced, so assignments need no undoing
+            let (assigned, _) = self.assign(env);
+            self.trace(&assigned, env);
+            let mut command = self.build_command(env, None)?;
+            let error = command.exec();
+            return Err(self.exec_error(error));
+        }
+        self.run(env)?;
+        Ok(env.last_status)
+    }
+
+    fn run(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        // Where and when the command started, for the audit log, which
+        // records it even if it fails before running, as when a
+        // redirection can't be made. Assignments alone aren't recorded.
+        let started = env
+            .audit
+            .as_ref()
+            .filter(|_| !self.operands().is_empty())
+            .map(|_| {
+                (
+                    history::now(),
+                    env.cwd.clone(),
+                    self.operands(),
+                    Instant::now(),
+                )
+            });
+        let result = self.run_unrecorded(env);
+        if let (Some(log), Some((timestamp, cwd, argv, start))) = (&env.audit, started) {
+            log.record(&audit::Entry {
+                timestamp,
+                cwd: &cwd,
+                argv: &argv,
+                redirections: &self.redirects,
+                status: result
+                    .as_ref()
+                    .map_or_else(ShellError::status, |()| env.last_status),
+                duration: start.elapsed().as_secs_f64(),
+            });
+        }
+        result
+    }
+
+    fn run_unrecorded(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        // Redirections only last for this command, except those of `exec`
+        let _saved = if let Executable::Exec = self.exec {
+            None
+        } else {
+            Some(SavedFds::apply(&self.redirects, &env.cwd).map_err(ShellError::Redirect)?)
+        };
+
+        if self.operands().is_empty() {
+            // Only assignments: they persist in the shell. The status is
+            // that of the last command substitution, if any.
+            let (assigned, status) = self.assign(env);
+            self.trace(&assigned, env);
+            env.last_arg.clear();
+            env.last_status = match env.subst_status.take() {
+                Some(subst_status) if status == 0 => subst_status,
+                _ => status,
+            };
+            return Ok(());
+        }
+
+        // Assignments before a command only last for that command
+        let mut saved = Vec::new();
+        for assignment in &self.assignments {
+            saved.push((assignment.name.clone(), env.take(&assignment.name)));
+        }
+        let (assigned, _) = self.assign(env);
+        self.trace(&assigned, env);
+
+        let result = match self.dispatch(env) {
+            // Reported while stderr is still redirected, so that
+            // `nosuch 2>/dev/null` stays quiet
+            Err(e @ (ShellError::CommandNotFound(_) | ShellError::NotExecutable { .. })) => {
+                e.report();
+                Ok(e.status())
+            }
+            result => result,
+        };
+        env.last_arg = self.operands().pop().unwrap_or_default();
+
+        for (name, old) in saved.into_iter().rev() {
+            env.restore(&name, old);
+        }
+        env.last_status = result?;
         Ok(())
     }
 
-    fn parse_args(input: &str) -> Vec<String> {
-        let mut args = Vec::new();
-        let mut current_arg = String::new();
+    // Runs the builtin or external command and returns its status
+    fn dispatch(&mut self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let status = match self.exec {
+            Executable::Cd => handle_cd(self, env),
+            Executable::Ccenv => handle_ccenv(self, env),
+            Executable::Bind => handle_bind(self, env),
+            Executable::Break => handle_break(self, env),
+            Executable::Builtin => return self.run_builtin(env),
+            Executable::Command => return self.run_command(env),
+            Executable::Declare => handle_declare(self, env),
+            Executable::Exit => handle_exit(self, env),
+            Executable::Echo => handle_echo(self),
+            Executable::Eval => handle_eval(self, env),
+            Executable::Exec => handle_exec(self, env),
+            Executable::Getopts => handle_getopts(self, env),
+            Executable::Hash => handle_hash(self, env),
+            Executable::Pwd => {
+                handle_pwd(env);
+                0
+            }
+            Executable::Read => handle_read(self, env),
+            Executable::Set => handle_set(&self.args, env),
+            Executable::Shift => handle_shift(self, env),
+            Executable::Shopt => handle_shopt(self, env),
+            Executable::Source => handle_source(self, env),
+            Executable::Times => handle_times(),
+            Executable::Type => handle_type(self, env),
+            Executable::Ulimit => handle_ulimit(self),
+            Executable::Umask => handle_umask(self),
+            Executable::Unset => handle_unset(&self.args, env),
+            Executable::Wait => handle_wait(self, env),
+            Executable::Unknown(_) => return self.cmd_exec(env, None),
+        };
+        Ok(status)
+    }
+
+    // Drops the first `n` operands, such as `command` and its options, so
+    // that the rest runs as a command of its own
+    fn shift_operands(&mut self, n: usize) {
+        let n = n.min(self.args.len());
+        self.args.drain(..n);
+        self.declared.drain(..n);
+        self.exec = match self.operands().first() {
+            Some(name) => Executable::from(name.clone()),
+            None => Executable::Unknown(String::new()),
+        };
+    }
+
+    // builtin name [args ...]: runs a builtin even if a command of the
+    // same name comes first
+    fn run_builtin(&mut self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        self.shift_operands(1);
+        match &self.exec {
+            Executable::Unknown(name) if name.is_empty() => Ok(0),
+            Executable::Unknown(name) => {
+                warn("builtin", format_args!("{}: not a shell builtin", name));
+                Ok(1)
+            }
+            _ => self.dispatch(env),
+        }
+    }
+
+    // command [-pVv] name [args ...]: runs a builtin or external command,
+    // or with -v/-V describes how the names would be run
+    fn run_command(&mut self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let operands = self.operands();
+        let mut default_path = false;
+        let mut describe = None;
+        let mut used = 1;
+        for arg in &operands[1..] {
+            if arg == "--" {
+                used += 1;
+                break;
+            }
+            let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+                break;
+            };
+            for flag in flags.chars() {
+                match flag {
+                    'p' => default_path = true,
+                    'v' | 'V' => describe = Some(flag),
+                    _ => {
+                        warn("command", format_args!("-{}: invalid option", flag));
+                        errln!("command: usage: command [-pVv] command [arg ...]");
+                        return Ok(2);
+                    }
+                }
+            }
+            used += 1;
+        }
+        if default_path && env.options.restricted {
+            return Err(ShellError::restricted("command: -p", None));
+        }
+        let search = default_path.then_some(resolve::DEFAULT_PATH);
+
+        if let Some(flag) = describe {
+            let mut status = 0;
+            for name in &operands[used..] {
+                let found = match search {
+                    Some(search) => {
+                        resolve::search_path(name, search, &env.cwd, &env.policy, false)
+                            .into_iter()
+                            .next()
+                            .map(|path| resolve::Resolved::File {
+                                path,
+                                hashed: false,
+                            })
+                    }
+                    None => resolve::resolve(name, env, true),
+                };
+                match (found, flag) {
+                    (Some(resolve::Resolved::File { path, .. }), 'v') => {
+                        outln!("{}", path.display())
+                    }
+                    (Some(_), 'v') => outln!("{}", name),
+                    (Some(found), _) => outln!("{}", found.describe(name)),
+                    (None, 'v') => status = 1,
+                    (None, _) => {
+                        warn("command", format_args!("{}: not found", name));
+                        status = 1;
+                    }
+                }
+            }
+            return Ok(status);
+        }
+
+        self.shift_operands(used);
+        match self.exec {
+            Executable::Unknown(_) => self.cmd_exec(env, search),
+            _ => self.dispatch(env),
+        }
+    }
+
+    fn parse_args(input: &str) -> Vec<Word> {
+        Cmd::parse_words(input, true)
+    }
+
+    /// Parses `text` as one word, in which whitespace and `<`, `>` and
+    /// `(` are ordinary characters, as in the word of `${name:-word}`.
+    pub fn parse_word(text: &str) -> Word {
+        Cmd::parse_words(text, false).pop().unwrap_or_default()
+    }
+
+    // Splits `input` into words at unquoted whitespace and redirection
+    // operators, unless `split` is false
+    fn parse_words(input: &str, split: bool) -> Vec<Word> {
+        let mut words = Vec::new();
+        let mut current = Word::default();
+        // Whether `current` has started (it may still be empty, e.g. `""`)
+        let mut in_word = false;
         let mut in_single_quotes = false;
         let mut in_double_quotes = false;
         let mut escaped = false;
+        let mut chars = input.chars().peekable();
 
-        for c in input.chars() {
+        while let Some(c) = chars.next() {
             if escaped {
                 if in_double_quotes {
-                    if c == '$' || c == '`' || c == '"' || c == '\\' || c == '\n' {
-                        current_arg.push(c);
-                    } else {
-                        current_arg.push('\\');
-                        current_arg.push(c);
+                    if c == '$' || c == '`' || c == '"' || c == '\\' {
+                        push_quoted(&mut current, &c.to_string());
+                    } else if c != '\n' {
+                        push_quoted(&mut current, &format!("\\{}", c));
                     }
-                } else if in_single_quotes {
-                    // Inside single quotes, backslash has no special meaning
-                    current_arg.push('\\');
-                    current_arg.push(c);
-                } else if c == '\n' {
-                    // Line continuation outside quotes
-                    // Skip both the backslash and newline
-                } else {
-                    // Outside quotes, backslash escapes any character
-                    current_arg.push(c);
+                } else if c != '\n' {
+                    // Outside quotes, backslash escapes any character;
+                    // backslash-newline is a line continuation
+                    push_quoted(&mut current, &c.to_string());
+                    in_word = true;
                 }
                 escaped = false;
+            } else if in_single_quotes {
+                // Inside single quotes, backslash has no special meaning
+                if c == '\'' {
+                    in_single_quotes = false;
+                } else {
+                    push_quoted(&mut current, &c.to_string());
+                }
             } else if c == '\\' {
                 // Potential escape sequence
                 escaped = true;
             } else if c == '\'' && !in_double_quotes {
-                // Toggle single quotes
-                in_single_quotes = !in_single_quotes;
-            } else if c == '"' && !in_single_quotes {
+                in_single_quotes = true;
+                push_quoted(&mut current, "");
+                in_word = true;
+            } else if c == '"' {
                 // Toggle double quotes
                 in_double_quotes = !in_double_quotes;
-            } else if c.is_whitespace() && !in_single_quotes && !in_double_quotes {
+                push_quoted(&mut current, "");
+                in_word = true;
+            } else if c == '('
+                && split
+                && !in_double_quotes
+                && current
+                    .as_assignment()
+                    .is_some_and(|a| a.value.parts.is_empty())
+            {
+                // Compound array assignment: a=(x y z)
+                let inner = take_until_close_paren(&mut chars);
+                current.parts.push(WordPart::Array(Cmd::parse_args(&inner)));
+            } else if c == '$' && chars.peek() == Some(&'(') {
+                chars.next();
+                let quoted = in_double_quotes;
+                if chars.next_if_eq(&'(').is_some() {
+                    // Arithmetic expansion: $((expr))
+                    let expr = take_until_close_paren(&mut chars);
+                    chars.next_if_eq(&')');
+                    current.parts.push(WordPart::Arith { expr, quoted });
+                } else {
+                    let command = take_until_close_paren(&mut chars);
+                    current
+                        .parts
+                        .push(WordPart::CommandSubst { command, quoted });
+                }
+                in_word = true;
+            } else if c == '`' {
+                let command = take_until_backquote(&mut chars);
+                current.parts.push(WordPart::CommandSubst {
+                    command,
+                    quoted: in_double_quotes,
+                });
+                in_word = true;
+            } else if c == '$' {
+                match expand::parse_param(&mut chars) {
+                    Some(name) => current.parts.push(WordPart::Param {
+                        name,
+                        quoted: in_double_quotes,
+                    }),
+                    None if in_double_quotes => push_quoted(&mut current, "$"),
+                    None => push_literal(&mut current, '$'),
+                }
+                in_word = true;
+            } else if (c == '<' || c == '>')
+                && split
+                && !in_double_quotes
+                && chars.peek() == Some(&'(')
+            {
+                // Process substitution: <(cmd) or >(cmd)
+                chars.next();
+                current.parts.push(WordPart::ProcSubst {
+                    command: take_until_close_paren(&mut chars),
+                    output: c == '>',
+                });
+                in_word = true;
+            } else if (c == '>' || c == '<' || c == '&' && chars.peek() == Some(&'>'))
+                && split
+                && !in_double_quotes
+                && !in_open_subscript(&current)
+            {
+                // A redirection operator is a word of its own, taking along
+                // the descriptor number right before it: `2>/dev/null`. The
+                // `2` of `2&>file` stays an argument, as `&>` takes no number.
+                let mut op = String::new();
+                if in_word {
+                    match current.as_literal() {
+                        Some(n) if c != '&' && n.bytes().all(|b| b.is_ascii_digit()) => {
+                            op.push_str(n)
+                        }
+                        _ => words.push(finish_word(std::mem::take(&mut current))),
+                    }
+                    current = Word::default();
+                    in_word = false;
+                }
+                op.push(c);
+                let follows: &[char] = match c {
+                    '>' => &['>', '|', '&'],
+                    '<' => &['>', '&'],
+                    _ => &['>'],
+                };
+                if let Some(&next) = chars.peek().filter(|next| follows.contains(next)) {
+                    op.push(next);
+                    chars.next();
+                    if op.ends_with("&>") && chars.peek() == Some(&'>') {
+                        op.push('>');
+                        chars.next();
+                    }
+                }
+                words.push(Word {
+                    parts: vec![WordPart::Operator(op)],
+                });
+            } else if c.is_whitespace()
+                && split
+                && !in_double_quotes
+                && !in_open_subscript(&current)
+            {
                 // End of argument
-                if !current_arg.is_empty() {
-                    args.push(current_arg);
-                    current_arg = String::new();
+                if in_word {
+                    words.push(finish_word(std::mem::take(&mut current)));
+                    in_word = false;
                 }
+            } else if in_double_quotes {
+                push_quoted(&mut current, &c.to_string());
             } else {
-                current_arg.push(c);
+                push_literal(&mut current, c);
+                in_word = true;
             }
         }
 
-        // Handle any trailing escape character
+        // A trailing backslash is kept as a literal backslash
         if escaped {
-            if !in_single_quotes && !in_double_quotes {
-                current_arg.push('\\');
-            } else if in_single_quotes {
-                current_arg.push('\\');
-            }
-            // In double quotes, a trailing backslash is just a backslash
-            if in_double_quotes {
-                current_arg.push('\\');
-            }
-        }
-
-        // Add the last argument if not empty
-        if !current_arg.is_empty() {
-            args.push(current_arg);
-        }
-
-        args
-    }
-
-    fn ensure_redirect(
-        args: &[String],
-    ) -> Result<
-        (
-            Option<RedirectInfo>,
-            Option<RedirectInfo>,
-            Option<RedirectInfo>,
-        ),
-        Box<dyn std::error::Error>,
-    > {
-        let mut output_target = None;
-        let mut stdout_redirect = None;
-        let mut stderr_redirect = None;
-        let mut stdin_redirect = None;
-        // process arguments to find redirection
-        let mut i = 1;
-        while i < args.len() {
-            if (args[i] == ">" || args[i] == "1>") && i + 1 < args.len() {
-                output_target = Some(args[i + 1].clone());
-                stdout_redirect = Some(RedirectInfo {
-                    fd: 1,
-                    path: args[i + 1].clone(),
-                    mode: RedirectMode::Write,
-                });
-            }
-            if (args[i] == ">>" || args[i] == "1>>") && i + 1 < args.len() {
-                output_target = Some(args[i + 1].clone());
-                stdout_redirect = Some(RedirectInfo {
-                    fd: 1,
-                    path: args[i + 1].clone(),
-                    mode: RedirectMode::Append,
-                });
-            }
-            if args[i] == ">|" && i + 1 < args.len() {
-                output_target = Some(args[i + 1].clone());
-                stdout_redirect = Some(RedirectInfo {
-                    fd: 1,
-                    path: args[i + 1].clone(),
-                    mode: RedirectMode::ForceWrite,
-                });
+            push_quoted(&mut current, "\\");
+            in_word = true;
+        }
+
+        // Add the last argument
+        if in_word {
+            words.push(finish_word(current));
+        }
+
+        words
+    }
+
+    // The redirections made by the operator `op`, a word of its own as
+    // split off by parse_args, with the expanded target `path`
+    fn parse_redirect(op: &str, path: String) -> Result<Vec<RedirectInfo>, ShellError> {
+        let Some((fd, op)) = redirect_op(op) else {
+            let message = format!("syntax error near unexpected token `{}'", op);
+            return Err(ShellError::syntax(message, op, 0));
+        };
+        let (default_fd, mode) = match op {
+            ">" => (1, RedirectMode::Write),
+            ">|" => (1, RedirectMode::ForceWrite),
+            ">>" => (1, RedirectMode::Append),
+            ">&" => (1, RedirectMode::DupOutput),
+            "<" => (0, RedirectMode::Read),
+            "<>" => (0, RedirectMode::ReadWrite),
+            "<&" => (0, RedirectMode::DupInput),
+            // &>file and &>>file redirect both stdout and stderr
+            _ => {
+                let mode = if op == "&>>" {
+                    RedirectMode::Append
+                } else {
+                    RedirectMode::Write
+                };
+                return Ok(vec![
+                    RedirectInfo { fd: 1, path, mode },
+                    RedirectInfo {
+                        fd: 2,
+                        path: "1".to_string(),
+                        mode: RedirectMode::DupOutput,
+                    },
+                ]);
             }
-            if args[i] == "2>" && i + 1 < args.len() {
-                output_target = Some(args[i + 1].clone());
-                stderr_redirect = Some(RedirectInfo {
-                    fd: 2,
-                    path: args[i + 1].clone(),
-                    mode: RedirectMode::Write,
-                });
+        };
+        Ok(vec![RedirectInfo {
+            fd: fd.unwrap_or(default_fd),
+            path,
+            mode,
+        }])
+    }
+
+    // Builds the process for an external command, looked up in `search`
+    // or else $PATH. Redirections are already applied to the shell's
+    // descriptors, which the process inherits.
+    fn build_command(
+        &self,
+        env: &mut ShellEnv,
+        search: Option<&str>,
+    ) -> Result<Command, ShellError> {
+        let operands = self.operands();
+        let prog = operands.first().cloned().unwrap_or_default();
+        if env.options.restricted && prog.contains('/') {
+            let reason = Some("cannot specify `/' in command names");
+            return Err(ShellError::restricted(prog, reason));
+        }
+        let Some(path) = resolve::command_path(&prog, env, search) else {
+            return Err(ShellError::CommandNotFound(prog));
+        };
+        let mut command = Command::new(path);
+        command
+            .arg0(&prog)
+            .current_dir(&env.cwd)
+            .env_clear()
+            .envs(env.exported());
+        for assignment in &self.assignments {
+            if let Some(value) = env.get(&assignment.name) {
+                command.env(&assignment.name, value);
             }
-            if args[i] == "2>>" && i + 1 < args.len() {
-                output_target = Some(args[i + 1].clone());
-                stderr_redirect = Some(RedirectInfo {
-                    fd: 2,
-                    path: args[i + 1].clone(),
-                    mode: RedirectMode::Append,
-                });
+        }
+        command.args(&operands[1..]);
+
+        Ok(command)
+    }
+
+    // The error for a command that was found but failed to start
+    fn exec_error(&self, error: std::io::Error) -> ShellError {
+        let command = self.operands().first().cloned().unwrap_or_default();
+        if error.kind() == std::io::ErrorKind::NotFound {
+            ShellError::CommandNotFound(command)
+        } else {
+            ShellError::NotExecutable { command, error }
+        }
+    }
+
+    pub fn cmd_exec(&self, env: &mut ShellEnv, search: Option<&str>) -> Result<i32, ShellError> {
+        if self.operands().is_empty() {
+            return Ok(0);
+        }
+        // Execute command, reaping it with wait4 to collect its CPU time
+        let child = self
+            .build_command(env, search)?
+            .spawn()
+            .map_err(|e| self.exec_error(e))?;
+        let (status, times) = wait_pid(child.id() as libc::pid_t);
+        env.child_times += times;
+        Ok(status)
+    }
+}
+
+// Whether the arguments so far run `declare`, perhaps by way of `command`
+// or `builtin`: assignments after it are expanded as they are before a
+// command, without field splitting, and only once
+fn declares(args: &[String]) -> bool {
+    let mut wrapped = false;
+    for arg in args {
+        match arg.as_str() {
+            "command" | "builtin" => wrapped = true,
+            option if wrapped && option.starts_with('-') => {}
+            name => return name == "declare",
+        }
+    }
+    false
+}
+
+// Splits a redirection operator into its descriptor number and operator:
+// `2>>` is (Some(2), ">>")
+fn redirect_op(arg: &str) -> Option<(Option<u32>, &str)> {
+    let op_start = arg.find(|c: char| !c.is_ascii_digit())?;
+    let (fd, op) = arg.split_at(op_start);
+    match op {
+        ">" | ">|" | ">>" | ">&" | "<" | "<>" | "<&" => Some((fd.parse().ok(), op)),
+        "&>" | "&>>" if fd.is_empty() => Some((None, op)),
+        _ => None,
+    }
+}
+
+fn push_literal(word: &mut Word, c: char) {
+    if let Some(WordPart::Literal(s)) = word.parts.last_mut() {
+        s.push(c);
+    } else {
+        word.parts.push(WordPart::Literal(c.to_string()));
+    }
+}
+
+fn push_quoted(word: &mut Word, text: &str) {
+    if let Some(WordPart::Quoted(s)) = word.parts.last_mut() {
+        s.push_str(text);
+    } else {
+        word.parts.push(WordPart::Quoted(text.to_string()));
+    }
+}
+
+// Whether the word so far is `name[` with the subscript still open, as in
+// `m[two words]=x`, where whitespace does not end the word
+fn in_open_subscript(word: &Word) -> bool {
+    let Some(WordPart::Literal(first)) = word.parts.first() else {
+        return false;
+    };
+    match first.split_once('[') {
+        Some((name, _)) if env::is_valid_name(name) => {}
+        _ => return false,
+    }
+    !word.parts.iter().any(|part| match part {
+        WordPart::Literal(s) => s.contains(']'),
+        _ => false,
+    })
+}
+
+// Drops the empty markers left by quotes in words that have other parts,
+// so that "${a[@]}" of an empty array expands to no fields at all
+fn finish_word(mut word: Word) -> Word {
+    if word.parts.len() > 1 {
+        word.parts
+            .retain(|part| !matches!(part, WordPart::Quoted(s) if s.is_empty()));
+    }
+    word
+}
+
+// Collects the text up to the matching unquoted `)`, consuming it.
+fn take_until_close_paren(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut inner = String::new();
+    let mut depth = 0;
+    let mut in_single_quotes = false;
+    let mut in_double_quotes = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !in_single_quotes => {
+                inner.push(c);
+                if let Some(next) = chars.next() {
+                    inner.push(next);
+                }
+                continue;
             }
-            if args[i] == "<" && i - 1 > 0 {
-                output_target = Some(args[i - 1].clone());
-                stdin_redirect = Some(RedirectInfo {
-                    fd: 0,
-                    path: args[i - 1].clone(),
-                    mode: RedirectMode::Read,
-                });
+            '\'' if !in_double_quotes => in_single_quotes = !in_single_quotes,
+            '"' if !in_single_quotes => in_double_quotes = !in_double_quotes,
+            '(' if !in_single_quotes && !in_double_quotes => depth += 1,
+            ')' if !in_single_quotes && !in_double_quotes => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
             }
+            _ => {}
+        }
+        inner.push(c);
+    }
+    inner
+}
 
-            i += 1;
+// Collects the command of a `...` substitution, consuming the closing
+// backquote. A backslash only escapes `$`, `` ` `` and `\`.
+fn take_until_backquote(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut command = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => break,
+            '\\' => match chars.next_if(|next| matches!(next, '$' | '`' | '\\')) {
+                Some(next) => command.push(next),
+                None => command.push(c),
+            },
+            _ => command.push(c),
         }
+    }
+    command
+}
 
-        if let Some(file_path) = output_target {
-            if let Some(parent) = std::path::Path::new(&file_path).parent() {
-                if !parent.exists() {
-                    std::fs::create_dir_all(parent)?;
+// The characters of `input` outside quotes, backquotes, `${...}`,
+// parentheses and `select ... done`, with their byte positions. The
+// outermost parentheses themselves are included, so that a subshell's
+// bounds can be found.
+fn unquoted_chars(input: &str) -> Vec<(usize, char)> {
+    let chars = scan_unquoted(input);
+    let regions = select_regions(input, &chars);
+    if regions.is_empty() {
+        return chars;
+    }
+    chars
+        .into_iter()
+        .filter(|&(i, _)| {
+            !regions
+                .iter()
+                .any(|&(start, end)| i >= start && end.map_or(true, |end| i < end))
+        })
+        .collect()
+}
+
+// Whether a character ends a word
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || ";|&()<>".contains(c)
+}
+
+// The `select ... done` commands of `input`, whose unquoted characters
+// are `chars`: where each starts and ends, with no end if the `done` is
+// missing. Nested ones are part of the outer one. Like other keywords,
+// `select` and `done` only count as whole unquoted words in command
+// position, as does the `do` after which a command starts.
+fn select_regions(input: &str, chars: &[(usize, char)]) -> Vec<(usize, Option<usize>)> {
+    let mut regions = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut command_position = true;
+    let mut k = 0;
+    while k < chars.len() {
+        let (i, c) = chars[k];
+        if is_delimiter(c) {
+            // A command starts after an operator, but not after `<` or `>`
+            if ";|&(\n".contains(c) {
+                command_position = true;
+            } else if !c.is_whitespace() {
+                command_position = false;
+            }
+            k += 1;
+            continue;
+        }
+        // The run of unquoted characters making up this word
+        let mut end = i + c.len_utf8();
+        k += 1;
+        while let Some(&(j, next)) = chars
+            .get(k)
+            .filter(|&&(j, next)| j == end && !is_delimiter(next))
+        {
+            end = j + next.len_utf8();
+            k += 1;
+        }
+        let whole = input[..i].ends_with(is_delimiter) || i == 0;
+        let whole = whole && input[end..].chars().next().map_or(true, is_delimiter);
+        let keyword = (whole && command_position).then(|| &input[i..end]);
+        match keyword {
+            Some("select") => {
+                if depth == 0 {
+                    start = i;
                 }
+                depth += 1;
             }
+            Some("done") if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    regions.push((start, Some(end)));
+                }
+            }
+            _ => {}
         }
-        Ok((stdout_redirect, stderr_redirect, stdin_redirect))
+        command_position = keyword == Some("do");
+    }
+    if depth > 0 {
+        regions.push((start, None));
     }
+    regions
+}
 
-    fn executable_exists(&self) -> bool {
-        match &self.exec {
-            Executable::Unknown(x) => {
-                if let Ok(path) = std::env::var("PATH") {
-                    for dir in path.split(':') {
-                        let path = std::path::Path::new(dir).join(x.trim());
-                        if path.exists() {
-                            return true;
-                        }
-                    }
-                }
-                println!("{}: command not found", x.trim());
-                false
+// Whether `input` has a `select` without its `done`
+fn unclosed_select(input: &str) -> bool {
+    select_regions(input, &scan_unquoted(input))
+        .iter()
+        .any(|(_, end)| end.is_none())
+}
+
+// The characters of `input` outside quotes, backquotes, `${...}` and
+// parentheses, with the outermost parentheses, as for `unquoted_chars`
+fn scan_unquoted(input: &str) -> Vec<(usize, char)> {
+    let mut found = Vec::new();
+    let mut in_single_quotes = false;
+    let mut in_double_quotes = false;
+    let mut in_backquotes = false;
+    let mut escaped = false;
+    // The closing characters of the open `(` and `${`
+    let mut open: Vec<char> = Vec::new();
+    let mut prev = ' ';
+    for (i, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if in_single_quotes {
+            in_single_quotes = c != '\'';
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_double_quotes = !in_double_quotes;
+        } else if c == '\'' && !in_double_quotes {
+            in_single_quotes = true;
+        } else if c == '`' {
+            in_backquotes = !in_backquotes;
+        } else if in_double_quotes || in_backquotes {
+            // Quoted text separates nothing
+        } else if c == '(' {
+            if open.is_empty() {
+                found.push((i, c));
+            }
+            open.push(')');
+        } else if c == '{' && prev == '$' {
+            open.push('}');
+        } else if open.last() == Some(&c) {
+            open.pop();
+            if open.is_empty() && c == ')' {
+                found.push((i, c));
             }
-            _ => true,
+        } else if open.is_empty() {
+            found.push((i, c));
         }
+        prev = c;
     }
+    found
+}
 
-    pub fn cmd_exec(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some((prog, args)) = self.args.split_first() {
-            if let Executable::Unknown(_) = Executable::from(prog.clone()) {
-                if self.executable_exists() {
-                    let mut command = Command::new(prog);
-                    let mut skip_next = false;
-                    for (i, arg) in args.iter().enumerate() {
-                        if skip_next {
-                            skip_next = false;
-                            continue;
-                        }
+// Splits a pipeline into its stages on unquoted `|`. The `|` of the `>|`
+// redirection operator is not a separator.
+fn split_pipeline(input: &str) -> Vec<&str> {
+    let mut stages = Vec::new();
+    let mut start = 0;
+    for (i, c) in unquoted_chars(input) {
+        if c == '|' && !input[..i].ends_with('>') {
+            stages.push(&input[start..i]);
+            start = i + 1;
+        }
+    }
+    stages.push(&input[start..]);
+    stages
+}
 
-                        // Skip redirection operators and their targets
-                        if (arg == ">"
-                            || arg == ">>"
-                            || arg == "1>"
-                            || arg == "1>>"
-                            || arg == "2>"
-                            || arg == "2>>"
-                            || arg == "<"
-                            || arg == ">|")
-                            && i + 1 < args.len()
-                        {
-                            skip_next = true;
-                            continue;
-                        }
+// Checks that every redirection operator among the `words` of `stage` has
+// a target. `next` is the token after the stage; errors point into `input`.
+fn check_redirects(input: &str, stage: &str, words: &[Word], next: &str) -> Result<(), ShellError> {
+    let offset = |s: &str| s.as_ptr() as usize - input.as_ptr() as usize;
+    let ops: Vec<Option<&str>> = words.iter().map(|word| word.as_operator()).collect();
+    for (j, op) in ops.iter().enumerate() {
+        let Some(op) = op else {
+            continue;
+        };
+        match ops.get(j + 1) {
+            Some(None) => {}
+            Some(Some(second)) => {
+                // Point at the second of the two operators
+                let first = stage.find(op).map_or(0, |p| p + op.len());
+                let column = stage[first..].find(second).map_or(first, |p| first + p);
+                let message = format!("syntax error near unexpected token `{}'", second);
+                return Err(ShellError::syntax(message, input, offset(stage) + column));
+            }
+            None => {
+                let message = format!("syntax error near unexpected token `{}'", next);
+                return Err(ShellError::syntax(
+                    message,
+                    input,
+                    offset(stage) + stage.len(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
 
-                        command.arg(arg);
-                    }
+// Finds a quote, backquote, `(` or `${` that is never closed, returning
+// its position and the character that would close it
+fn unclosed(line: &str) -> Option<(usize, char)> {
+    let mut open: Vec<(usize, char)> = Vec::new();
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        let inside = open.last().map(|&(_, close)| close);
+        if escaped {
+            escaped = false;
+        } else if inside == Some('\'') {
+            if c == '\'' {
+                open.pop();
+            }
+        } else if c == '\\' {
+            escaped = true;
+        } else if Some(c) == inside {
+            open.pop();
+        } else if inside == Some('"') {
+            // Only substitutions nest inside double quotes
+            match c {
+                '`' => open.push((i, '`')),
+                '(' if prev == '$' => open.push((i, ')')),
+                '{' if prev == '$' => open.push((i, '}')),
+                _ => {}
+            }
+        } else {
+            match c {
+                '\'' | '"' | '`' => open.push((i, c)),
+                '(' => open.push((i, ')')),
+                '{' if prev == '$' => open.push((i, '}')),
+                _ => {}
+            }
+        }
+        prev = if escaped { ' ' } else { c };
+    }
+    open.pop()
+}
 
-                    if let Some(stdout_info) = &self.stdout_redirect {
-                        let file = match stdout_info.mode {
-                            RedirectMode::Write | RedirectMode::ForceWrite => {
-                                File::create(&stdout_info.path)?
-                            }
-                            RedirectMode::Append => OpenOptions::new()
-                                .append(true)
-                                .create(true)
-                                .open(&stdout_info.path)?,
-                            _ => return Err("Invalid redirection mode for stdout".into()),
-                        };
-                        command.stdout(Stdio::from(file));
-                    } else {
-                        command.stdout(Stdio::inherit());
-                    }
+fn pipe() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
 
-                    if let Some(stderr_info) = &self.stderr_redirect {
-                        let file = match stderr_info.mode {
-                            RedirectMode::Write | RedirectMode::ForceWrite => {
-                                File::create(&stderr_info.path)?
-                            }
-                            RedirectMode::Append => OpenOptions::new()
-                                .append(true)
-                                .create(true)
-                                .open(&stderr_info.path)?,
-                            _ => return Err("Invalid redirection mode for stderr".into()),
-                        };
-                        command.stderr(Stdio::from(file));
-                    } else {
-                        command.stderr(Stdio::inherit());
-                    }
+// Waits for a child and converts its wait status like the shell does:
+// the exit code, or 128 + signal number. Also returns the CPU time the
+// child used.
+fn wait_pid(pid: libc::pid_t) -> (i32, CpuTimes) {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        if unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } != -1 {
+            break;
+        }
+        if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+            return (1, CpuTimes::default());
+        }
+    }
+    (exit_status(status), CpuTimes::from_rusage(&usage))
+}
 
-                    // Setup stdin redirection
-                    if let Some(stdin_info) = &self.stdin_redirect {
-                        let file = File::open(&stdin_info.path)?;
-                        command.stdin(Stdio::from(file));
-                    } else {
-                        command.stdin(Stdio::inherit());
-                    }
+// A wait status as the shell's status: the exit code, or 128 + the number
+// of the signal that killed the process
+fn exit_status(status: i32) -> i32 {
+    if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else {
+        libc::WEXITSTATUS(status)
+    }
+}
 
-                    // Execute command
-                    command.status()?;
-                }
+// Removes comments: each unquoted `#` at the start of a word and the
+// rest of its line.
+fn strip_comments(input: &str) -> Cow<'_, str> {
+    let mut in_single_quotes = false;
+    let mut in_double_quotes = false;
+    let mut escaped = false;
+    let mut in_comment = false;
+    let mut prev = ' ';
+    let mut stripped = String::new();
+    // Where the text not yet copied to `stripped` starts
+    let mut kept = 0;
+    for (i, c) in input.char_indices() {
+        if in_comment {
+            if c == '\n' {
+                in_comment = false;
+                kept = i;
             }
+        } else if escaped {
+            escaped = false;
+        } else if in_single_quotes {
+            in_single_quotes = c != '\'';
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_double_quotes = !in_double_quotes;
+        } else if c == '\'' && !in_double_quotes {
+            in_single_quotes = true;
+        } else if c == '#' && !in_double_quotes && (prev.is_whitespace() || "|;&(".contains(prev)) {
+            stripped.push_str(&input[kept..i]);
+            in_comment = true;
         }
-        Ok(())
+        prev = c;
+    }
+    if kept == 0 && !in_comment {
+        return Cow::Borrowed(input);
+    }
+    if !in_comment {
+        stripped.push_str(&input[kept..]);
+    }
+    Cow::Owned(stripped)
+}
+
+// Splits off a leading `time` or `time -p` keyword.
+fn strip_time_keyword(input: &str) -> (Option<TimeFormat>, &str) {
+    fn keyword<'a>(s: &'a str, word: &str) -> Option<&'a str> {
+        s.strip_prefix(word)
+            .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+            .map(str::trim_start)
+    }
+    let Some(rest) = keyword(input.trim_start(), "time") else {
+        return (None, input);
+    };
+    match keyword(rest, "-p") {
+        Some(rest) => (Some(TimeFormat::Posix), rest),
+        None => (Some(TimeFormat::Default), rest),
+    }
+}
+
+/// Quotes a string for display so that it reads back as one word.
+pub fn shell_quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
     }
 }
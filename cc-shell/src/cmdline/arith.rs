@@ -0,0 +1,457 @@
+use super::env::{is_valid_name, ShellEnv};
+
+// Variables holding expressions are evaluated recursively up to this depth
+const MAX_DEPTH: usize = 64;
+
+/// Evaluates an arithmetic expression, as in `$((...))`, applying any
+/// assignments it makes (`x=1`, `i++`, `n+=2`) to the environment.
+pub fn eval(expr: &str, env: &mut ShellEnv) -> Result<i64, String> {
+    let mut evaluator = Evaluator {
+        env,
+        assigned: Vec::new(),
+        depth: 0,
+    };
+    let value = evaluator.eval_str(expr)?;
+    for (name, value) in evaluator.assigned {
+        env.check_writable(name.split('[').next().unwrap_or_default())?;
+        match name.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            Some((array, sub)) => env.set_element(array, sub, &value.to_string())?,
+            None => env.set(&name, &value.to_string()),
+        }
+    }
+    Ok(value)
+}
+
+/// Evaluates an expression without changing any variables, as for array
+/// subscripts.
+pub fn eval_readonly(expr: &str, env: &ShellEnv) -> Result<i64, String> {
+    Evaluator {
+        env,
+        assigned: Vec::new(),
+        depth: 0,
+    }
+    .eval_str(expr)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    // Operators and parentheses, longest match first
+    Op(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "?", ":", "=", "(", ")", ",",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap_or_default();
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#' && c != '_' && c != '@')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Num(parse_number(&rest[..end])?));
+            rest = &rest[end..];
+        } else if c == '_' || c.is_ascii_alphabetic() {
+            let mut end = rest
+                .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            // Array elements: a[i + 1]
+            if rest[end..].starts_with('[') {
+                let close = rest[end..]
+                    .find(']')
+                    .ok_or_else(|| format!("{}: bad array subscript", rest))?;
+                end += close + 1;
+            }
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!(
+                "{}: syntax error: invalid arithmetic operator (error token is \"{}\")",
+                expr.trim(),
+                rest
+            ));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// Decimal, 0x hexadecimal, 0 octal or base#digits
+fn parse_number(text: &str) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "{}: value too great for base (error token is \"{}\")",
+            text, text
+        )
+    };
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base @ 2..=64) => (base, digits),
+            _ => return Err(format!("{}: invalid arithmetic base", text)),
+        }
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // Bases above 36 use a-z, A-Z, @ and _ as digits
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    // `x = e` or `x op= e`; the operator is "=" for plain assignment
+    Assign(&'static str, String, Box<Expr>),
+    // ++x, --x (prefix) and x++, x-- (postfix)
+    Step {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+// Binary operators by precedence, lowest first
+const BINARY_LEVELS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn error(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some(token) => format!("syntax error in expression (error token is \"{}\")", token),
+            None => "syntax error: operand expected".to_string(),
+        }
+    }
+
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut expr = self.assignment()?;
+        while self.eat(",") {
+            let rhs = self.assignment()?;
+            expr = Expr::Binary(",", Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, String> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if matches!(
+                *op,
+                "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "<<=" | ">>=" | "&=" | "^=" | "|="
+            ) {
+                let (name, op) = (name.clone(), *op);
+                self.pos += 2;
+                let value = self.assignment()?;
+                return Ok(Expr::Assign(op, name, Box::new(value)));
+            }
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let then = self.comma()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        Ok(Expr::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(ops) = BINARY_LEVELS.get(level) else {
+            return self.power();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| ops.contains(op)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // `**` is right-associative and binds tighter than unary minus on its
+    // right: 2**-1 parses, -2**2 is (-2)**2
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.unary()?;
+        if self.eat("**") {
+            let exponent = self.power()?;
+            return Ok(Expr::Binary("**", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if let Some(op @ ("++" | "--")) = self.peek_op() {
+            self.pos += 1;
+            let Some(Token::Name(name)) = self.tokens.get(self.pos).cloned() else {
+                return Err(self.error());
+            };
+            self.pos += 1;
+            let delta = if op == "++" { 1 } else { -1 };
+            return Ok(Expr::Step {
+                name,
+                delta,
+                prefix: true,
+            });
+        }
+        if let Some(op @ ("+" | "-" | "!" | "~")) = self.peek_op() {
+            self.pos += 1;
+            return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Expr::Num(n))
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                if let Some(op @ ("++" | "--")) = self.peek_op() {
+                    self.pos += 1;
+                    let delta = if op == "++" { 1 } else { -1 };
+                    return Ok(Expr::Step {
+                        name,
+                        delta,
+                        prefix: false,
+                    });
+                }
+                Ok(Expr::Var(name))
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Err(self.error()),
+        }
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    env: &'a ShellEnv,
+    // Assignments made so far, applied to the environment afterwards
+    assigned: Vec<(String, i64)>,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval_str(&mut self, expr: &str) -> Result<i64, String> {
+        if expr.trim().is_empty() {
+            return Ok(0);
+        }
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!(
+                "{}: expression recursion level exceeded",
+                expr.trim()
+            ));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(expr)?,
+            pos: 0,
+        };
+        let ast = parser
+            .comma()
+            .map_err(|e| format!("{}: {}", expr.trim(), e))?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!("{}: {}", expr.trim(), parser.error()));
+        }
+        let value = self.eval(&ast);
+        self.depth -= 1;
+        value
+    }
+
+    // The value of a variable: unset or empty is 0, anything else is
+    // itself evaluated as an expression
+    fn var(&mut self, name: &str) -> Result<i64, String> {
+        if let Some((_, value)) = self.assigned.iter().rev().find(|(n, _)| n == name) {
+            return Ok(*value);
+        }
+        let text = match name.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            Some((array, sub)) if is_valid_name(array) => {
+                let key = if self.env.is_assoc(array) {
+                    sub.to_string()
+                } else {
+                    self.eval_str(sub)?.to_string()
+                };
+                self.env.get_element(array, &key)?
+            }
+            _ => self.env.get(name),
+        };
+        match text {
+            Some(text) if !text.trim().is_empty() => self.eval_str(&text),
+            _ => Ok(0),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<i64, String> {
+        Ok(match expr {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => self.var(name)?,
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    _ => value,
+                }
+            }
+            Expr::Binary("&&", lhs, rhs) => (self.eval(lhs)? != 0 && self.eval(rhs)? != 0) as i64,
+            Expr::Binary("||", lhs, rhs) => (self.eval(lhs)? != 0 || self.eval(rhs)? != 0) as i64,
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                apply(op, lhs, rhs)?
+            }
+            Expr::Assign(op, name, value) => {
+                let mut value = self.eval(value)?;
+                if let Some(op) = op.strip_suffix('=').filter(|op| !op.is_empty()) {
+                    value = apply(op, self.var(name)?, value)?;
+                }
+                self.assigned.push((name.clone(), value));
+                value
+            }
+            Expr::Step {
+                name,
+                delta,
+                prefix,
+            } => {
+                let old = self.var(name)?;
+                let new = old.wrapping_add(*delta);
+                self.assigned.push((name.clone(), new));
+                if *prefix {
+                    new
+                } else {
+                    old
+                }
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                if self.eval(cond)? != 0 {
+                    self.eval(then)?
+                } else {
+                    self.eval(otherwise)?
+                }
+            }
+        })
+    }
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    Ok(match op {
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("division by 0".to_string()),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "**" if rhs < 0 => return Err("exponent less than 0".to_string()),
+        "**" => lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32),
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "<" => (lhs < rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "&" => lhs & rhs,
+        "^" => lhs ^ rhs,
+        "|" => lhs | rhs,
+        "," => rhs,
+        _ => return Err(format!("{}: unknown operator", op)),
+    })
+}
@@ -0,0 +1,44 @@
+use super::RedirectInfo;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The audit log: one line of JSON for each command run, builtins and
+/// external commands alike, appended to a file.
+pub struct AuditLog {
+    file: File,
+}
+
+/// What the audit log records of a command.
+#[derive(Serialize)]
+pub struct Entry<'a> {
+    // When the command started, in seconds since the epoch
+    pub timestamp: u64,
+    pub cwd: &'a Path,
+    // The arguments after expansion, without the redirections
+    pub argv: &'a [String],
+    pub redirections: &'a [RedirectInfo],
+    pub status: i32,
+    // How long it ran, in seconds
+    pub duration: f64,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(AuditLog { file })
+    }
+
+    /// Appends an entry. Each is a single write, so that those of the
+    /// stages of a pipeline, written by separate processes, don't mix.
+    pub fn record(&self, entry: &Entry) {
+        let Ok(mut line) = serde_json::to_vec(entry) else {
+            return;
+        };
+        line.push(b'\n');
+        if let Err(e) = (&self.file).write_all(&line) {
+            errln!("cc-shell: audit log: {}", crate::error::os_message(&e));
+        }
+    }
+}
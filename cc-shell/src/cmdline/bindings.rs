@@ -0,0 +1,400 @@
+use rustyline::{At, Cmd, CompletionType, KeyCode, KeyEvent, Modifiers, Movement, Word};
+
+/// Key bindings and line editor settings changed with `bind`. The shell
+/// applies pending changes to the line editor before each prompt.
+pub struct KeyBindings {
+    // (key sequence as written, key, action name), in the order bound
+    bound: Vec<(String, KeyEvent, &'static str)>,
+    // Changes not yet applied to the editor; None removes a binding
+    pending: Vec<(KeyEvent, Option<Cmd>)>,
+    // `list` shows all candidates, `circular` cycles through them
+    pub completion: CompletionType,
+    // Candidates match whatever the case of the word typed
+    pub ignore_case: bool,
+    // Ask before listing at least this many candidates; never if negative
+    pub query_items: i64,
+    // Show the rest of a matching history entry after the cursor
+    pub hints: bool,
+    // Ctrl-R starts out showing only commands run in the current directory
+    pub search_cwd: bool,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            bound: Vec::new(),
+            pending: Vec::new(),
+            completion: CompletionType::List,
+            ignore_case: false,
+            query_items: 100,
+            hints: false,
+            search_cwd: false,
+        }
+    }
+}
+
+// The bindable actions, by their readline names
+fn actions() -> Vec<(&'static str, Cmd)> {
+    let emacs_word = Movement::ForwardWord(1, At::AfterEnd, Word::Emacs);
+    vec![
+        ("abort", Cmd::Abort),
+        ("accept-hint", Cmd::CompleteHint),
+        ("accept-line", Cmd::AcceptLine),
+        ("backward-char", Cmd::Move(Movement::BackwardChar(1))),
+        (
+            "backward-kill-word",
+            Cmd::Kill(Movement::BackwardWord(1, Word::Emacs)),
+        ),
+        (
+            "backward-word",
+            Cmd::Move(Movement::BackwardWord(1, Word::Emacs)),
+        ),
+        ("beginning-of-history", Cmd::BeginningOfHistory),
+        ("beginning-of-line", Cmd::Move(Movement::BeginningOfLine)),
+        ("clear-screen", Cmd::ClearScreen),
+        ("complete", Cmd::Complete),
+        ("delete-char", Cmd::Kill(Movement::ForwardChar(1))),
+        ("end-of-history", Cmd::EndOfHistory),
+        ("end-of-line", Cmd::Move(Movement::EndOfLine)),
+        ("forward-char", Cmd::Move(Movement::ForwardChar(1))),
+        ("forward-search-history", Cmd::ForwardSearchHistory),
+        ("forward-word", Cmd::Move(emacs_word.clone())),
+        ("history-search-backward", Cmd::HistorySearchBackward),
+        ("history-search-forward", Cmd::HistorySearchForward),
+        ("kill-line", Cmd::Kill(Movement::EndOfLine)),
+        ("kill-word", Cmd::Kill(emacs_word)),
+        ("next-history", Cmd::NextHistory),
+        ("previous-history", Cmd::PreviousHistory),
+        ("reverse-search-history", Cmd::ReverseSearchHistory),
+        ("transpose-chars", Cmd::TransposeChars),
+        ("undo", Cmd::Undo(1)),
+        ("unix-line-discard", Cmd::Kill(Movement::BeginningOfLine)),
+        (
+            "unix-word-rubout",
+            Cmd::Kill(Movement::BackwardWord(1, Word::Big)),
+        ),
+        ("yank", Cmd::Yank(1, rustyline::Anchor::Before)),
+    ]
+}
+
+impl KeyBindings {
+    /// The names accepted as actions, for `bind -l`.
+    pub fn action_names() -> Vec<&'static str> {
+        actions().into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Applies one line of `bind` or rc file syntax: either
+    /// `"keyseq": action` or `set variable value`. `editing-mode` is
+    /// returned for the caller to store in the shell options.
+    pub fn parse_line(&mut self, line: &str) -> Result<Option<bool>, String> {
+        let line = line.trim();
+        if let Some(setting) = line.strip_prefix("set ") {
+            return self.set_variable(setting.trim());
+        }
+        let (keyseq, action) = line
+            .rsplit_once(':')
+            .ok_or_else(|| format!("{}: no key sequence terminator", line))?;
+        let action = action.trim();
+        let (name, cmd) = actions()
+            .into_iter()
+            .find(|(name, _)| *name == action)
+            .ok_or_else(|| format!("{}: unknown function name", action))?;
+        let keyseq = keyseq.trim();
+        let key = parse_keyseq(keyseq)?;
+        self.bound.retain(|(_, k, _)| *k != key);
+        self.bound.push((keyseq.to_string(), key, name));
+        self.pending.push((key, Some(cmd)));
+        Ok(None)
+    }
+
+    fn set_variable(&mut self, setting: &str) -> Result<Option<bool>, String> {
+        let (name, value) = setting
+            .split_once(char::is_whitespace)
+            .unwrap_or((setting, ""));
+        match (name, value.trim()) {
+            ("editing-mode", "vi") => return Ok(Some(true)),
+            ("editing-mode", "emacs") => return Ok(Some(false)),
+            ("completion-ignore-case", "on") => self.ignore_case = true,
+            ("completion-ignore-case", "off") => self.ignore_case = false,
+            ("completion-query-items", value) => match value.parse() {
+                Ok(items) => self.query_items = items,
+                Err(_) => return Err(format!("{}: invalid value for {}", value, name)),
+            },
+            ("completion-type", "list") => self.completion = CompletionType::List,
+            ("completion-type", "circular") => self.completion = CompletionType::Circular,
+            ("history-search-cwd", "on") => self.search_cwd = true,
+            ("history-search-cwd", "off") => self.search_cwd = false,
+            ("show-hints", "on") => self.hints = true,
+            ("show-hints", "off") => self.hints = false,
+            (
+                "editing-mode"
+                | "completion-ignore-case"
+                | "completion-type"
+                | "history-search-cwd"
+                | "show-hints",
+                value,
+            ) => return Err(format!("{}: invalid value for {}", value, name)),
+            _ => return Err(format!("{}: unknown variable name", name)),
+        }
+        Ok(None)
+    }
+
+    /// Removes the binding of a key sequence, as for `bind -r`.
+    pub fn unbind(&mut self, keyseq: &str) -> Result<(), String> {
+        let key = parse_keyseq(keyseq.trim())?;
+        self.bound.retain(|(_, k, _)| *k != key);
+        self.pending.push((key, None));
+        Ok(())
+    }
+
+    /// The bindings in a form `bind` accepts back, for `bind -p`.
+    pub fn describe(&self) -> Vec<String> {
+        self.bound
+            .iter()
+            .map(|(keyseq, _, action)| format!("{}: {}", keyseq, action))
+            .collect()
+    }
+
+    /// The variables as `set` lines, for `bind -v`.
+    pub fn describe_variables(&self, vi: bool) -> Vec<String> {
+        let completion = match self.completion {
+            CompletionType::Circular => "circular",
+            _ => "list",
+        };
+        vec![
+            format!(
+                "set completion-ignore-case {}",
+                if self.ignore_case { "on" } else { "off" }
+            ),
+            format!("set completion-query-items {}", self.query_items),
+            format!("set completion-type {}", completion),
+            format!("set editing-mode {}", if vi { "vi" } else { "emacs" }),
+            format!(
+                "set history-search-cwd {}",
+                if self.search_cwd { "on" } else { "off" }
+            ),
+            format!("set show-hints {}", if self.hints { "on" } else { "off" }),
+        ]
+    }
+
+    /// Takes the changes made since the last call, for the line editor.
+    pub fn take_pending(&mut self) -> Vec<(KeyEvent, Option<Cmd>)> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+// Parses a quoted readline key sequence such as "\C-r", "\M-f", "\e[A"
+// or "\t" into the single key the line editor reports for it
+fn parse_keyseq(keyseq: &str) -> Result<KeyEvent, String> {
+    let inner = keyseq
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("{}: key sequence must be quoted", keyseq))?;
+
+    let mut chars = inner.chars().peekable();
+    let mut keys = Vec::new();
+    let mut mods = Modifiers::NONE;
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('C') if chars.next_if_eq(&'-').is_some() => {
+                    let c = chars.next().ok_or("\\C-: missing key")?;
+                    if c == '?' {
+                        '\x7f'
+                    } else {
+                        ((c.to_ascii_uppercase() as u8) & 0x1f) as char
+                    }
+                }
+                Some('M') if chars.next_if_eq(&'-').is_some() => {
+                    mods |= Modifiers::ALT;
+                    continue;
+                }
+                Some('e') => '\x1b',
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('a') => '\x07',
+                Some(c @ ('\\' | '"' | '\'')) => c,
+                Some(c) => return Err(format!("\\{}: unknown escape in key sequence", c)),
+                None => '\\',
+            }
+        } else {
+            c
+        };
+        keys.push(KeyEvent::new(c, mods));
+        mods = Modifiers::NONE;
+    }
+
+    match keys.as_slice() {
+        [key] => Ok(*key),
+        // ESC before a key is how terminals send Meta
+        [KeyEvent(KeyCode::Esc, Modifiers::NONE), KeyEvent(KeyCode::Char(c), m)] => {
+            Ok(KeyEvent(KeyCode::Char(*c), *m | Modifiers::ALT))
+        }
+        [KeyEvent(KeyCode::Esc, _), rest @ ..] => {
+            let text: String = rest
+                .iter()
+                .filter_map(|key| match key.0 {
+                    KeyCode::Char(c) => Some(c),
+                    _ => None,
+                })
+                .collect();
+            csi_key(&text).ok_or_else(|| format!("{}: unknown escape sequence", keyseq))
+        }
+        [] => Err("empty key sequence".to_string()),
+        _ => Err(format!("{}: only single keys can be bound", keyseq)),
+    }
+}
+
+// The key sent as `ESC` followed by `text`, for the usual cursor keys
+fn csi_key(text: &str) -> Option<KeyEvent> {
+    let code = match text {
+        "[A" | "OA" => KeyCode::Up,
+        "[B" | "OB" => KeyCode::Down,
+        "[C" | "OC" => KeyCode::Right,
+        "[D" | "OD" => KeyCode::Left,
+        "[H" | "OH" | "[1~" => KeyCode::Home,
+        "[F" | "OF" | "[4~" => KeyCode::End,
+        "[2~" => KeyCode::Insert,
+        "[3~" => KeyCode::Delete,
+        "[5~" => KeyCode::PageUp,
+        "[6~" => KeyCode::PageDown,
+        "[Z" => KeyCode::BackTab,
+        _ => return None,
+    };
+    Some(KeyEvent(code, Modifiers::NONE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_sequences() {
+        let key = |keyseq| parse_keyseq(keyseq).unwrap();
+        assert_eq!(key(r#""\C-r""#), KeyEvent::ctrl('R'));
+        assert_eq!(key(r#""\C-x""#), key(r#""\C-X""#));
+        assert_eq!(key(r#""\M-f""#), KeyEvent::alt('f'));
+        assert_eq!(key(r#""\ef""#), KeyEvent::alt('f'));
+        assert_eq!(key(r#""\e[A""#), KeyEvent(KeyCode::Up, Modifiers::NONE));
+        assert_eq!(key(r#""\eOH""#), KeyEvent(KeyCode::Home, Modifiers::NONE));
+        assert_eq!(
+            key(r#""\e[3~""#),
+            KeyEvent(KeyCode::Delete, Modifiers::NONE)
+        );
+        assert_eq!(key(r#""\t""#), KeyEvent(KeyCode::Tab, Modifiers::NONE));
+        assert_eq!(
+            key(r#""\C-?""#),
+            KeyEvent(KeyCode::Backspace, Modifiers::NONE)
+        );
+        assert_eq!(key(r#""\"""#), KeyEvent::from('"'));
+        assert_eq!(key(r#""x""#), KeyEvent::from('x'));
+    }
+
+    #[test]
+    fn rejected_key_sequences() {
+        let error = |keyseq| parse_keyseq(keyseq).unwrap_err();
+        assert_eq!(error(r"\C-r"), r"\C-r: key sequence must be quoted");
+        assert_eq!(error(r#""ab""#), r#""ab": only single keys can be bound"#);
+        assert_eq!(error(r#""\q""#), r"\q: unknown escape in key sequence");
+        assert_eq!(error(r#""\e[9~""#), r#""\e[9~": unknown escape sequence"#);
+        assert_eq!(error(r#""\C-""#), r"\C-: missing key");
+        assert_eq!(error(r#""""#), "empty key sequence");
+    }
+
+    #[test]
+    fn bindings_replace_and_remove() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(bindings.parse_line(r#""\C-t": transpose-chars"#), Ok(None));
+        assert_eq!(bindings.parse_line(r#"  "\C-t"  :  undo  "#), Ok(None));
+        assert_eq!(bindings.parse_line(r#""\M-b":backward-word"#), Ok(None));
+        assert_eq!(
+            bindings.describe(),
+            [r#""\C-t": undo"#, r#""\M-b": backward-word"#]
+        );
+        bindings.unbind(r#""\C-t""#).unwrap();
+        assert_eq!(bindings.describe(), [r#""\M-b": backward-word"#]);
+
+        let pending = bindings.take_pending();
+        let pending: Vec<_> = pending
+            .iter()
+            .map(|(key, cmd)| (*key, cmd.is_some()))
+            .collect();
+        assert_eq!(
+            pending,
+            [
+                (KeyEvent::ctrl('T'), true),
+                (KeyEvent::ctrl('T'), true),
+                (KeyEvent::alt('b'), true),
+                (KeyEvent::ctrl('T'), false),
+            ]
+        );
+        assert!(bindings.take_pending().is_empty());
+    }
+
+    #[test]
+    fn rejected_bindings() {
+        let mut bindings = KeyBindings::default();
+        let mut error = |line| bindings.parse_line(line).unwrap_err();
+        assert_eq!(error(r#""\C-t""#), r#""\C-t": no key sequence terminator"#);
+        assert_eq!(
+            error(r#""\C-t": no-such-thing"#),
+            "no-such-thing: unknown function name"
+        );
+        assert_eq!(error("\\C-t: undo"), r"\C-t: key sequence must be quoted");
+        assert!(bindings.describe().is_empty());
+        assert!(bindings.take_pending().is_empty());
+    }
+
+    #[test]
+    fn variables() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(bindings.parse_line("set editing-mode vi"), Ok(Some(true)));
+        assert_eq!(
+            bindings.parse_line("set editing-mode emacs"),
+            Ok(Some(false))
+        );
+        assert_eq!(
+            bindings.parse_line("set completion-ignore-case on"),
+            Ok(None)
+        );
+        assert_eq!(
+            bindings.parse_line("set completion-query-items -1"),
+            Ok(None)
+        );
+        assert_eq!(
+            bindings.parse_line("set completion-type  circular"),
+            Ok(None)
+        );
+        assert_eq!(bindings.parse_line("set show-hints on"), Ok(None));
+        assert!(bindings.ignore_case && bindings.hints);
+        assert_eq!(bindings.query_items, -1);
+        assert!(matches!(bindings.completion, CompletionType::Circular));
+
+        let mut error = |line| bindings.parse_line(line).unwrap_err();
+        assert_eq!(
+            error("set editing-mode ed"),
+            "ed: invalid value for editing-mode"
+        );
+        assert_eq!(error("set show-hints"), ": invalid value for show-hints");
+        assert_eq!(
+            error("set completion-query-items many"),
+            "many: invalid value for completion-query-items"
+        );
+        assert_eq!(
+            error("set bell-style none"),
+            "bell-style: unknown variable name"
+        );
+        assert_eq!(
+            bindings.describe_variables(true),
+            [
+                "set completion-ignore-case on",
+                "set completion-query-items -1",
+                "set completion-type circular",
+                "set editing-mode vi",
+                "set history-search-cwd off",
+                "set show-hints on",
+            ]
+        );
+    }
+}
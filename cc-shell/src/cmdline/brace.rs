@@ -0,0 +1,176 @@
+use super::expand::{Word, WordPart};
+
+// A word flattened for brace expansion: characters of unquoted text, which
+// may hold braces, and other parts carried along whole
+#[derive(Clone)]
+enum Item {
+    Char(char),
+    Part(WordPart),
+}
+
+/// Brace expansion, the first expansion phase: `a{b,c}d` becomes `abd acd`
+/// and `{1..10..2}` a sequence. Only unquoted braces are considered.
+pub fn expand(word: &Word) -> Vec<Word> {
+    let has_brace = word
+        .parts
+        .iter()
+        .any(|part| matches!(part, WordPart::Literal(s) if s.contains('{')));
+    if !has_brace {
+        return vec![word.clone()];
+    }
+    let mut items = Vec::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Literal(s) => items.extend(s.chars().map(Item::Char)),
+            other => items.push(Item::Part(other.clone())),
+        }
+    }
+    expand_items(&items)
+        .iter()
+        .map(|items| to_word(items))
+        .collect()
+}
+
+fn expand_items(items: &[Item]) -> Vec<Vec<Item>> {
+    // Find the first `{` with a matching `}` that makes a valid expansion
+    for open in 0..items.len() {
+        if !matches!(items[open], Item::Char('{')) {
+            continue;
+        }
+        let Some((close, commas)) = find_close(items, open) else {
+            continue;
+        };
+        let inner = &items[open + 1..close];
+        let alternatives: Vec<Vec<Item>> = if commas.is_empty() {
+            match sequence(inner) {
+                Some(seq) => seq
+                    .into_iter()
+                    .map(|s| s.chars().map(Item::Char).collect())
+                    .collect(),
+                None => continue,
+            }
+        } else {
+            let mut alternatives = Vec::new();
+            let mut start = open + 1;
+            for &comma in commas.iter().chain(std::iter::once(&close)) {
+                alternatives.push(items[start..comma].to_vec());
+                start = comma + 1;
+            }
+            alternatives
+        };
+
+        let prefix = &items[..open];
+        let suffix = &items[close + 1..];
+        let mut result = Vec::new();
+        for alternative in alternatives {
+            // Nested braces in an alternative, and later braces in the
+            // suffix, are expanded in turn
+            let mut combined = prefix.to_vec();
+            combined.extend(alternative);
+            combined.extend_from_slice(suffix);
+            result.extend(expand_items(&combined));
+        }
+        return result;
+    }
+    vec![items.to_vec()]
+}
+
+// The position of the `}` matching the `{` at `open`, with the positions of
+// the top-level commas in between
+fn find_close(items: &[Item], open: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (i, item) in items.iter().enumerate().skip(open + 1) {
+        match item {
+            Item::Char('{') => depth += 1,
+            Item::Char('}') if depth == 0 => return Some((i, commas)),
+            Item::Char('}') => depth -= 1,
+            Item::Char(',') if depth == 0 => commas.push(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// `x..y` or `x..y..step` over integers or single letters
+fn sequence(inner: &[Item]) -> Option<Vec<String>> {
+    let mut text = String::new();
+    for item in inner {
+        match item {
+            Item::Char(c) => text.push(*c),
+            Item::Part(_) => return None,
+        }
+    }
+    let mut fields = text.split("..");
+    let (start, end) = (fields.next()?, fields.next()?);
+    let step = match fields.next() {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+
+    if let (Ok(from), Ok(to)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // A leading zero on either end pads all values to the same width
+        let padded = |s: &str| {
+            s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            range(from, to, step)
+                .map(|n| {
+                    if n < 0 {
+                        format!("-{:0width$}", -n, width = width.saturating_sub(1))
+                    } else {
+                        format!("{:0width$}", n, width = width)
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => Some(c as i64),
+            _ => None,
+        }
+    };
+    let (from, to) = (single(start)?, single(end)?);
+    Some(
+        range(from, to, step)
+            .filter_map(|n| char::from_u32(n as u32))
+            .map(String::from)
+            .collect(),
+    )
+}
+
+// Counts from `from` towards `to` inclusive, in either direction
+fn range(from: i64, to: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = from.abs_diff(to) / step + 1;
+    let step = if from <= to {
+        step as i64
+    } else {
+        -(step as i64)
+    };
+    (0..count as i64).map(move |i| from + i * step)
+}
+
+fn to_word(items: &[Item]) -> Word {
+    let mut word = Word::default();
+    for item in items {
+        match item {
+            Item::Char(c) => match word.parts.last_mut() {
+                Some(WordPart::Literal(s)) => s.push(*c),
+                _ => word.parts.push(WordPart::Literal(c.to_string())),
+            },
+            Item::Part(part) => word.parts.push(part.clone()),
+        }
+    }
+    word
+}
@@ -1,139 +1,1467 @@
+use super::bindings::KeyBindings;
+use super::ccenv;
+use super::env::{is_valid_name, ShellEnv, Value};
 use super::executable::Executable;
-use super::{Cmd, RedirectMode};
-use std::fs::OpenOptions;
-use std::io::Write;
-
-pub fn handle_cd(cmd: &[String]) {
-    let dir = cmd.get(1).map_or("", |s| s.trim());
-    let path = if dir.starts_with("~") {
-        if let Ok(home) = std::env::var("HOME") {
-            if dir == "~" {
-                std::path::PathBuf::from(home)
-            } else {
-                std::path::Path::new(&home).join(&dir[1..])
+use super::expand::{self, split_text};
+use super::fds::SavedFds;
+use super::hooks;
+use super::list;
+use super::options::ShellOptions;
+use super::resolve::{self, Resolved};
+use super::resources::{self, CpuTimes, Limit, LIMITS};
+use super::{wait_pid, Cmd};
+use crate::error::{os_message, warn};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+pub fn handle_bind(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let mut args = operands.iter().skip(1);
+    let mut status = 0;
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-l" => {
+                for name in KeyBindings::action_names() {
+                    outln!("{}", name);
+                }
+                Ok(())
             }
-        } else {
-            std::path::PathBuf::from(dir)
+            "-p" => {
+                for line in env.bindings.describe() {
+                    outln!("{}", line);
+                }
+                Ok(())
+            }
+            "-v" => {
+                for line in env.bindings.describe_variables(env.options.vi) {
+                    outln!("{}", line);
+                }
+                Ok(())
+            }
+            "-r" => match args.next() {
+                Some(keyseq) => env.bindings.unbind(keyseq),
+                None => Err("-r: option requires an argument".to_string()),
+            },
+            "-f" => match args.next() {
+                Some(path) => bind_file(path, env),
+                None => Err("-f: option requires an argument".to_string()),
+            },
+            opt if opt.starts_with('-') => {
+                warn("bind", format_args!("{}: invalid option", opt));
+                errln!("bind: usage: bind [-lpv] [-f filename] [-r keyseq] [keyseq:function-name | set variable value]");
+                return 2;
+            }
+            line => bind_line(line, env),
+        };
+        if let Err(e) = result {
+            warn("bind", e);
+            status = 1;
+        }
+    }
+    status
+}
+
+// Applies one binding or `set` line
+fn bind_line(line: &str, env: &mut ShellEnv) -> Result<(), String> {
+    if let Some(vi) = env.bindings.parse_line(line)? {
+        env.options.vi = vi;
+    }
+    Ok(())
+}
+
+// Reads bindings from a file, one per line; `#` starts a comment
+fn bind_file(path: &str, env: &mut ShellEnv) -> Result<(), String> {
+    let text = std::fs::read_to_string(env.path(path))
+        .map_err(|e| format!("{}: {}", path, os_message(&e)))?;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        bind_line(line, env).map_err(|e| format!("{}: line {}: {}", path, n + 1, e))?;
+    }
+    Ok(())
+}
+
+pub fn handle_cd(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    if env.options.restricted {
+        warn("cd", "restricted");
+        return 1;
+    }
+    let operands = cmd.operands();
+    // Tildes were already expanded with the rest of the word
+    let (dir, print) = match operands.get(1).map(|s| s.trim()) {
+        None => match env.get("HOME") {
+            Some(home) => (home, false),
+            None => {
+                warn("cd", "HOME not set");
+                return 1;
+            }
+        },
+        Some("-") => match env.get("OLDPWD") {
+            Some(old) => (old, true),
+            None => {
+                warn("cd", "OLDPWD not set");
+                return 1;
+            }
+        },
+        Some(dir) => (dir.to_string(), false),
+    };
+
+    let new = match enterable_dir(&env.path(&dir)) {
+        Ok(new) => new,
+        Err(e) => {
+            warn("cd", format_args!("{}: {}", dir, os_message(&e)));
+            return 1;
         }
-    } else {
-        std::path::PathBuf::from(dir)
     };
+    let old = std::mem::replace(&mut env.cwd, new);
+    env.set("OLDPWD", &old.to_string_lossy());
+    let new = env.cwd.to_string_lossy().into_owned();
+    env.set("PWD", &new);
+    if print {
+        outln!("{}", new);
+    }
+    if old != env.cwd {
+        ccenv::update(env);
+        hooks::run("chpwd", &[], env);
+    }
+    0
+}
+
+/// ccenv [allow | deny | status]: trusts the .ccenv file of the working
+/// directory, or of the nearest parent with one, as it is now, so that
+/// `shopt -s ccenv` loads it; forgets it and unloads it; or shows whether
+/// it is trusted and loaded.
+pub fn handle_ccenv(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let action = operands.get(1).map_or("status", String::as_str);
+    if !matches!(action, "allow" | "deny" | "status") || operands.len() > 2 {
+        errln!("ccenv: usage: ccenv [allow | deny | status]");
+        return 2;
+    }
+    let Some(file) = ccenv::find(&env.cwd) else {
+        warn("ccenv", format_args!("no {} file here", ccenv::FILE_NAME));
+        return 1;
+    };
+    let name = file.to_string_lossy().into_owned();
+    if action == "status" {
+        let loaded = env.ccenv.as_ref().is_some_and(|l| l.file == file);
+        return match ccenv::is_trusted(&file, env) {
+            Ok(trusted) => {
+                let trust = if trusted { "trusted" } else { "not trusted" };
+                let state = if loaded { ", loaded" } else { "" };
+                outln!("{}: {}{}", name, trust, state);
+                0
+            }
+            Err(e) => {
+                warn(&name, os_message(&e));
+                1
+            }
+        };
+    }
+    if env.options.restricted {
+        warn("ccenv", "restricted");
+        return 1;
+    }
+    if let Err(e) = ccenv::set_trusted(&file, action == "allow", env) {
+        warn("ccenv", os_message(&e));
+        return 1;
+    }
+    if action == "deny" {
+        ccenv::unload(env);
+    }
+    ccenv::update(env);
+    0
+}
+
+// The physical path of a directory the shell can change to: one that
+// exists and can be searched, as chdir(2) would check
+pub(crate) fn enterable_dir(path: &Path) -> std::io::Result<PathBuf> {
+    let path = std::fs::canonicalize(path)?;
+    if !path.is_dir() {
+        return Err(std::io::Error::from_raw_os_error(libc::ENOTDIR));
+    }
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| std::io::Error::from_raw_os_error(libc::EINVAL))?;
+    if unsafe { libc::access(c_path.as_ptr(), libc::X_OK) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(path)
+}
 
-    if std::env::set_current_dir(&path).is_err() {
-        println!("cd: {}: No such file or directory", path.display());
+// Asks the shell to exit once the current command is done, so that a
+// login shell can still run its logout file
+pub fn handle_exit(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    env.exit_requested = true;
+    match cmd.operands().get(1) {
+        None => env.last_status,
+        Some(arg) => match arg.trim().parse::<i32>() {
+            Ok(num) => num & 0xff,
+            Err(_) => {
+                warn("exit", format_args!("{}: numeric argument required", arg));
+                2
+            }
+        },
     }
 }
 
-pub fn handle_exit(cmd: &[String]) {
-    let res = cmd.last().unwrap();
-    match res.trim().parse::<i32>() {
-        Ok(num) => std::process::exit(num),
-        Err(_) => println!("Invalid exit command"),
+/// eval [arg ...]: runs the arguments, joined by spaces, as shell code,
+/// which may span lines.
+pub fn handle_eval(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let source = cmd.operands()[1..].join(" ");
+    if source.trim().is_empty() {
+        return 0;
+    }
+    if let Some(mut list) = list::parse_line(&source, None, env) {
+        list.execute(env);
+    }
+    env.last_status
+}
+
+/// source file [arg ...], also `.`: runs the commands of a file in this
+/// shell. A file name without a slash is looked for in $PATH, then in the
+/// working directory. Arguments become the positional parameters while it
+/// runs, unless the file sets its own.
+pub fn handle_source(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let [name, file, args @ ..] = operands.as_slice() else {
+        warn(&operands[0], "filename argument required");
+        errln!("{0}: usage: {0} filename [arguments]", operands[0]);
+        return 2;
+    };
+    if env.options.restricted && file.contains('/') {
+        warn(name, format_args!("{}: restricted", file));
+        return 1;
+    }
+    let path = if file.contains('/') {
+        env.path(file)
+    } else {
+        source_path(file, env).unwrap_or_else(|| env.path(file))
+    };
+    let source = match std::fs::read(&path) {
+        Ok(source) => String::from_utf8_lossy(&source).into_owned(),
+        Err(e) => {
+            warn(file, os_message(&e));
+            return 1;
+        }
+    };
+    if args.is_empty() {
+        return list::run_lines(&source, env);
+    }
+    let saved = std::mem::replace(&mut env.positional, args.to_vec());
+    let status = list::run_lines(&source, env);
+    if env.positional == args {
+        env.positional = saved;
     }
+    status
 }
 
-pub fn handle_echo(cmd: &Cmd) -> Result<(), Box<dyn std::error::Error>> {
+// The first readable file called `name` in $PATH
+fn source_path(name: &str, env: &ShellEnv) -> Option<PathBuf> {
+    let path = env.get("PATH")?;
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| env.path(dir).join(name))
+        .find(|candidate| candidate.is_file() && File::open(candidate).is_ok())
+}
+
+/// wait [pid | %job ...]: waits for background jobs, all of them if none
+/// are given, and returns the status of the last one named.
+pub fn handle_wait(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    if operands.len() == 1 {
+        for pid in env.jobs.pids() {
+            wait_job(pid, env);
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for arg in &operands[1..] {
+        let pid = if arg.starts_with('%') {
+            match env.jobs.find(arg) {
+                Some(pid) => pid,
+                None => {
+                    warn("wait", format_args!("{}: no such job", arg));
+                    status = 127;
+                    continue;
+                }
+            }
+        } else {
+            match arg.parse::<libc::pid_t>() {
+                Ok(pid) if pid > 0 => pid,
+                _ => {
+                    warn(
+                        "wait",
+                        format_args!("`{}': not a pid or valid job spec", arg),
+                    );
+                    status = 1;
+                    continue;
+                }
+            }
+        };
+        if !env.jobs.contains(pid) {
+            warn(
+                "wait",
+                format_args!("pid {} is not a child of this shell", pid),
+            );
+            status = 127;
+            continue;
+        }
+        status = wait_job(pid, env);
+    }
+    status
+}
+
+fn wait_job(pid: libc::pid_t, env: &mut ShellEnv) -> i32 {
+    let (status, times) = wait_pid(pid);
+    env.child_times += times;
+    env.jobs.remove(pid);
+    status
+}
+
+pub fn handle_echo(cmd: &Cmd) -> i32 {
+    let operands = cmd.operands();
+    let mut args = &operands[1..];
+
     // Check for -n flag
     let mut no_newline = false;
-    let mut arg_index = 1;
-    let mut output_message = String::new();
-
-    if cmd.args.len() > 1 && cmd.args[1] == "-n" {
+    if args.first().is_some_and(|arg| arg == "-n") {
         no_newline = true;
-        arg_index = 2;
-    }
-
-    // Process arguments to find redirection
-    let mut i = arg_index;
-    while i < cmd.args.len() {
-        if (cmd.args[i] == ">"
-            || cmd.args[i] == ">>"
-            || cmd.args[i] == "1>"
-            || cmd.args[i] == "1>>"
-            || cmd.args[i] == "2>"
-            || cmd.args[i] == "2>>"
-            || cmd.args[i] == "<"
-            || cmd.args[i] == ">|")
-            && i + 1 < cmd.args.len()
-        {
-            i += 2;
+        args = &args[1..];
+    }
+
+    let mut output_message = args.join(" ");
+    if !no_newline {
+        output_message.push('\n');
+    }
+    // print! would panic if stdout is closed or a broken pipe
+    let mut stdout = std::io::stdout();
+    match stdout
+        .write_all(output_message.as_bytes())
+        .and_then(|_| stdout.flush())
+    {
+        Ok(()) => 0,
+        Err(e) => {
+            warn("echo", format_args!("write error: {}", os_message(&e)));
+            1
+        }
+    }
+}
+
+/// exec [command [args ...]]: replaces the shell with the command. Without
+/// one, the redirections stay in effect for the rest of the session.
+pub fn handle_exec(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    if env.options.restricted {
+        warn("exec", "restricted");
+        return 1;
+    }
+    let operands = cmd.operands();
+    let args = match operands.get(1).map(String::as_str) {
+        Some("--") => &operands[2..],
+        _ => &operands[1..],
+    };
+    // A program embedding the shell can't be replaced: there the command
+    // runs as a child, with the redirections for it alone, and the run
+    // ends when it does
+    let embedded = env.embedded && !args.is_empty();
+    let _saved = if embedded {
+        match SavedFds::apply(&cmd.redirects, &env.cwd) {
+            Ok(saved) => Some(saved),
+            Err(e) => {
+                warn("exec", e);
+                return 1;
+            }
+        }
+    } else {
+        if let Err(e) = env.fds.apply(&cmd.redirects, &env.cwd) {
+            warn("exec", e);
+            return 1;
+        }
+        None
+    };
+    let Some(prog) = args.first() else {
+        return 0;
+    };
+
+    let Some(path) = resolve::command_path(prog, env, None) else {
+        warn("exec", format_args!("{}: not found", prog));
+        return 127;
+    };
+    let mut command = Command::new(path);
+    command
+        .arg0(prog)
+        .current_dir(&env.cwd)
+        .args(&args[1..])
+        .env_clear()
+        .envs(env.exported());
+    for assignment in &cmd.assignments {
+        if let Some(value) = env.get(&assignment.name) {
+            command.env(&assignment.name, value);
+        }
+    }
+    let _ = std::io::stdout().flush();
+    if embedded {
+        return match command.spawn() {
+            Ok(child) => {
+                let (status, times) = wait_pid(child.id() as libc::pid_t);
+                env.child_times += times;
+                env.exit_requested = true;
+                status
+            }
+            Err(err) => exec_failed(prog, err),
+        };
+    }
+    // Only returns if the command could not be executed
+    exec_failed(prog, command.exec())
+}
+
+fn exec_failed(prog: &str, err: std::io::Error) -> i32 {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        warn("exec", format_args!("{}: not found", prog));
+        127
+    } else {
+        warn("exec", format_args!("{}: {}", prog, os_message(&err)));
+        126
+    }
+}
+
+pub fn handle_pwd(env: &ShellEnv) {
+    outln!("{}", env.cwd.display())
+}
+
+/// type [-afptP] name ...: tells how each name would be run. -t prints
+/// only the kind, -p the file that would run, -P searches $PATH even for
+/// builtins and -a shows every match, not only the first.
+pub fn handle_type(cmd: &Cmd, env: &ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let mut all = false;
+    let mut kind_only = false;
+    let mut path_only = false;
+    let mut force_path = false;
+    let mut names = operands.iter().skip(1).peekable();
+    while let Some(flags) = names.peek().and_then(|arg| arg.strip_prefix('-')) {
+        names.next();
+        if flags == "-" {
             break;
         }
+        for flag in flags.chars() {
+            match flag {
+                'a' => all = true,
+                // No shell functions, so -f changes nothing
+                'f' => {}
+                'p' => path_only = true,
+                'P' => force_path = true,
+                't' => kind_only = true,
+                _ => {
+                    warn("type", format_args!("-{}: invalid option", flag));
+                    errln!("type: usage: type [-afptP] name [name ...]");
+                    return 2;
+                }
+            }
+        }
+    }
+
+    let mut status = 0;
+    for name in names {
+        let mut found = if all {
+            resolve::resolve_all(name, env)
+        } else {
+            resolve::resolve(name, env, true).into_iter().collect()
+        };
+        if force_path {
+            // As if there were no keywords or builtins
+            found.retain(|r| matches!(r, Resolved::File { .. }));
+            if found.is_empty() {
+                let search = env.get("PATH").unwrap_or_default();
+                found.extend(
+                    resolve::search_path(name, &search, &env.cwd, &env.policy, all)
+                        .into_iter()
+                        .map(|path| Resolved::File {
+                            path,
+                            hashed: false,
+                        }),
+                );
+            }
+        }
+        if found.is_empty() {
+            if !kind_only && !path_only && !force_path {
+                warn("type", format_args!("{}: not found", name));
+            }
+            status = 1;
+        }
+        for resolved in found {
+            match resolved {
+                _ if kind_only => outln!("{}", resolved.kind()),
+                Resolved::File { path, .. } if path_only || force_path => {
+                    outln!("{}", path.display())
+                }
+                _ if path_only => {}
+                _ => outln!("{}", resolved.describe(name)),
+            }
+        }
+    }
+    status
+}
+
+/// hash [-r] [-d name] [-t name] [name ...]: shows or changes the table
+/// of where commands were found in $PATH. Without arguments it lists the
+/// commands run so far and how often.
+pub fn handle_hash(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let path = env.get("PATH").unwrap_or_default();
+    let mut args = operands.iter().skip(1).peekable();
+    let mut delete = false;
+    let mut show = false;
+    while let Some(flags) = args.peek().and_then(|arg| arg.strip_prefix('-')) {
+        args.next();
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'r' => env.hash.clear(),
+                'd' => delete = true,
+                't' => show = true,
+                _ => {
+                    warn("hash", format_args!("-{}: invalid option", flag));
+                    errln!("hash: usage: hash [-r] [-d name] [-t name] [name ...]");
+                    return 2;
+                }
+            }
+        }
+    }
+
+    let names: Vec<&String> = args.collect();
+    if names.is_empty() {
+        if delete || show {
+            warn(
+                "hash",
+                format_args!(
+                    "-{}: option requires an argument",
+                    if delete { 'd' } else { 't' }
+                ),
+            );
+            return 1;
+        }
+        if operands.len() == 1 {
+            let mut entries = env.hash.entries().peekable();
+            if entries.peek().is_none() {
+                errln!("hash: hash table empty");
+            } else {
+                outln!("hits\tcommand");
+                for (hits, location) in entries {
+                    outln!("{:>4}\t{}", hits, location.display());
+                }
+            }
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in names {
+        let ok = if delete {
+            env.hash.remove(name)
+        } else if show {
+            match env.hash.get(name, &path, &env.cwd) {
+                Some(location) => {
+                    outln!("{}", location.display());
+                    true
+                }
+                None => false,
+            }
+        } else {
+            // Builtins are never looked up in $PATH
+            Executable::is_builtin(name)
+                || env.hash.add(name, &path, &env.cwd, &env.policy).is_some()
+        };
+        if !ok {
+            warn("hash", format_args!("{}: not found", name));
+            status = 1;
+        }
+    }
+    status
+}
 
-        if i > arg_index && !output_message.is_empty() {
-            output_message.push(' ');
+/// read [-r] [-p prompt] [-a array] [-d delim] [-t timeout] [-n count] [name ...]
+pub fn handle_read(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let args: Vec<&String> = cmd.args.iter().skip(1).collect();
+
+    let mut raw = false;
+    let mut prompt = None;
+    let mut array = None;
+    let mut delim = '\n';
+    let mut timeout: Option<f64> = None;
+    let mut count: Option<usize> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i];
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        let flags: Vec<char> = arg[1..].chars().collect();
+        for (j, &flag) in flags.iter().enumerate() {
+            match flag {
+                'r' => raw = true,
+                'p' | 'a' | 'd' | 't' | 'n' => {
+                    // The option value is the rest of this argument or the next one
+                    let rest: String = flags[j + 1..].iter().collect();
+                    let value = if !rest.is_empty() {
+                        rest
+                    } else {
+                        i += 1;
+                        match args.get(i) {
+                            Some(v) => v.to_string(),
+                            None => {
+                                warn(
+                                    "read",
+                                    format_args!("-{}: option requires an argument", flag),
+                                );
+                                return 2;
+                            }
+                        }
+                    };
+                    match flag {
+                        'p' => prompt = Some(value),
+                        'a' => array = Some(value),
+                        'd' => delim = value.chars().next().unwrap_or('\0'),
+                        't' => match value.parse::<f64>() {
+                            Ok(t) if t >= 0.0 => timeout = Some(t),
+                            _ => {
+                                warn(
+                                    "read",
+                                    format_args!("{}: invalid timeout specification", value),
+                                );
+                                return 1;
+                            }
+                        },
+                        _ => match value.parse::<usize>() {
+                            Ok(n) => count = Some(n),
+                            Err(_) => {
+                                warn("read", format_args!("{}: invalid number", value));
+                                return 1;
+                            }
+                        },
+                    }
+                    break;
+                }
+                other => {
+                    warn("read", format_args!("-{}: invalid option", other));
+                    errln!("read: usage: read [-r] [-a array] [-d delim] [-n nchars] [-p prompt] [-t timeout] [name ...]");
+                    return 2;
+                }
+            }
         }
-        output_message.push_str(&cmd.args[i]);
         i += 1;
     }
+    let names = &args[i..];
+    for name in names.iter().copied().chain(array.as_ref()) {
+        if !is_valid_name(name) {
+            warn("read", format_args!("`{}': not a valid identifier", name));
+            return 1;
+        }
+    }
 
-    match (&cmd.stdout_redirect, &cmd.stderr_redirect) {
-        (Some(redirect), None) | (None, Some(redirect)) => {
-            let mut file = match redirect.mode {
-                RedirectMode::Write | RedirectMode::ForceWrite => OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(&redirect.path)?,
-                RedirectMode::Append => OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(&redirect.path)?,
-                _ => return Err("Invalid redirection mode".into()),
-            };
-            if redirect.fd == 1 {
-                if no_newline {
-                    write!(file, "{}", output_message)?;
-                } else {
-                    writeln!(file, "{}", output_message)?;
+    // Read from the shell's own stdin without buffering so that later
+    // commands see the rest of the input
+    let stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+    let input: &File = &stdin;
+    let fd = input.as_raw_fd();
+    let is_tty = unsafe { libc::isatty(fd) } == 1;
+
+    if let Some(prompt) = &prompt {
+        if is_tty {
+            err!("{}", prompt);
+        }
+    }
+
+    if timeout == Some(0.0) {
+        // -t 0 only reports whether input is available
+        return if wait_readable(fd, Duration::ZERO) {
+            0
+        } else {
+            1
+        };
+    }
+
+    // With -n on a terminal, return as soon as enough characters are typed
+    let _guard = match count {
+        Some(_) if is_tty => NonCanonicalGuard::new(fd),
+        _ => None,
+    };
+
+    let deadline = timeout.map(|t| Instant::now() + Duration::from_secs_f64(t));
+    // Characters read, flagged when escaped by a backslash
+    let mut text: Vec<(char, bool)> = Vec::new();
+    let mut pending = Vec::new();
+    let mut nchars = 0;
+    let mut escaped = false;
+    let mut status = 0;
+    loop {
+        if count.is_some_and(|n| nchars >= n) {
+            break;
+        }
+        if let Some(deadline) = deadline {
+            if !wait_readable(fd, deadline.saturating_duration_since(Instant::now())) {
+                status = TIMED_OUT;
+                break;
+            }
+        }
+        let mut byte = [0u8; 1];
+        match (&*input).read(&mut byte) {
+            Ok(0) => {
+                status = 1;
+                break;
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => {
+                status = 1;
+                break;
+            }
+        }
+
+        // Decode UTF-8 a byte at a time
+        pending.push(byte[0]);
+        let c = match std::str::from_utf8(&pending) {
+            Ok(s) => s.chars().next().unwrap_or_default(),
+            Err(e) if e.error_len().is_none() => continue,
+            Err(_) => char::REPLACEMENT_CHARACTER,
+        };
+        pending.clear();
+        nchars += 1;
+
+        if escaped {
+            // Backslash-newline is a line continuation
+            if c != '\n' {
+                text.push((c, true));
+            }
+            escaped = false;
+        } else if c == '\\' && !raw {
+            escaped = true;
+        } else if c == delim {
+            break;
+        } else {
+            text.push((c, false));
+        }
+    }
+
+    let ifs = env.ifs();
+    for name in array.iter().chain(names.iter().copied()) {
+        if let Err(e) = env.check_writable(name) {
+            warn("read", e);
+            return 1;
+        }
+    }
+    if let Some(array) = array {
+        env.set_indexed(&array, split_text(&text, &ifs, 0));
+    } else if names.is_empty() {
+        let line: String = text.iter().map(|&(c, _)| c).collect();
+        env.set("REPLY", &line);
+    } else {
+        let fields = split_text(&text, &ifs, names.len());
+        for (i, name) in names.iter().enumerate() {
+            env.set(name, fields.get(i).map_or("", |s| s.as_str()));
+        }
+    }
+    status
+}
+
+// The status of `read -t` when time runs out, as in bash: 128 + SIGALRM,
+// which end of input (1) can't be mistaken for
+const TIMED_OUT: i32 = 128 + libc::SIGALRM;
+
+// Whether `fd` can be read within `timeout`. A signal doesn't cut the wait
+// short, and an error is left for the read to report.
+fn wait_readable(fd: RawFd, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let left = deadline.saturating_duration_since(Instant::now());
+        let ms = left.as_millis().min(i32::MAX as u128) as i32;
+        match unsafe { libc::poll(&mut pfd, 1, ms) } {
+            0 => return false,
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            _ => return true,
+        }
+    }
+}
+
+// Puts a terminal into non-canonical mode until dropped
+struct NonCanonicalGuard {
+    fd: RawFd,
+    saved: libc::termios,
+}
+
+impl NonCanonicalGuard {
+    fn new(fd: RawFd) -> Option<Self> {
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return None;
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !libc::ICANON;
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) };
+        Some(NonCanonicalGuard { fd, saved })
+    }
+}
+
+impl Drop for NonCanonicalGuard {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved) };
+    }
+}
+
+/// declare [-aAxp] [name[=value] ...]
+pub fn handle_declare(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let mut indexed = false;
+    let mut assoc = false;
+    let mut export = false;
+    let mut print = false;
+    let mut status = 0;
+    // Whether any names were given, even ones that were then rejected
+    let mut given = false;
+    let mut names = Vec::new();
+
+    let operands = cmd.operands();
+    for (i, arg) in operands.iter().enumerate().skip(1) {
+        // Assignment words were expanded as such; other arguments may
+        // still expand to one, as in `declare $name=value`
+        let assignment = match cmd.declared.get(i) {
+            Some(Some(assignment)) => Some(assignment.clone()),
+            _ => expand::ExpandedAssignment::parse(arg),
+        };
+        if let Some(assignment) = assignment {
+            given = true;
+            let name = assignment.name.clone();
+            if indexed || assoc {
+                if let Err(e) = env.declare_array(&name, assoc) {
+                    warn("declare", e);
+                    status = 1;
+                    continue;
+                }
+            }
+            if let Err(e) = assignment.perform(env) {
+                warn("declare", e);
+                status = 1;
+                continue;
+            }
+            if export {
+                env.export(&name);
+            }
+            names.push(name);
+            continue;
+        }
+        if let Some(flags) = arg.strip_prefix('-').filter(|_| !given) {
+            for flag in flags.chars() {
+                match flag {
+                    'a' => indexed = true,
+                    'A' => assoc = true,
+                    'x' => export = true,
+                    'p' => print = true,
+                    other => {
+                        warn("declare", format_args!("-{}: invalid option", other));
+                        errln!("declare: usage: declare [-aAxp] [name[=value] ...]");
+                        return 2;
+                    }
+                }
+            }
+            continue;
+        }
+        given = true;
+        if !is_valid_name(arg) {
+            warn("declare", format_args!("`{}': not a valid identifier", arg));
+            status = 1;
+            continue;
+        }
+        if print {
+            names.push(arg.clone());
+            continue;
+        }
+        if indexed || assoc {
+            if let Err(e) = env.declare_array(arg, assoc) {
+                warn("declare", e);
+                status = 1;
+            }
+        }
+        if export {
+            env.export(arg);
+        }
+        names.push(arg.clone());
+    }
+
+    // Every variable is listed only when no names were given
+    if print || !(given || indexed || assoc || export) {
+        let names = if given { names } else { env.names() };
+        for name in names {
+            match format_declaration(&name, env) {
+                Some(decl) => outln!("{}", decl),
+                None => {
+                    warn("declare", format_args!("{}: not found", name));
+                    status = 1;
+                }
+            }
+        }
+    }
+    status
+}
+
+// Formats a variable the way `declare -p` prints it
+fn format_declaration(name: &str, env: &ShellEnv) -> Option<String> {
+    let var = env.var(name)?;
+    let quote = |s: &str| {
+        let mut out = String::from("\"");
+        for c in s.chars() {
+            if matches!(c, '"' | '\\' | '$' | '`') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('"');
+        out
+    };
+    let export = if var.exported { "x" } else { "" };
+    Some(match &var.value {
+        Value::Scalar(s) => {
+            let flags = if var.exported { "-x" } else { "--" };
+            format!("declare {} {}={}", flags, name, quote(s))
+        }
+        Value::Indexed(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|(k, v)| format!("[{}]={}", k, quote(v)))
+                .collect();
+            format!("declare -a{} {}=({})", export, name, items.join(" "))
+        }
+        Value::Assoc(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|(k, v)| format!("[{}]={}", k, quote(v)))
+                .collect();
+            format!("declare -A{} {}=({})", export, name, items.join(" "))
+        }
+    })
+}
+
+/// unset [-v] name[subscript] ...
+pub fn handle_unset(cmd: &[String], env: &mut ShellEnv) -> i32 {
+    let mut status = 0;
+    for arg in cmd.iter().skip(1) {
+        if arg == "-v" {
+            continue;
+        }
+        let name = arg.split('[').next().unwrap_or_default();
+        let result = env.check_writable(name).and_then(|()| {
+            match arg.strip_suffix(']').and_then(|s| s.split_once('[')) {
+                Some((name, "@" | "*")) if is_valid_name(name) => {
+                    env.unset(name);
+                    Ok(())
                 }
+                Some((name, sub)) if is_valid_name(name) => env.unset_element(name, sub),
+                _ if is_valid_name(arg) => {
+                    env.unset(arg);
+                    Ok(())
+                }
+                _ => Err(format!("`{}': not a valid identifier", arg)),
+            }
+        });
+        if let Err(e) = result {
+            warn("unset", e);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// set [-x] [+x] [-o option] [+o option] [--] [arg ...]
+pub fn handle_set(cmd: &[String], env: &mut ShellEnv) -> i32 {
+    let args: Vec<&String> = cmd.iter().skip(1).collect();
+
+    if args.is_empty() {
+        for name in env.names() {
+            if let Some(decl) = format_declaration(&name, env) {
+                // `declare -- x="1"` is printed by set as `x="1"`
+                let assignment = decl.splitn(3, ' ').nth(2).unwrap_or_default();
+                outln!("{}", assignment);
+            }
+        }
+        return 0;
+    }
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i];
+        let on = arg.starts_with('-');
+        if arg == "--" || !(on || arg.starts_with('+')) {
+            // The rest replaces the positional parameters; a bare `--`
+            // clears them
+            let rest = if arg == "--" {
+                &args[i + 1..]
             } else {
-                if no_newline {
-                    print!("{}", output_message);
-                    std::io::stdout().flush()?;
-                } else {
-                    println!("{}", output_message);
+                &args[i..]
+            };
+            env.positional = rest.iter().map(|arg| arg.to_string()).collect();
+            break;
+        }
+        if &arg[1..] == "o" {
+            i += 1;
+            match args.get(i) {
+                Some(name) => {
+                    if let Err(e) = env.options.set(name, on) {
+                        warn("set", e);
+                        return 1;
+                    }
+                }
+                None if on => {
+                    for (name, value) in env.options.list() {
+                        outln!("{:<15}\t{}", name, if value { "on" } else { "off" });
+                    }
                 }
+                None => {
+                    for (name, value) in env.options.list() {
+                        outln!("set {}o {}", if value { '-' } else { '+' }, name);
+                    }
+                }
+            }
+        } else {
+            for flag in arg[1..].chars() {
+                let Some(name) = ShellOptions::flag_name(flag) else {
+                    warn("set", format_args!("{}{}: invalid option", &arg[..1], flag));
+                    errln!("set: usage: set [-x] [-o option-name] [--] [arg ...]");
+                    return 2;
+                };
+                let _ = env.options.set(name, on);
+            }
+        }
+        i += 1;
+    }
+    0
+}
+
+/// shift [n]: drops the first `n` positional parameters, 1 by default
+pub fn handle_shift(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let n = match operands.get(1).map(|arg| (arg, arg.parse::<i64>())) {
+        None => 1,
+        Some((_, Ok(n))) if n >= 0 => n as usize,
+        Some((arg, Ok(_))) => {
+            warn("shift", format_args!("{}: shift count out of range", arg));
+            return 1;
+        }
+        Some((arg, Err(_))) => {
+            warn("shift", format_args!("{}: numeric argument required", arg));
+            return 1;
+        }
+    };
+    if n > env.positional.len() {
+        return 1;
+    }
+    env.positional.drain(..n);
+    0
+}
+
+/// break [n]: leaves the `n` innermost `select` loops, 1 by default
+pub fn handle_break(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    if operands.len() > 2 {
+        warn("break", "too many arguments");
+        return 1;
+    }
+    let n = match operands.get(1).map(|arg| (arg, arg.parse::<i64>())) {
+        None => 1,
+        Some((_, Ok(n))) if n > 0 => n as usize,
+        Some((arg, Ok(_))) => {
+            warn("break", format_args!("{}: loop count out of range", arg));
+            return 1;
+        }
+        Some((arg, Err(_))) => {
+            warn("break", format_args!("{}: numeric argument required", arg));
+            return 1;
+        }
+    };
+    if env.loops == 0 {
+        warn("break", "only meaningful in a `select' loop");
+        return 0;
+    }
+    env.breaking = n.min(env.loops);
+    0
+}
+
+/// getopts optstring name [arg ...]: parses the next option of the
+/// arguments, or of the positional parameters, into `name`, with $OPTARG
+/// for its argument and $OPTIND for the index of the next argument. A
+/// leading `:` in `optstring` reports errors through `name` and $OPTARG
+/// instead of printing them.
+pub fn handle_getopts(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let [_, spec, name, args @ ..] = operands.as_slice() else {
+        errln!("getopts: usage: getopts optstring name [arg ...]");
+        return 2;
+    };
+    if !is_valid_name(name) {
+        warn(
+            "getopts",
+            format_args!("`{}': not a valid identifier", name),
+        );
+        return 1;
+    }
+    if let Err(e) = env.check_writable(name) {
+        warn("getopts", e);
+        return 1;
+    }
+    let args = if args.is_empty() {
+        env.positional.clone()
+    } else {
+        args.to_vec()
+    };
+    let (silent, spec) = match spec.strip_prefix(':') {
+        Some(spec) => (true, spec),
+        None => (false, spec.as_str()),
+    };
+
+    let mut optind = env
+        .get("OPTIND")
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+    // A changed $OPTIND, e.g. reset to 1, starts at the beginning of an arg
+    let mut offset = match env.getopts {
+        (last, offset) if last == optind => offset,
+        _ => 0,
+    };
+
+    let mut found = None;
+    if let Some(arg) = args.get(optind - 1) {
+        if offset == 0 && arg == "--" {
+            optind += 1;
+        } else if offset > 0 || arg.len() > 1 && arg.starts_with('-') {
+            found = arg[offset.max(1)..].chars().next().map(|c| (arg, c));
+        }
+    }
+    let Some((arg, option)) = found else {
+        // No more options
+        env.getopts = (optind, 0);
+        env.set("OPTIND", &optind.to_string());
+        env.set(name, "?");
+        return 1;
+    };
+    offset = offset.max(1) + option.len_utf8();
+    let rest = &arg[offset..];
+    if rest.is_empty() {
+        optind += 1;
+        offset = 0;
+    }
+
+    let mut result = option.to_string();
+    let mut optarg = None;
+    match spec.find(option).filter(|_| option != ':') {
+        None => {
+            if !silent {
+                errln!("cc-shell: illegal option -- {}", option);
             }
+            result = "?".to_string();
+            optarg = silent.then(|| option.to_string());
         }
-        _ => {
-            // No redirection, print to stdout
-            if no_newline {
-                print!("{}", output_message);
-                std::io::stdout().flush()?;
+        Some(i) if spec[i + option.len_utf8()..].starts_with(':') => {
+            // The argument is the rest of this word, or else the next one
+            if !rest.is_empty() {
+                optarg = Some(rest.to_string());
+                optind += 1;
+                offset = 0;
+            } else if let Some(next) = args.get(optind - 1) {
+                optarg = Some(next.clone());
+                optind += 1;
+            } else if silent {
+                result = ":".to_string();
+                optarg = Some(option.to_string());
             } else {
-                println!("{}", output_message);
+                errln!("cc-shell: option requires an argument -- {}", option);
+                result = "?".to_string();
             }
         }
+        Some(_) => {}
+    }
+    env.getopts = (optind, offset);
+    env.set("OPTIND", &optind.to_string());
+    env.set(name, &result);
+    match optarg {
+        Some(optarg) => env.set("OPTARG", &optarg),
+        None => env.unset("OPTARG"),
     }
+    0
+}
 
-    Ok(())
+/// shopt [-su] [-pq] [optname ...]: sets, unsets or shows the options of
+/// `shopt`. With names and neither -s nor -u, the status tells whether
+/// they are all on.
+pub fn handle_shopt(cmd: &Cmd, env: &mut ShellEnv) -> i32 {
+    let operands = cmd.operands();
+    let mut set = None;
+    let mut print = false;
+    let mut quiet = false;
+    let mut used = 1;
+    for arg in &operands[1..] {
+        if arg == "--" {
+            used += 1;
+            break;
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+            break;
+        };
+        for flag in flags.chars() {
+            match flag {
+                's' | 'u' if set.is_some_and(|on| on != (flag == 's')) => {
+                    warn("shopt", "cannot set and unset shell options simultaneously");
+                    return 1;
+                }
+                's' | 'u' => set = Some(flag == 's'),
+                'p' => print = true,
+                'q' => quiet = true,
+                _ => {
+                    warn("shopt", format_args!("-{}: invalid option", flag));
+                    errln!("shopt: usage: shopt [-pqsu] [optname ...]");
+                    return 2;
+                }
+            }
+        }
+        used += 1;
+    }
+    let names = &operands[used..];
+
+    let show = |name: &str, on: bool| {
+        if quiet {
+            return;
+        }
+        if print {
+            outln!("shopt {} {}", if on { "-s" } else { "-u" }, name);
+        } else {
+            outln!("{:<15}\t{}", name, if on { "on" } else { "off" });
+        }
+    };
+    if names.is_empty() {
+        // List the options, only those on or off with -s or -u
+        for (name, on) in env.options.list_shopt() {
+            if set.map_or(true, |wanted| wanted == on) {
+                show(name, on);
+            }
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in names {
+        if let Some(on) = set {
+            if let Err(e) = env.options.set_shopt(name, on) {
+                warn("shopt", e);
+                status = 1;
+            }
+            continue;
+        }
+        match env
+            .options
+            .list_shopt()
+            .into_iter()
+            .find(|(n, _)| n == name)
+        {
+            Some((name, on)) => {
+                show(name, on);
+                if !on {
+                    status = 1;
+                }
+            }
+            None => {
+                warn("shopt", format_args!("{}: invalid shell option name", name));
+                status = 1;
+            }
+        }
+    }
+    status
 }
 
-pub fn handle_pwd() {
-    if let Ok(path) = std::env::current_dir() {
-        println!("{}", path.display())
+/// times: user and system time of the shell, then of its children
+pub fn handle_times() -> i32 {
+    let shell = CpuTimes::of(libc::RUSAGE_SELF);
+    let children = CpuTimes::of(libc::RUSAGE_CHILDREN);
+    for times in [shell, children] {
+        outln!(
+            "{} {}",
+            resources::format_minutes(times.user),
+            resources::format_minutes(times.sys)
+        );
     }
+    0
 }
 
-pub fn handle_type(cmd: &[String]) {
-    let second = cmd.last().unwrap().trim();
-    if Executable::is_builtin(second) {
-        println!("{second} is a shell builtin");
-    } else if let Ok(path) = std::env::var("PATH") {
-        let mut cmd_exist = false;
-        for dir in path.split(':') {
-            let p = std::path::Path::new(dir).join(second);
-            if p.exists() {
-                println!("{second} is {}", p.to_str().unwrap());
-                cmd_exist = true;
-                break;
+/// umask [-p] [-S] [mode]
+pub fn handle_umask(cmd: &Cmd) -> i32 {
+    let mut symbolic = false;
+    let mut reusable = false;
+    let mut mode = None;
+    let operands = cmd.operands();
+    for arg in operands.iter().skip(1) {
+        match arg.as_str() {
+            "-S" => symbolic = true,
+            "-p" => reusable = true,
+            "-pS" | "-Sp" => (symbolic, reusable) = (true, true),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                warn("umask", format_args!("{}: invalid option", arg));
+                errln!("umask: usage: umask [-p] [-S] [mode]");
+                return 2;
             }
+            _ => mode = Some(arg),
         }
-        if !cmd_exist {
-            println!("{second}: not found");
+    }
+
+    let mask = resources::current_umask();
+    let Some(mode) = mode else {
+        let shown = if symbolic {
+            resources::symbolic_umask(mask)
+        } else {
+            format!("{:04o}", mask)
+        };
+        if reusable {
+            outln!("umask{} {}", if symbolic { " -S" } else { "" }, shown);
+        } else {
+            outln!("{}", shown);
+        }
+        return 0;
+    };
+
+    let new_mask = if mode.starts_with(|c: char| c.is_ascii_digit()) {
+        match u32::from_str_radix(mode, 8) {
+            Ok(m) if m <= 0o777 => m,
+            _ => {
+                warn("umask", format_args!("{}: octal number out of range", mode));
+                return 1;
+            }
         }
     } else {
-        println!("{second}: not found");
+        match resources::apply_symbolic_umask(mask, mode) {
+            Ok(m) => m,
+            Err(e) => {
+                warn("umask", e);
+                return 1;
+            }
+        }
+    };
+    unsafe { libc::umask(new_mask as libc::mode_t) };
+    0
+}
+
+/// ulimit [-SHa] [-cfntv] [limit]
+pub fn handle_ulimit(cmd: &Cmd) -> i32 {
+    let mut soft = false;
+    let mut hard = false;
+    let mut all = false;
+    let mut selected: Vec<&Limit> = Vec::new();
+    let mut value = None;
+    let operands = cmd.operands();
+    for arg in operands.iter().skip(1) {
+        let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+            value = Some(arg.as_str());
+            continue;
+        };
+        for flag in flags.chars() {
+            match flag {
+                'S' => soft = true,
+                'H' => hard = true,
+                'a' => all = true,
+                _ => match Limit::find(flag) {
+                    Some(limit) => selected.push(limit),
+                    None => {
+                        warn("ulimit", format_args!("-{}: invalid option", flag));
+                        errln!("ulimit: usage: ulimit [-SHa] [-cfntv] [limit]");
+                        return 2;
+                    }
+                },
+            }
+        }
+    }
+    if all {
+        selected = LIMITS.iter().collect();
+    }
+    if selected.is_empty() {
+        selected.extend(Limit::find('f'));
+    }
+
+    let Some(value) = value.filter(|_| !all) else {
+        // Queries show the soft limit unless -H is given
+        let labelled = selected.len() > 1;
+        for limit in selected {
+            let shown = match limit.get(hard && !soft) {
+                Ok(Some(n)) => n.to_string(),
+                Ok(None) => "unlimited".to_string(),
+                Err(e) => {
+                    warn(
+                        "ulimit",
+                        format_args!(
+                            "{}: cannot get limit: {}",
+                            limit.description,
+                            os_message(&e)
+                        ),
+                    );
+                    return 1;
+                }
+            };
+            if labelled {
+                let unit = if limit.unit.is_empty() {
+                    format!("(-{})", limit.flag)
+                } else {
+                    format!("({}, -{})", limit.unit, limit.flag)
+                };
+                outln!("{:<20}{:>16} {}", limit.description, unit, shown);
+            } else {
+                outln!("{}", shown);
+            }
+        }
+        return 0;
+    };
+
+    let parsed = match value {
+        "unlimited" => None,
+        n => match n.parse::<u64>() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                warn("ulimit", format_args!("{}: invalid number", n));
+                return 1;
+            }
+        },
+    };
+    // Setting a limit without -S or -H sets both
+    if !soft && !hard {
+        (soft, hard) = (true, true);
+    }
+    let mut status = 0;
+    for limit in selected {
+        if let Err(e) = limit.set(parsed, soft, hard) {
+            warn(
+                "ulimit",
+                format_args!(
+                    "{}: cannot modify limit: {}",
+                    limit.description,
+                    os_message(&e)
+                ),
+            );
+            status = 1;
+        }
     }
+    status
 }
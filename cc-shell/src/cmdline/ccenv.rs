@@ -0,0 +1,274 @@
+use super::env::ShellEnv;
+use super::list;
+use crate::error::{os_message, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The name of a directory's environment file.
+pub const FILE_NAME: &str = ".ccenv";
+
+/// The environment file loaded for the working directory, with the
+/// values of the variables it changed from before it was loaded.
+pub struct Loaded {
+    pub file: PathBuf,
+    saved: Vec<(String, Option<String>)>,
+}
+
+/// The nearest environment file: in `dir` or the closest of its parents.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|file| file.is_file())
+}
+
+/// Loads the environment file of the working directory once the shell
+/// has moved, with `shopt -s ccenv`, unloading the previous one. Files
+/// are only run if trusted with `ccenv allow` as they are now.
+pub fn update(env: &mut ShellEnv) {
+    if !env.options.ccenv {
+        return;
+    }
+    let file = find(&env.cwd);
+    if env.ccenv.as_ref().map(|loaded| &loaded.file) == file.as_ref() {
+        return;
+    }
+    unload(env);
+    let Some(file) = file else {
+        return;
+    };
+    match trusted_source(&file, env) {
+        Ok(Some(source)) => load(file, &source, env),
+        Ok(None) => warn(
+            &file.to_string_lossy(),
+            "not trusted; run `ccenv allow' to load it",
+        ),
+        Err(e) => warn(&file.to_string_lossy(), os_message(&e)),
+    }
+}
+
+// Runs an environment file, remembering what the variables it changed
+// were before
+fn load(file: PathBuf, source: &[u8], env: &mut ShellEnv) {
+    let source = String::from_utf8_lossy(source);
+    let before: HashMap<String, String> = env
+        .names()
+        .into_iter()
+        .filter_map(|name| env.get(&name).map(|value| (name, value)))
+        .collect();
+    let status = env.last_status;
+    list::run_lines(&source, env);
+    env.last_status = status;
+
+    let mut saved = Vec::new();
+    for name in env.names() {
+        let old = before.get(&name);
+        if env.get(&name).as_ref() != old {
+            saved.push((name, old.cloned()));
+        }
+    }
+    for name in before.keys() {
+        if env.var(name).is_none() {
+            saved.push((name.clone(), before.get(name).cloned()));
+        }
+    }
+    env.ccenv = Some(Loaded { file, saved });
+}
+
+/// Restores the variables the loaded environment file changed.
+pub fn unload(env: &mut ShellEnv) {
+    let Some(loaded) = env.ccenv.take() else {
+        return;
+    };
+    for (name, value) in loaded.saved {
+        match value {
+            Some(value) => env.set(&name, &value),
+            None => env.unset(&name),
+        }
+    }
+}
+
+// The trust database: a line of `sha256sum` output for each file trusted
+// with `ccenv allow`, in ~/.ccshell_trusted
+fn database(env: &ShellEnv) -> Option<PathBuf> {
+    env.get("HOME")
+        .map(|home| Path::new(&home).join(".ccshell_trusted"))
+}
+
+fn read_database(env: &ShellEnv) -> Vec<(String, PathBuf)> {
+    let Some(contents) = database(env).and_then(|db| std::fs::read_to_string(db).ok()) else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| line.split_once("  "))
+        .map(|(hash, file)| (hash.to_string(), PathBuf::from(file)))
+        .collect()
+}
+
+/// Whether `file`, with its contents as they are now, has been trusted.
+pub fn is_trusted(file: &Path, env: &ShellEnv) -> std::io::Result<bool> {
+    Ok(trusted_source(file, env)?.is_some())
+}
+
+// The contents of `file` if they are as trusted. They are read once, so
+// that what runs is what was checked even if the file changes meanwhile.
+fn trusted_source(file: &Path, env: &ShellEnv) -> std::io::Result<Option<Vec<u8>>> {
+    let source = std::fs::read(file)?;
+    let hash = sha256_hex(&source);
+    let trusted = read_database(env)
+        .iter()
+        .any(|(trusted, path)| *trusted == hash && path == file);
+    Ok(trusted.then_some(source))
+}
+
+/// Trusts `file` as it is now, or with `trust` false forgets it.
+pub fn set_trusted(file: &Path, trust: bool, env: &ShellEnv) -> std::io::Result<()> {
+    let Some(db) = database(env) else {
+        return Err(std::io::Error::other("HOME not set"));
+    };
+    let mut entries = read_database(env);
+    entries.retain(|(_, path)| path != file);
+    if trust {
+        entries.push((sha256_hex(&std::fs::read(file)?), file.to_path_buf()));
+    }
+    let contents: String = entries
+        .iter()
+        .map(|(hash, path)| format!("{}  {}\n", hash, path.display()))
+        .collect();
+    std::fs::write(db, contents)
+}
+
+// SHA-256 (FIPS 180-4) of `data`, in hex as `sha256sum` prints it
+fn sha256_hex(data: &[u8]) -> String {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    // The message, a 1 bit, zeros and the length in bits fill whole blocks
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    h.iter().map(|word| format!("{:08x}", word)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_known_answers() {
+        // FIPS 180-4 examples, and lengths either side of a block boundary
+        let cases: [(&[u8], &str); 7] = [
+            (
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+            (
+                &[b'a'; 55],
+                "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+            ),
+            (
+                &[b'a'; 56],
+                "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a",
+            ),
+            (
+                &[b'a'; 64],
+                "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+            ),
+            (
+                &[b'a'; 1_000_000],
+                "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+            ),
+        ];
+        for (data, hash) in cases {
+            assert_eq!(sha256_hex(data), hash, "{} bytes", data.len());
+        }
+    }
+
+    #[test]
+    fn trust_follows_the_contents() {
+        let home = std::env::temp_dir().join(format!("ccshell-ccenv-{}", std::process::id()));
+        std::fs::create_dir_all(&home).unwrap();
+        let mut env = ShellEnv::new();
+        env.set("HOME", &home.to_string_lossy());
+        let file = home.join(FILE_NAME);
+        std::fs::write(&file, "GREETING=hello\n").unwrap();
+
+        assert_eq!(trusted_source(&file, &env).unwrap(), None);
+        set_trusted(&file, true, &env).unwrap();
+        assert_eq!(
+            trusted_source(&file, &env).unwrap().as_deref(),
+            Some(&b"GREETING=hello\n"[..])
+        );
+        std::fs::write(&file, "GREETING=changed\n").unwrap();
+        assert_eq!(trusted_source(&file, &env).unwrap(), None);
+
+        set_trusted(&file, true, &env).unwrap();
+        assert!(is_trusted(&file, &env).unwrap());
+        set_trusted(&file, false, &env).unwrap();
+        assert!(!is_trusted(&file, &env).unwrap());
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+use super::arith;
+use super::audit::AuditLog;
+use super::bindings::KeyBindings;
+use super::ccenv;
+use super::fds::FdTable;
+use super::jobs::Jobs;
+use super::options::ShellOptions;
+use super::resolve::{CommandHash, Policy};
+use super::resources::CpuTimes;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+// Default field separators used when IFS is unset
+pub const DEFAULT_IFS: &str = " \t\n";
+
+// Variables that decide what runs, which a restricted shell may not change
+const RESTRICTED_VARS: &[&str] = &["ENV", "PATH", "SHELL"];
+
+pub enum Value {
+    Scalar(String),
+    // Sparse indexed array: `a=(x y z)`, `a[3]=w`
+    Indexed(BTreeMap<usize, String>),
+    // Associative array, created with `declare -A`
+    Assoc(BTreeMap<String, String>),
+}
+
+pub struct Variable {
+    pub value: Value,
+    // Exported variables are passed to child processes
+    pub exported: bool,
+}
+
+/// ShellEnv holds the shell variables, options and the status of the last command.
+pub struct ShellEnv {
+    vars: HashMap<String, Variable>,
+    pub options: ShellOptions,
+    pub last_status: i32,
+    // CPU time of the children waited for with wait4, for `time`
+    pub child_times: CpuTimes,
+    // Descriptors opened for the shell itself with `exec`
+    pub fds: FdTable,
+    // Status of the last command substitution while expanding a command
+    pub subst_status: Option<i32>,
+    // Line editor bindings changed with `bind`
+    pub bindings: KeyBindings,
+    // Set by `exit`: no more commands are read
+    pub exit_requested: bool,
+    // Where commands run so far were found in $PATH, for `hash`
+    pub hash: CommandHash,
+    // The working directory. `cd` changes only this, never the process's
+    // own, and commands are started in it.
+    pub cwd: PathBuf,
+    // The external commands that may be run
+    pub policy: Policy,
+    // $1, $2, ...: the script's arguments, or as set with `set --`
+    pub positional: Vec<String>,
+    // Where getopts is: the $OPTIND it last set and the position in a
+    // group of options like `-abc` at that index
+    pub getopts: (usize, usize),
+    // $0: the script's name, or the shell's
+    pub arg0: String,
+    // $$: the shell's process ID, which subshells keep
+    pub shell_pid: libc::pid_t,
+    // $!: the process of the last background job
+    pub last_background: Option<libc::pid_t>,
+    // $_: the last argument of the previous command
+    pub last_arg: String,
+    // Background jobs not yet waited for
+    pub jobs: Jobs,
+    // The directory environment file loaded, with `shopt -s ccenv`
+    pub ccenv: Option<ccenv::Loaded>,
+    // Set while a hook such as `chpwd` runs, so that hooks don't nest
+    pub in_hook: bool,
+    // Where each command run is recorded, if anywhere
+    pub audit: Option<AuditLog>,
+    // How many `select` loops are running, and how many of them `break`
+    // is leaving
+    pub loops: usize,
+    pub breaking: usize,
+    // Running inside another program through the library, which `exec`
+    // must not replace
+    pub embedded: bool,
+}
+
+impl ShellEnv {
+    pub fn new() -> Self {
+        let mut vars = HashMap::new();
+        for (name, value) in std::env::vars() {
+            vars.insert(
+                name,
+                Variable {
+                    value: Value::Scalar(value),
+                    exported: true,
+                },
+            );
+        }
+        let mut env = ShellEnv {
+            vars,
+            options: ShellOptions::default(),
+            last_status: 0,
+            child_times: CpuTimes::default(),
+            fds: FdTable::default(),
+            subst_status: None,
+            bindings: KeyBindings::default(),
+            exit_requested: false,
+            hash: CommandHash::default(),
+            cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            policy: Policy::default(),
+            positional: Vec::new(),
+            getopts: (1, 0),
+            arg0: "cc-shell".to_string(),
+            shell_pid: std::process::id() as libc::pid_t,
+            last_background: None,
+            last_arg: String::new(),
+            jobs: Jobs::default(),
+            ccenv: None,
+            in_hook: false,
+            audit: None,
+            loops: 0,
+            breaking: 0,
+            embedded: false,
+        };
+        if env.get("IFS").is_none() {
+            env.set("IFS", DEFAULT_IFS);
+        }
+        env
+    }
+
+    /// Resolves a path relative to the working directory.
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.cwd.join(path)
+    }
+
+    /// Returns the scalar value of a variable, or element 0 of an array.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => return Some(self.last_status.to_string()),
+            "#" => return Some(self.positional.len().to_string()),
+            "@" | "*" => return Some(self.positional.join(" ")),
+            "0" => return Some(self.arg0.clone()),
+            "$" => return Some(self.shell_pid.to_string()),
+            "!" => return self.last_background.map(|pid| pid.to_string()),
+            "-" => return Some(self.options.flags()),
+            "_" => return Some(self.last_arg.clone()),
+            _ => {}
+        }
+        if let Ok(n) = name.parse::<usize>() {
+            return n
+                .checked_sub(1)
+                .and_then(|i| self.positional.get(i))
+                .cloned();
+        }
+        self.vars.get(name).and_then(|var| match &var.value {
+            Value::Scalar(s) => Some(s.clone()),
+            Value::Indexed(items) => items.get(&0).cloned(),
+            Value::Assoc(items) => items.get("0").cloned(),
+        })
+    }
+
+    pub fn var(&self, name: &str) -> Option<&Variable> {
+        self.vars.get(name)
+    }
+
+    pub fn is_assoc(&self, name: &str) -> bool {
+        matches!(
+            self.vars.get(name),
+            Some(Variable {
+                value: Value::Assoc(_),
+                ..
+            })
+        )
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.vars.get_mut(name) {
+            // Assigning to an array name assigns element 0
+            Some(Variable {
+                value: Value::Indexed(items),
+                ..
+            }) => {
+                items.insert(0, value.to_string());
+            }
+            Some(Variable {
+                value: Value::Assoc(items),
+                ..
+            }) => {
+                items.insert("0".to_string(), value.to_string());
+            }
+            Some(var) => var.value = Value::Scalar(value.to_string()),
+            None => self.insert(name, Value::Scalar(value.to_string())),
+        }
+    }
+
+    pub fn set_indexed(&mut self, name: &str, items: Vec<String>) {
+        self.set_value(
+            name,
+            Value::Indexed(items.into_iter().enumerate().collect()),
+        );
+    }
+
+    /// Replaces the value of a variable, keeping its attributes.
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        match self.vars.get_mut(name) {
+            Some(var) => var.value = value,
+            None => self.insert(name, value),
+        }
+    }
+
+    fn insert(&mut self, name: &str, value: Value) {
+        self.vars.insert(
+            name.to_string(),
+            Variable {
+                value,
+                exported: false,
+            },
+        );
+    }
+
+    /// `declare -a`/`declare -A`: converts the variable to an array,
+    /// keeping a scalar value as element 0.
+    pub fn declare_array(&mut self, name: &str, assoc: bool) -> Result<(), String> {
+        self.check_writable(name)?;
+        let Some(var) = self.vars.get_mut(name) else {
+            let value = if assoc {
+                Value::Assoc(BTreeMap::new())
+            } else {
+                Value::Indexed(BTreeMap::new())
+            };
+            self.insert(name, value);
+            return Ok(());
+        };
+        match (&mut var.value, assoc) {
+            (Value::Scalar(s), true) => {
+                var.value = Value::Assoc(BTreeMap::from([("0".to_string(), std::mem::take(s))]))
+            }
+            (Value::Scalar(s), false) => {
+                var.value = Value::Indexed(BTreeMap::from([(0, std::mem::take(s))]))
+            }
+            (Value::Indexed(_), true) => {
+                return Err(format!(
+                    "{}: cannot convert indexed to associative array",
+                    name
+                ))
+            }
+            (Value::Assoc(_), false) => {
+                return Err(format!(
+                    "{}: cannot convert associative to indexed array",
+                    name
+                ))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Looks up `name[subscript]`. The subscript is already expanded.
+    pub fn get_element(&self, name: &str, subscript: &str) -> Result<Option<String>, String> {
+        match self.vars.get(name).map(|v| &v.value) {
+            None => Ok(None),
+            Some(Value::Assoc(items)) => Ok(items.get(subscript).cloned()),
+            Some(Value::Indexed(items)) => {
+                let index = self.resolve_index(subscript, items)?;
+                Ok(index.and_then(|i| items.get(&i).cloned()))
+            }
+            Some(Value::Scalar(s)) => {
+                let index = self.eval_index(subscript)?;
+                Ok((index == 0 || index == -1).then(|| s.clone()))
+            }
+        }
+    }
+
+    /// Assigns `name[subscript]=value`, creating an indexed array if needed.
+    pub fn set_element(&mut self, name: &str, subscript: &str, value: &str) -> Result<(), String> {
+        if self.is_assoc(name) {
+            if let Some(Value::Assoc(items)) = self.vars.get_mut(name).map(|v| &mut v.value) {
+                items.insert(subscript.to_string(), value.to_string());
+            }
+            return Ok(());
+        }
+        let index = match self.vars.get(name).map(|v| &v.value) {
+            Some(Value::Indexed(items)) => self.resolve_index(subscript, items)?,
+            _ => usize::try_from(self.eval_index(subscript)?).ok(),
+        }
+        .ok_or_else(|| format!("{}[{}]: bad array subscript", name, subscript))?;
+        self.declare_array(name, false)?;
+        if let Some(Value::Indexed(items)) = self.vars.get_mut(name).map(|v| &mut v.value) {
+            items.insert(index, value.to_string());
+        }
+        Ok(())
+    }
+
+    /// Removes a single element, as for `unset a[i]`.
+    pub fn unset_element(&mut self, name: &str, subscript: &str) -> Result<(), String> {
+        let index = match self.vars.get(name).map(|v| &v.value) {
+            Some(Value::Indexed(items)) => self.resolve_index(subscript, items)?,
+            Some(Value::Scalar(_)) => usize::try_from(self.eval_index(subscript)?).ok(),
+            _ => None,
+        };
+        match self.vars.get_mut(name).map(|v| &mut v.value) {
+            Some(Value::Assoc(items)) => {
+                items.remove(subscript);
+            }
+            Some(Value::Indexed(items)) => {
+                if let Some(i) = index {
+                    items.remove(&i);
+                }
+            }
+            Some(Value::Scalar(_)) if index == Some(0) => {
+                self.vars.remove(name);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// All values of a variable: array elements in order, or the scalar.
+    pub fn values(&self, name: &str) -> Vec<String> {
+        match self.vars.get(name).map(|v| &v.value) {
+            None => Vec::new(),
+            Some(Value::Scalar(s)) => vec![s.clone()],
+            Some(Value::Indexed(items)) => items.values().cloned().collect(),
+            Some(Value::Assoc(items)) => items.values().cloned().collect(),
+        }
+    }
+
+    /// The indices or keys of a variable, as for `${!a[@]}`.
+    pub fn keys(&self, name: &str) -> Vec<String> {
+        match self.vars.get(name).map(|v| &v.value) {
+            None => Vec::new(),
+            Some(Value::Scalar(_)) => vec!["0".to_string()],
+            Some(Value::Indexed(items)) => items.keys().map(|k| k.to_string()).collect(),
+            Some(Value::Assoc(items)) => items.keys().cloned().collect(),
+        }
+    }
+
+    // Negative indices count back from the end of the array
+    fn resolve_index(
+        &self,
+        subscript: &str,
+        items: &BTreeMap<usize, String>,
+    ) -> Result<Option<usize>, String> {
+        let index = self.eval_index(subscript)?;
+        if index >= 0 {
+            return Ok(Some(index as usize));
+        }
+        let len = items.keys().next_back().map_or(0, |k| k + 1) as i64;
+        Ok(usize::try_from(len + index).ok())
+    }
+
+    /// Evaluates an indexed-array subscript as an arithmetic expression.
+    pub fn eval_index(&self, subscript: &str) -> Result<i64, String> {
+        arith::eval_readonly(subscript, self)
+    }
+
+    /// Checks that a variable may be assigned or unset: a restricted shell
+    /// keeps those that decide what runs.
+    pub fn check_writable(&self, name: &str) -> Result<(), String> {
+        if self.options.restricted && RESTRICTED_VARS.contains(&name) {
+            return Err(format!("{}: readonly variable", name));
+        }
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.vars.remove(name);
+    }
+
+    /// Removes a variable and returns it so it can be restored later,
+    /// used for temporary `NAME=value cmd` assignments.
+    pub fn take(&mut self, name: &str) -> Option<Variable> {
+        self.vars.remove(name)
+    }
+
+    pub fn restore(&mut self, name: &str, var: Option<Variable>) {
+        match var {
+            Some(var) => {
+                self.vars.insert(name.to_string(), var);
+            }
+            None => {
+                self.vars.remove(name);
+            }
+        }
+    }
+
+    /// Marks a variable for export, creating it if needed.
+    pub fn export(&mut self, name: &str) {
+        match self.vars.get_mut(name) {
+            Some(var) => var.exported = true,
+            None => {
+                self.vars.insert(
+                    name.to_string(),
+                    Variable {
+                        value: Value::Scalar(String::new()),
+                        exported: true,
+                    },
+                );
+            }
+        }
+    }
+
+    /// The field separators currently in effect.
+    pub fn ifs(&self) -> String {
+        self.get("IFS").unwrap_or_else(|| DEFAULT_IFS.to_string())
+    }
+
+    /// Exported scalar variables, as passed to child processes.
+    pub fn exported(&self) -> Vec<(String, String)> {
+        self.vars
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .filter_map(|(name, var)| match &var.value {
+                Value::Scalar(s) => Some((name.clone(), s.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All variable names, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.vars.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for ShellEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
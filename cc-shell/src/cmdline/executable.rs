@@ -1,20 +1,60 @@
 pub enum Executable {
+    Bind,
+    Break,
+    Builtin,
     Cd,
+    Ccenv,
+    Command,
+    Declare,
     Echo,
+    Eval,
+    Exec,
     Exit,
+    Getopts,
+    Hash,
     Pwd,
+    Read,
+    Set,
+    Shift,
+    Shopt,
+    Source,
+    Times,
     Type,
+    Ulimit,
+    Umask,
+    Unset,
+    Wait,
     Unknown(String),
 }
 
 impl From<String> for Executable {
     fn from(s: String) -> Self {
         match s.as_str() {
+            "bind" => Executable::Bind,
+            "break" => Executable::Break,
+            "builtin" => Executable::Builtin,
             "cd" => Executable::Cd,
+            "ccenv" => Executable::Ccenv,
+            "command" => Executable::Command,
+            "declare" => Executable::Declare,
             "echo" => Executable::Echo,
+            "eval" => Executable::Eval,
+            "exec" => Executable::Exec,
             "exit" => Executable::Exit,
+            "getopts" => Executable::Getopts,
+            "hash" => Executable::Hash,
             "pwd" => Executable::Pwd,
+            "read" => Executable::Read,
+            "set" => Executable::Set,
+            "shift" => Executable::Shift,
+            "shopt" => Executable::Shopt,
+            "source" | "." => Executable::Source,
+            "times" => Executable::Times,
             "type" => Executable::Type,
+            "ulimit" => Executable::Ulimit,
+            "umask" => Executable::Umask,
+            "unset" => Executable::Unset,
+            "wait" => Executable::Wait,
             other => Executable::Unknown(other.to_string()),
         }
     }
@@ -22,10 +62,7 @@ impl From<String> for Executable {
 
 impl Executable {
     pub fn is_builtin(s: &str) -> bool {
-        match Executable::from(s.to_string()) {
-            Executable::Unknown(_) => false,
-            _ => true,
-        }
+        !matches!(Executable::from(s.to_string()), Executable::Unknown(_))
     }
     pub fn get_builtin_str() -> Vec<String> {
         vec![
@@ -34,6 +71,27 @@ impl Executable {
             "type".to_string(),
             "exit".to_string(),
             "echo".to_string(),
+            "exec".to_string(),
+            "read".to_string(),
+            "declare".to_string(),
+            "unset".to_string(),
+            "set".to_string(),
+            "times".to_string(),
+            "umask".to_string(),
+            "ulimit".to_string(),
+            "bind".to_string(),
+            "builtin".to_string(),
+            "command".to_string(),
+            "hash".to_string(),
+            "shopt".to_string(),
+            "getopts".to_string(),
+            "shift".to_string(),
+            "eval".to_string(),
+            "source".to_string(),
+            ".".to_string(),
+            "wait".to_string(),
+            "ccenv".to_string(),
+            "break".to_string(),
         ]
     }
 }
@@ -0,0 +1,964 @@
+use super::env::{is_valid_name, ShellEnv, Value};
+use super::{arith, brace, glob, subst};
+use crate::error::ShellError;
+use std::collections::BTreeMap;
+
+/// A shell word as written on the command line, before expansion.
+#[derive(Clone, Debug, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+#[derive(Clone, Debug)]
+pub enum WordPart {
+    // Unquoted text
+    Literal(String),
+    // Text from '...', "..." or a backslash escape
+    Quoted(String),
+    // $name or ${...}; `quoted` is true inside double quotes
+    Param { name: String, quoted: bool },
+    // Elements of a compound array assignment: `a=(x y [k]=v)`
+    Array(Vec<Word>),
+    // Process substitution: `<(cmd)`, or `>(cmd)` when `output` is true
+    ProcSubst { command: String, output: bool },
+    // Command substitution: $(cmd) or `cmd`
+    CommandSubst { command: String, quoted: bool },
+    // Arithmetic expansion: $((expr))
+    Arith { expr: String, quoted: bool },
+    // An unquoted redirection operator such as `2>>`, a word of its own
+    Operator(String),
+}
+
+/// `name=value`, `name+=value` or `name[subscript]=value`.
+pub struct Assignment {
+    pub name: String,
+    pub subscript: Option<Word>,
+    pub append: bool,
+    pub value: Word,
+}
+
+/// A character produced by expansion.
+#[derive(Clone, Copy)]
+pub struct ExpChar {
+    pub c: char,
+    pub quoted: bool,
+    // Only unquoted results of expansions are subject to field splitting
+    pub from_expansion: bool,
+}
+
+impl ExpChar {
+    fn splittable(&self, ifs: &str) -> bool {
+        self.from_expansion && !self.quoted && ifs.contains(self.c)
+    }
+
+    fn is_ifs_whitespace(&self, ifs: &str) -> bool {
+        self.splittable(ifs) && matches!(self.c, ' ' | '\t' | '\n')
+    }
+}
+
+impl Word {
+    /// The unquoted literal text of the word, if it has no quotes or expansions.
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(s)] => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The redirection operator this word is, if it is one.
+    pub fn as_operator(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Operator(op)] => Some(op),
+            _ => None,
+        }
+    }
+
+    /// Parses an assignment word, if this is one.
+    pub fn as_assignment(&self) -> Option<Assignment> {
+        let WordPart::Literal(first) = self.parts.first()? else {
+            return None;
+        };
+        let name_len = first
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(first.len());
+        let name = &first[..name_len];
+        if !is_valid_name(name) {
+            return None;
+        }
+        let (subscript, part, offset) = if first[name_len..].starts_with('[') {
+            let (sub, part, offset) = self.split_bracket(0, name_len + 1)?;
+            (Some(sub), part, offset)
+        } else {
+            (None, 0, name_len)
+        };
+        let (append, value) = self.split_value(part, offset)?;
+        Some(Assignment {
+            name: name.to_string(),
+            subscript,
+            append,
+            value,
+        })
+    }
+
+    /// Parses a `[key]=value` element of a compound array assignment.
+    fn as_keyed_element(&self) -> Option<(Word, Word)> {
+        match self.parts.first()? {
+            WordPart::Literal(first) if first.starts_with('[') => {}
+            _ => return None,
+        }
+        let (key, part, offset) = self.split_bracket(0, 1)?;
+        let (_, value) = self.split_value(part, offset)?;
+        Some((key, value))
+    }
+
+    // Collects the subscript text up to the closing `]`, which may span
+    // several parts as in `a[$i]`. Returns the subscript and the position
+    // just past the bracket.
+    fn split_bracket(&self, mut part: usize, mut offset: usize) -> Option<(Word, usize, usize)> {
+        let mut sub = Word::default();
+        loop {
+            match self.parts.get(part)? {
+                WordPart::Literal(s) => {
+                    if let Some(end) = s[offset..].find(']') {
+                        if end > 0 {
+                            sub.parts
+                                .push(WordPart::Literal(s[offset..offset + end].to_string()));
+                        }
+                        return Some((sub, part, offset + end + 1));
+                    }
+                    if offset < s.len() {
+                        sub.parts.push(WordPart::Literal(s[offset..].to_string()));
+                    }
+                }
+                other => sub.parts.push(other.clone()),
+            }
+            part += 1;
+            offset = 0;
+        }
+    }
+
+    // Splits off `=value` or `+=value` starting at the given position.
+    fn split_value(&self, part: usize, offset: usize) -> Option<(bool, Word)> {
+        let WordPart::Literal(s) = self.parts.get(part)? else {
+            return None;
+        };
+        let rest = &s[offset..];
+        let (append, rest) = match rest.strip_prefix("+=") {
+            Some(rest) => (true, rest),
+            None => (false, rest.strip_prefix('=')?),
+        };
+        let mut parts = Vec::new();
+        if !rest.is_empty() {
+            parts.push(WordPart::Literal(rest.to_string()));
+        }
+        parts.extend(self.parts[part + 1..].iter().cloned());
+        Some((append, Word { parts }))
+    }
+}
+
+/// Parses the parameter following a `$`: `name`, `{...}` or a single
+/// special character. Returns None for a lone `$`.
+pub fn parse_param(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    match chars.peek().copied()? {
+        '{' => {
+            chars.next();
+            let mut name = String::new();
+            let mut depth = 0;
+            for c in chars.by_ref() {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 0 => break,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                name.push(c);
+            }
+            Some(name)
+        }
+        c @ ('?' | '$' | '!' | '#' | '@' | '*' | '-') => {
+            chars.next();
+            Some(c.to_string())
+        }
+        c if c.is_ascii_digit() => {
+            chars.next();
+            Some(c.to_string())
+        }
+        c if c == '_' || c.is_ascii_alphabetic() => {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if c != '_' && !c.is_ascii_alphanumeric() {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+// The result of expanding a parameter
+enum Expansion {
+    Str(String),
+    // ${a[@]} and friends produce one field per element
+    List(Vec<String>),
+}
+
+// Splits `name[subscript]` into its name and subscript.
+fn split_subscript(expr: &str) -> Option<(&str, &str)> {
+    let inner = expr.strip_suffix(']')?;
+    let (name, sub) = inner.split_once('[')?;
+    is_valid_name(name).then_some((name, sub))
+}
+
+// The length of the parameter at the start of `expr`: a name, perhaps
+// with a subscript, a number or a special parameter
+fn param_len(expr: &str) -> Option<usize> {
+    let first = expr.chars().next()?;
+    if first == '_' || first.is_ascii_alphabetic() {
+        let end = expr
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(expr.len());
+        return match expr[end..].starts_with('[') {
+            true => Some(end + expr[end..].find(']')? + 1),
+            false => Some(end),
+        };
+    }
+    if first.is_ascii_digit() {
+        return Some(
+            expr.find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(expr.len()),
+        );
+    }
+    matches!(first, '?' | '$' | '!' | '#' | '@' | '*' | '-').then_some(1)
+}
+
+// Whether `${expr}` is one of the forms lookup knows, operators aside
+fn is_param(expr: &str) -> bool {
+    if let Some(rest) = expr.strip_prefix('!').filter(|rest| !rest.is_empty()) {
+        return matches!(split_subscript(rest), Some((_, "@" | "*")));
+    }
+    let expr = match expr.strip_prefix('#') {
+        Some(rest) if !rest.is_empty() => rest,
+        _ => expr,
+    };
+    param_len(expr) == Some(expr.len())
+}
+
+// Splits `name:-word` and the like into the parameter, the operator and
+// the word. Without the colon, only an unset parameter counts as missing.
+fn split_operator(expr: &str) -> Option<(&str, &str, &str)> {
+    let (param, rest) = expr.split_at(param_len(expr)?);
+    [":-", ":=", ":+", ":?", "-", "=", "+", "?"]
+        .into_iter()
+        .find_map(|op| Some((param, op, rest.strip_prefix(op)?)))
+}
+
+// Whether the word of `${param<op>word}` is used, rather than the value
+fn uses_word(param: &str, op: &str, env: &ShellEnv) -> bool {
+    let set = match split_subscript(param) {
+        Some((name, "@" | "*")) => !env.values(name).is_empty(),
+        Some((name, sub)) => env
+            .get_element(name, &expand_inline(sub, env))
+            .is_ok_and(|value| value.is_some()),
+        None if matches!(param, "@" | "*") => !env.positional.is_empty(),
+        None => env.get(param).is_some(),
+    };
+    let empty = match lookup(param, false, env) {
+        Expansion::Str(value) => value.is_empty(),
+        Expansion::List(items) => items.is_empty(),
+    };
+    let missing = !set || op.starts_with(':') && empty;
+    missing != op.ends_with('+')
+}
+
+fn lookup(expr: &str, quoted: bool, env: &ShellEnv) -> Expansion {
+    // ${name:-word} and friends, where nothing may be assigned or run, as
+    // in subscripts: the word is expanded inline
+    if let Some((param, op, word)) = split_operator(expr) {
+        return match (uses_word(param, op, env), op.ends_with('+')) {
+            (true, _) => Expansion::Str(expand_inline(word, env)),
+            (false, true) => Expansion::Str(String::new()),
+            (false, false) => lookup(param, quoted, env),
+        };
+    }
+    // ${#name}, ${#a[@]}, ${#a[i]}
+    if let Some(rest) = expr.strip_prefix('#').filter(|r| !r.is_empty()) {
+        let len = match split_subscript(rest) {
+            Some((name, "@" | "*")) => env.values(name).len(),
+            Some((name, sub)) => element(name, sub, env).chars().count(),
+            None => env.get(rest).unwrap_or_default().chars().count(),
+        };
+        return Expansion::Str(len.to_string());
+    }
+    // ${!a[@]}
+    if let Some(rest) = expr.strip_prefix('!') {
+        if let Some((name, sub @ ("@" | "*"))) = split_subscript(rest) {
+            return join_list(env.keys(name), sub == "*", quoted, env);
+        }
+    }
+    if let "@" | "*" = expr {
+        return join_list(env.positional.clone(), expr == "*", quoted, env);
+    }
+    match split_subscript(expr) {
+        Some((name, sub @ ("@" | "*"))) => join_list(env.values(name), sub == "*", quoted, env),
+        Some((name, sub)) => Expansion::Str(element(name, sub, env)),
+        None => Expansion::Str(env.get(expr).unwrap_or_default()),
+    }
+}
+
+// "${a[*]}" joins the elements with the first character of IFS
+fn join_list(items: Vec<String>, star: bool, quoted: bool, env: &ShellEnv) -> Expansion {
+    if star && quoted {
+        let sep = env
+            .ifs()
+            .chars()
+            .next()
+            .map(String::from)
+            .unwrap_or_default();
+        Expansion::Str(items.join(&sep))
+    } else {
+        Expansion::List(items)
+    }
+}
+
+fn element(name: &str, sub: &str, env: &ShellEnv) -> String {
+    let key = expand_inline(sub, env);
+    match env.get_element(name, &key) {
+        Ok(value) => value.unwrap_or_default(),
+        Err(e) => {
+            ShellError::Expansion(e).report();
+            String::new()
+        }
+    }
+}
+
+/// Expands `$name` and `${...}` inside a string, as in array subscripts.
+pub fn expand_inline(text: &str, env: &ShellEnv) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        match parse_param(&mut chars) {
+            Some(expr) => match lookup(&expr, true, env) {
+                Expansion::Str(s) => out.push_str(&s),
+                Expansion::List(items) => out.push_str(&items.join(" ")),
+            },
+            None => out.push('$'),
+        }
+    }
+    out
+}
+
+// Expands parameters, command substitutions and nested arithmetic in the
+// text of $((...)) before it is evaluated
+fn expand_arith_text(text: &str, env: &mut ShellEnv) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '`' {
+            let command = super::take_until_backquote(&mut chars);
+            out.push_str(&subst::command_output(&command, env)?);
+            continue;
+        }
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'(').is_some() {
+            if chars.next_if_eq(&'(').is_some() {
+                let inner = super::take_until_close_paren(&mut chars);
+                chars.next_if_eq(&')');
+                let inner = expand_arith_text(&inner, env)?;
+                out.push_str(&arith::eval(&inner, env)?.to_string());
+            } else {
+                let command = super::take_until_close_paren(&mut chars);
+                out.push_str(&subst::command_output(&command, env)?);
+            }
+            continue;
+        }
+        match parse_param(&mut chars) {
+            Some(expr) => match lookup(&expr, true, env) {
+                Expansion::Str(s) => out.push_str(&s),
+                Expansion::List(items) => out.push_str(&items.join(" ")),
+            },
+            None => out.push('$'),
+        }
+    }
+    Ok(out)
+}
+
+// A field being assembled; `keep` marks fields that survive even when
+// empty, like "" or "$empty"
+#[derive(Default)]
+struct Field {
+    chars: Vec<ExpChar>,
+    keep: bool,
+}
+
+impl Field {
+    fn push(&mut self, text: &str, quoted: bool, from_expansion: bool) {
+        self.chars.extend(text.chars().map(|c| ExpChar {
+            c,
+            quoted,
+            from_expansion,
+        }));
+        self.keep |= quoted;
+    }
+}
+
+// Tilde, parameter, command and arithmetic expansion, left to right. In
+// assignment values a tilde is also expanded after each unquoted `:`.
+fn expand_to_fields(
+    word: &Word,
+    env: &mut ShellEnv,
+    assignment: bool,
+) -> Result<Vec<Field>, String> {
+    let mut fields = vec![Field::default()];
+    for (i, part) in word.parts.iter().enumerate() {
+        let current = fields.last_mut().expect("at least one field");
+        match part {
+            WordPart::Literal(s) => {
+                // The tilde prefix runs up to the first slash, so it must
+                // not continue into a following quoted or expanded part
+                let whole = i + 1 == word.parts.len();
+                let mut segments = if assignment {
+                    s.split_inclusive(':').collect()
+                } else {
+                    vec![s.as_str()]
+                };
+                if i > 0 {
+                    current.push(segments.remove(0), false, false);
+                }
+                for segment in segments {
+                    match expand_tilde(segment, whole, assignment, env) {
+                        Some((home, rest)) => {
+                            current.push(&home, true, true);
+                            current.push(rest, false, false);
+                        }
+                        None => current.push(segment, false, false),
+                    }
+                }
+            }
+            WordPart::Quoted(s) => current.push(s, true, false),
+            WordPart::Param { name, quoted } => expand_param(name, *quoted, env, &mut fields)?,
+            WordPart::CommandSubst { command, quoted } => {
+                if env.options.noexec {
+                    // Not run under `set -n` or --dry-run; shown as written
+                    current.push(&format!("$({})", command), true, false);
+                } else {
+                    let output = subst::command_output(command, env)?;
+                    let current = fields.last_mut().expect("at least one field");
+                    current.push(&output, *quoted, true);
+                }
+            }
+            WordPart::Arith { expr, quoted } => {
+                let expr = expand_arith_text(expr, env)?;
+                let value = arith::eval(&expr, env)?;
+                let current = fields.last_mut().expect("at least one field");
+                current.push(&value.to_string(), *quoted, true);
+            }
+            WordPart::Array(elements) => {
+                let mut items = Vec::new();
+                for element in elements {
+                    items.push(expand_word_string(element, env)?);
+                }
+                let current = fields.last_mut().expect("at least one field");
+                current.push(&format!("({})", items.join(" ")), false, false);
+            }
+            // Operators are taken out before expansion, except in words
+            // that aren't commands, such as those of `select`
+            WordPart::Operator(op) => current.push(op, true, false),
+            // Substitutions are started before expansion and replaced by
+            // their paths; one that was not started shows as written
+            WordPart::ProcSubst { command, output } => {
+                let op = if *output { '>' } else { '<' };
+                current.push(&format!("{}({})", op, command), true, false);
+            }
+        }
+    }
+    Ok(fields)
+}
+
+// Expands `$name` or `${expr}` onto the end of `fields`
+fn expand_param(
+    expr: &str,
+    quoted: bool,
+    env: &mut ShellEnv,
+    fields: &mut Vec<Field>,
+) -> Result<(), String> {
+    let Some((param, op, word)) = split_operator(expr) else {
+        if !is_param(expr) {
+            return Err(format!("${{{}}}: bad substitution", expr));
+        }
+        push_expansion(lookup(expr, quoted, env), quoted, fields);
+        return Ok(());
+    };
+    if !uses_word(param, op, env) {
+        let value = match op.ends_with('+') {
+            true => Expansion::Str(String::new()),
+            false => lookup(param, quoted, env),
+        };
+        push_expansion(value, quoted, fields);
+        return Ok(());
+    }
+    match op.trim_start_matches(':') {
+        "=" => {
+            let value = expand_word_string(&super::Cmd::parse_word(word), env)?;
+            match split_subscript(param) {
+                Some((name, sub)) => {
+                    env.check_writable(name)?;
+                    let key = expand_inline(sub, env);
+                    env.set_element(name, &key, &value)?;
+                }
+                None if is_valid_name(param) => {
+                    env.check_writable(param)?;
+                    env.set(param, &value);
+                }
+                None => return Err(format!("${}: cannot assign in this way", param)),
+            }
+            push_expansion(Expansion::Str(value), quoted, fields);
+        }
+        "?" => {
+            let message = match word {
+                "" => "parameter null or not set".to_string(),
+                word => expand_word_string(&super::Cmd::parse_word(word), env)?,
+            };
+            // As in other shells, a script stops there
+            if !env.options.interactive {
+                env.exit_requested = true;
+            }
+            return Err(format!("{}: {}", param, message));
+        }
+        // `-` and `+`: the word's fields, which are split if unquoted
+        _ => {
+            let mut word = super::Cmd::parse_word(word);
+            if quoted {
+                // Inside double quotes, so no tilde expansion either
+                for part in &mut word.parts {
+                    if let WordPart::Literal(text) = part {
+                        *part = WordPart::Quoted(std::mem::take(text));
+                    }
+                }
+            }
+            for (i, field) in expand_to_fields(&word, env, false)?.into_iter().enumerate() {
+                if i > 0 {
+                    fields.push(Field::default());
+                }
+                let current = fields.last_mut().expect("at least one field");
+                current
+                    .chars
+                    .extend(field.chars.into_iter().map(|ec| ExpChar {
+                        quoted: ec.quoted || quoted,
+                        from_expansion: true,
+                        ..ec
+                    }));
+                current.keep |= field.keep || quoted;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn push_expansion(expansion: Expansion, quoted: bool, fields: &mut Vec<Field>) {
+    match expansion {
+        Expansion::Str(value) => {
+            let current = fields.last_mut().expect("at least one field");
+            current.push(&value, quoted, true);
+        }
+        Expansion::List(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    fields.push(Field::default());
+                }
+                let current = fields.last_mut().expect("at least one field");
+                current.push(item, quoted, true);
+            }
+        }
+    }
+}
+
+// Expands a leading `~`, `~user`, `~+` or `~-` up to the first slash (or
+// colon, in assignments). Returns the directory and the rest of the text.
+fn expand_tilde<'a>(
+    text: &'a str,
+    whole: bool,
+    assignment: bool,
+    env: &ShellEnv,
+) -> Option<(String, &'a str)> {
+    let rest = text.strip_prefix('~')?;
+    let end = if assignment {
+        rest.find(['/', ':'])
+    } else {
+        rest.find('/')
+    };
+    let end = end.or(whole.then_some(rest.len()))?;
+    let dir = match &rest[..end] {
+        "" => env.get("HOME").or_else(|| home_dir(None))?,
+        "+" => env.get("PWD")?,
+        "-" => env.get("OLDPWD")?,
+        user => home_dir(Some(user))?,
+    };
+    Some((dir, &rest[end..]))
+}
+
+// The home directory of a user from the password database, or of the
+// current user
+fn home_dir(user: Option<&str>) -> Option<String> {
+    let entry = match user {
+        Some(user) => {
+            let name = std::ffi::CString::new(user).ok()?;
+            unsafe { libc::getpwnam(name.as_ptr()) }
+        }
+        None => unsafe { libc::getpwuid(libc::getuid()) },
+    };
+    if entry.is_null() {
+        return None;
+    }
+    let dir = unsafe { std::ffi::CStr::from_ptr((*entry).pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+/// Expands a word into fields. The phases run in order: brace expansion;
+/// tilde, parameter, command and arithmetic expansion; field splitting on
+/// `$IFS`; pathname expansion; and quote removal.
+pub fn expand_word(word: &Word, env: &mut ShellEnv) -> Result<Vec<String>, String> {
+    let mut result = Vec::new();
+    for word in brace::expand(word) {
+        for field in expand_to_fields(&word, env, false)? {
+            let ifs = env.ifs();
+            let split = split_chars(&field.chars, &ifs, 0);
+            if split.is_empty() && field.keep {
+                // "" and '' still produce an (empty) argument
+                result.push(String::new());
+            }
+            for chars in split {
+                match glob::expand(chars, &env.cwd).filter(|_| !env.options.noglob) {
+                    Some(paths) => result.extend(paths),
+                    None => result.push(chars.iter().map(|ec| ec.c).collect()),
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+pub fn expand_words(words: &[Word], env: &mut ShellEnv) -> Result<Vec<String>, String> {
+    let mut result = Vec::new();
+    for word in words {
+        result.extend(expand_word(word, env)?);
+    }
+    Ok(result)
+}
+
+/// Expands a word without field splitting or pathname expansion, as for
+/// assignment values.
+pub fn expand_word_string(word: &Word, env: &mut ShellEnv) -> Result<String, String> {
+    let fields: Vec<String> = expand_to_fields(word, env, false)?
+        .iter()
+        .map(|f| f.chars.iter().map(|ec| ec.c).collect())
+        .collect();
+    Ok(fields.join(" "))
+}
+
+// Like expand_word_string, with tilde expansion after each `:` as well
+fn expand_assignment_value(word: &Word, env: &mut ShellEnv) -> Result<String, String> {
+    let fields: Vec<String> = expand_to_fields(word, env, true)?
+        .iter()
+        .map(|f| f.chars.iter().map(|ec| ec.c).collect())
+        .collect();
+    Ok(fields.join(" "))
+}
+
+/// Performs an assignment, including `a=(...)`, `a+=(...)` and `a[i]=v`.
+/// Returns the assignment as performed, for `set -x`.
+pub fn assign(assignment: &Assignment, env: &mut ShellEnv) -> Result<String, String> {
+    expand_assignment(assignment, env)?.perform(env)
+}
+
+/// An assignment with its subscript and value expanded, so that `declare`
+/// can perform it after expanding all its arguments.
+#[derive(Clone)]
+pub struct ExpandedAssignment {
+    pub name: String,
+    subscript: Option<String>,
+    append: bool,
+    value: AssignedValue,
+}
+
+#[derive(Clone)]
+enum AssignedValue {
+    Scalar(String),
+    Array(Vec<Element>),
+}
+
+// An element of a compound array assignment
+#[derive(Clone)]
+enum Element {
+    // `[key]=value`
+    Keyed(String, String),
+    // The fields of any other word
+    Fields(Vec<String>),
+}
+
+/// Expands the subscript and value of an assignment word.
+pub fn expand_assignment(
+    assignment: &Assignment,
+    env: &mut ShellEnv,
+) -> Result<ExpandedAssignment, String> {
+    let subscript = match &assignment.subscript {
+        Some(sub) => Some(expand_word_string(sub, env)?),
+        None => None,
+    };
+    let value = match assignment.value.parts.as_slice() {
+        [WordPart::Array(elements)] => {
+            let mut expanded = Vec::new();
+            for element in elements {
+                expanded.push(match element.as_keyed_element() {
+                    Some((key, value)) => Element::Keyed(
+                        expand_word_string(&key, env)?,
+                        expand_word_string(&value, env)?,
+                    ),
+                    None => Element::Fields(expand_word(element, env)?),
+                });
+            }
+            AssignedValue::Array(expanded)
+        }
+        _ => AssignedValue::Scalar(expand_assignment_value(&assignment.value, env)?),
+    };
+    Ok(ExpandedAssignment {
+        name: assignment.name.clone(),
+        subscript,
+        append: assignment.append,
+        value,
+    })
+}
+
+impl ExpandedAssignment {
+    /// Parses `name=value`, `name+=value` or `name[subscript]=value` that
+    /// an expansion produced, as in `declare $x`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (target, value) = text.split_once('=')?;
+        let (target, append) = match target.strip_suffix('+') {
+            Some(target) => (target, true),
+            None => (target, false),
+        };
+        let (name, subscript) = match target.strip_suffix(']') {
+            Some(target) => {
+                let (name, sub) = target.split_once('[')?;
+                (name, Some(sub.to_string()))
+            }
+            None => (target, None),
+        };
+        if !is_valid_name(name) {
+            return None;
+        }
+        Some(ExpandedAssignment {
+            name: name.to_string(),
+            subscript,
+            append,
+            value: AssignedValue::Scalar(value.to_string()),
+        })
+    }
+
+    /// Performs the assignment, returning it as shown by `set -x`.
+    pub fn perform(&self, env: &mut ShellEnv) -> Result<String, String> {
+        let name = &self.name;
+        env.check_writable(name)?;
+        let op = if self.append { "+=" } else { "=" };
+        let value = match &self.value {
+            AssignedValue::Array(elements) => {
+                if self.subscript.is_some() {
+                    return Err(format!("{}: cannot assign list to array member", name));
+                }
+                let values = assign_array(name, elements, self.append, env)?;
+                let values: Vec<String> = values.iter().map(|v| super::shell_quote(v)).collect();
+                return Ok(format!("{}{}({})", name, op, values.join(" ")));
+            }
+            AssignedValue::Scalar(value) => value,
+        };
+
+        let shown = super::shell_quote(value);
+        match &self.subscript {
+            Some(key) => {
+                let mut new = value.clone();
+                if self.append {
+                    let old = env.get_element(name, key)?.unwrap_or_default();
+                    new = old + &new;
+                }
+                env.set_element(name, key, &new)?;
+                Ok(format!("{}[{}]{}{}", name, key, op, shown))
+            }
+            None => {
+                let mut new = value.clone();
+                if self.append {
+                    new = env.get(name).unwrap_or_default() + &new;
+                }
+                env.set(name, &new);
+                Ok(format!("{}{}{}", name, op, shown))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ExpandedAssignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(key) = &self.subscript {
+            write!(f, "[{}]", key)?;
+        }
+        let op = if self.append { "+=" } else { "=" };
+        match &self.value {
+            AssignedValue::Scalar(value) => write!(f, "{}{}", op, value),
+            AssignedValue::Array(elements) => {
+                let mut items = Vec::new();
+                for element in elements {
+                    match element {
+                        Element::Keyed(key, value) => {
+                            items.push(format!("[{}]={}", key, super::shell_quote(value)))
+                        }
+                        Element::Fields(fields) => {
+                            items.extend(fields.iter().map(|field| super::shell_quote(field)))
+                        }
+                    }
+                }
+                write!(f, "{}({})", op, items.join(" "))
+            }
+        }
+    }
+}
+
+fn assign_array(
+    name: &str,
+    elements: &[Element],
+    append: bool,
+    env: &mut ShellEnv,
+) -> Result<Vec<String>, String> {
+    // The values given, in order
+    let mut given = Vec::new();
+    if env.is_assoc(name) {
+        let mut items = BTreeMap::new();
+        if append {
+            for key in env.keys(name) {
+                let value = env.get_element(name, &key)?.unwrap_or_default();
+                items.insert(key, value);
+            }
+        }
+        for element in elements {
+            let (key, value) = match element {
+                Element::Keyed(key, value) => (key, value),
+                Element::Fields(fields) => {
+                    return Err(format!(
+                        "{}: {}: must use subscript when assigning associative array",
+                        name,
+                        fields.join(" ")
+                    ))
+                }
+            };
+            given.push(format!("[{}]={}", key, value));
+            items.insert(key.clone(), value.clone());
+        }
+        env.set_value(name, Value::Assoc(items));
+        return Ok(given);
+    }
+
+    let mut items = BTreeMap::new();
+    if append {
+        for key in env.keys(name) {
+            let value = env.get_element(name, &key)?.unwrap_or_default();
+            items.insert(env.eval_index(&key)? as usize, value);
+        }
+    }
+    let mut next = items.keys().next_back().map_or(0, |k| k + 1);
+    for element in elements {
+        match element {
+            Element::Keyed(key, value) => {
+                let index = env.eval_index(key)?;
+                let index = usize::try_from(index)
+                    .map_err(|_| format!("{}[{}]: bad array subscript", name, index))?;
+                given.push(format!("[{}]={}", index, value));
+                items.insert(index, value.clone());
+                next = index + 1;
+            }
+            Element::Fields(fields) => {
+                for field in fields {
+                    given.push(field.clone());
+                    items.insert(next, field.clone());
+                    next += 1;
+                }
+            }
+        }
+    }
+    env.set_value(name, Value::Indexed(items));
+    Ok(given)
+}
+
+/// Splits characters into fields on unquoted IFS characters from expansions.
+///
+/// IFS whitespace around a delimiter is collapsed, and leading or trailing
+/// IFS whitespace never produces a field. With `max > 0`, the last field
+/// receives the rest of the input (minus trailing IFS whitespace), which is
+/// how `read` assigns its final variable.
+pub fn split_fields(chars: &[ExpChar], ifs: &str, max: usize) -> Vec<String> {
+    split_chars(chars, ifs, max)
+        .into_iter()
+        .map(|field| field.iter().map(|ec| ec.c).collect())
+        .collect()
+}
+
+// split_fields, keeping the characters' quoting for pathname expansion
+fn split_chars<'a>(chars: &'a [ExpChar], ifs: &str, max: usize) -> Vec<&'a [ExpChar]> {
+    let n = chars.len();
+    let mut fields = Vec::new();
+    let mut i = 0;
+    while i < n && chars[i].is_ifs_whitespace(ifs) {
+        i += 1;
+    }
+    while i < n {
+        if max > 0 && fields.len() + 1 == max {
+            let mut end = n;
+            while end > i && chars[end - 1].is_ifs_whitespace(ifs) {
+                end -= 1;
+            }
+            fields.push(&chars[i..end]);
+            return fields;
+        }
+
+        let start = i;
+        while i < n && !chars[i].splittable(ifs) {
+            i += 1;
+        }
+        fields.push(&chars[start..i]);
+
+        // Consume one delimiter: IFS whitespace, at most one other IFS
+        // character, then any further IFS whitespace
+        while i < n && chars[i].is_ifs_whitespace(ifs) {
+            i += 1;
+        }
+        if i < n && chars[i].splittable(ifs) {
+            i += 1;
+            while i < n && chars[i].is_ifs_whitespace(ifs) {
+                i += 1;
+            }
+        }
+    }
+    fields
+}
+
+/// Splits plain text on `$IFS`, as `read` does. Characters flagged as
+/// escaped are never treated as separators.
+pub fn split_text(text: &[(char, bool)], ifs: &str, max: usize) -> Vec<String> {
+    let chars: Vec<ExpChar> = text
+        .iter()
+        .map(|&(c, escaped)| ExpChar {
+            c,
+            quoted: escaped,
+            from_expansion: true,
+        })
+        .collect();
+    split_fields(&chars, ifs, max)
+}
@@ -0,0 +1,185 @@
+use super::{RedirectInfo, RedirectMode};
+use crate::error::os_message;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::path::Path;
+
+// Saved copies of redirected descriptors are kept at or above this number,
+// out of the way of descriptors a script uses
+const SAVED_FD_BASE: RawFd = 100;
+
+/// Descriptors the shell has redirected for itself with `exec`. They stay
+/// in effect for later commands and are inherited by child processes.
+#[derive(Default)]
+pub struct FdTable {
+    // descriptor -> the redirection that opened it, e.g. `3>log`
+    open: BTreeMap<RawFd, String>,
+}
+
+impl FdTable {
+    /// Applies redirections permanently, as for `exec 3>log` or `exec 3>&-`.
+    pub fn apply(&mut self, redirects: &[RedirectInfo], cwd: &Path) -> Result<(), String> {
+        for redirect in redirects {
+            redirect_fd(redirect, cwd)?;
+            let fd = redirect.fd as RawFd;
+            if redirect.closes() {
+                self.open.remove(&fd);
+            } else {
+                self.open.insert(fd, redirect.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The descriptors opened with `exec`, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (RawFd, &str)> {
+        self.open.iter().map(|(fd, desc)| (*fd, desc.as_str()))
+    }
+}
+
+/// Temporary redirections for a single command. The original descriptors
+/// are put back when this is dropped. Relative file names are relative to
+/// the shell's working directory, `cwd`.
+pub struct SavedFds {
+    // descriptor -> copy of what it was before, or None if it was closed
+    saved: Vec<(RawFd, Option<OwnedFd>)>,
+}
+
+impl SavedFds {
+    pub fn apply(redirects: &[RedirectInfo], cwd: &Path) -> Result<Self, String> {
+        let mut saved = SavedFds { saved: Vec::new() };
+        if redirects.is_empty() {
+            return Ok(saved);
+        }
+        // Output buffered so far belongs to the original stdout
+        let _ = io::stdout().flush();
+        for redirect in redirects {
+            let fd = redirect.fd as RawFd;
+            if !saved.saved.iter().any(|(f, _)| *f == fd) {
+                saved.saved.push((fd, save_fd(fd)));
+            }
+            // Dropping `saved` undoes the redirections applied so far
+            redirect_fd(redirect, cwd)?;
+        }
+        Ok(saved)
+    }
+}
+
+impl Drop for SavedFds {
+    fn drop(&mut self) {
+        if self.saved.is_empty() {
+            return;
+        }
+        let _ = io::stdout().flush();
+        for (fd, copy) in self.saved.drain(..).rev() {
+            unsafe {
+                match copy {
+                    Some(copy) => libc::dup2(copy.as_raw_fd(), fd),
+                    None => libc::close(fd),
+                };
+            }
+        }
+    }
+}
+
+/// Applies redirections for good, in a child process about to exec.
+pub fn redirect_all(redirects: &[RedirectInfo], cwd: &Path) -> Result<(), String> {
+    redirects
+        .iter()
+        .try_for_each(|redirect| redirect_fd(redirect, cwd))
+}
+
+// Duplicates `fd` out of the way, or returns None if it is not open
+fn save_fd(fd: RawFd) -> Option<OwnedFd> {
+    let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, SAVED_FD_BASE) };
+    (copy != -1).then(|| unsafe { OwnedFd::from_raw_fd(copy) })
+}
+
+fn is_open(fd: RawFd) -> bool {
+    unsafe { libc::fcntl(fd, libc::F_GETFD) != -1 }
+}
+
+// Points `redirect.fd` at the redirection's target
+fn redirect_fd(redirect: &RedirectInfo, cwd: &Path) -> Result<(), String> {
+    let fd = redirect.fd as RawFd;
+    if redirect.closes() {
+        unsafe { libc::close(fd) };
+        return Ok(());
+    }
+    if let RedirectMode::DupOutput | RedirectMode::DupInput = redirect.mode {
+        let source = match redirect.path.parse::<RawFd>() {
+            Ok(source) if is_open(source) => source,
+            Ok(_) => return Err(format!("{}: Bad file descriptor", redirect.path)),
+            Err(_) => return Err(format!("{}: ambiguous redirect", redirect.path)),
+        };
+        if source != fd {
+            unsafe { libc::dup2(source, fd) };
+        }
+        return Ok(());
+    }
+
+    let file =
+        open_target(redirect, cwd).map_err(|e| format!("{}: {}", redirect.path, os_message(&e)))?;
+    if file.as_raw_fd() == fd {
+        // Opened right onto the descriptor: keep it open across exec
+        let raw = file.into_raw_fd();
+        unsafe { libc::fcntl(raw, libc::F_SETFD, 0) };
+    } else if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+        return Err(format!(
+            "{}: {}",
+            fd,
+            os_message(&io::Error::last_os_error())
+        ));
+    }
+    Ok(())
+}
+
+fn open_target(redirect: &RedirectInfo, cwd: &Path) -> io::Result<File> {
+    let path = &cwd.join(&redirect.path);
+    match &redirect.mode {
+        RedirectMode::Read => File::open(path),
+        RedirectMode::ReadWrite => OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path),
+        mode => {
+            // Missing parent directories of an output file are created
+            if let Some(parent) = path.parent() {
+                if !parent.as_os_str().is_empty() && !parent.exists() {
+                    std::fs::create_dir_all(parent)?;
+                }
+            }
+            let append = matches!(mode, RedirectMode::Append);
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(append)
+                .truncate(!append)
+                .open(path)
+        }
+    }
+}
+
+/// Reads a line from descriptor 0 a byte at a time, leaving what follows
+/// it for the commands run next. `None` at the end of input.
+pub fn read_line_unbuffered() -> io::Result<Option<String>> {
+    let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        match stdin.read(&mut byte) {
+            Ok(0) if line.is_empty() => return Ok(None),
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
@@ -0,0 +1,229 @@
+use super::expand::ExpChar;
+use std::path::Path;
+
+// One element of a pattern; quoted characters are always literal
+#[derive(Debug)]
+enum Token {
+    Char(char),
+    // *
+    Any,
+    // ?
+    One,
+    // [...]: the set of ranges, negated by a leading `!` or `^`
+    Class {
+        negated: bool,
+        ranges: Vec<ClassItem>,
+    },
+}
+
+#[derive(Debug)]
+enum ClassItem {
+    Range(char, char),
+    // [:alpha:] and friends
+    Named(String),
+}
+
+/// Pathname expansion: the sorted paths matching a field with unquoted
+/// `*`, `?` or `[...]`, relative paths being relative to `cwd`. Returns
+/// None if the field is not a pattern or nothing matches, in which case
+/// it is kept as it is.
+pub fn expand(field: &[ExpChar], cwd: &Path) -> Option<Vec<String>> {
+    let components: Vec<&[ExpChar]> = field.split(|ec| ec.c == '/').collect();
+    if !components.iter().any(|c| is_pattern(c)) {
+        return None;
+    }
+
+    // Paths matched so far, without a trailing slash
+    let absolute = field.first().is_some_and(|ec| ec.c == '/');
+    let mut paths = vec![String::new()];
+    let last = components.len() - 1;
+    for (i, component) in components.iter().enumerate() {
+        if i == 0 && absolute {
+            continue;
+        }
+        if component.is_empty() {
+            // Repeated or trailing slash
+            for path in &mut paths {
+                path.push('/');
+            }
+            continue;
+        }
+        let prefix = |path: &str| {
+            if path.is_empty() && !absolute {
+                String::new()
+            } else {
+                format!("{}/", path)
+            }
+        };
+        if !is_pattern(component) {
+            let name: String = component.iter().map(|ec| ec.c).collect();
+            paths = paths
+                .into_iter()
+                .map(|path| prefix(&path) + &name)
+                .collect();
+            continue;
+        }
+
+        let tokens = parse(component);
+        // Hidden files only match a pattern starting with a literal dot
+        let dot_ok = matches!(tokens.first(), Some(Token::Char('.')));
+        let mut matched = Vec::new();
+        for path in &paths {
+            let dir = match path.as_str() {
+                "" if absolute => "/",
+                "" => ".",
+                path => path,
+            };
+            let Ok(entries) = std::fs::read_dir(cwd.join(dir)) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| dot_ok || !name.starts_with('.'))
+                .filter(|name| matches_tokens(&tokens, &name.chars().collect::<Vec<_>>()))
+                .collect();
+            names.sort();
+            for name in names {
+                let candidate = prefix(path) + &name;
+                // Only directories can match a component with more after it
+                if i == last || cwd.join(&candidate).is_dir() {
+                    matched.push(candidate);
+                }
+            }
+        }
+        paths = matched;
+    }
+
+    // Literal components after the last pattern must exist
+    paths.retain(|path| cwd.join(path).symlink_metadata().is_ok());
+    if paths.is_empty() {
+        return None;
+    }
+    paths.sort();
+    Some(paths)
+}
+
+fn is_pattern(chars: &[ExpChar]) -> bool {
+    chars
+        .iter()
+        .any(|ec| !ec.quoted && matches!(ec.c, '*' | '?' | '['))
+}
+
+fn parse(chars: &[ExpChar]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ec = chars[i];
+        i += 1;
+        if ec.quoted {
+            tokens.push(Token::Char(ec.c));
+            continue;
+        }
+        match ec.c {
+            '*' => tokens.push(Token::Any),
+            '?' => tokens.push(Token::One),
+            '[' => match parse_class(&chars[i..]) {
+                Some((class, len)) => {
+                    tokens.push(class);
+                    i += len;
+                }
+                // A `[` without a closing `]` is literal
+                None => tokens.push(Token::Char('[')),
+            },
+            c => tokens.push(Token::Char(c)),
+        }
+    }
+    tokens
+}
+
+// Parses a bracket expression after its `[`, returning it and the number
+// of characters used, including the closing `]`
+fn parse_class(chars: &[ExpChar]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first().map(|ec| ec.c), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < chars.len() {
+        let c = chars[i].c;
+        // A `]` right at the start is part of the set
+        if c == ']' && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        if c == '[' && chars.get(i + 1).is_some_and(|ec| ec.c == ':') {
+            let rest: String = chars[i + 2..].iter().map(|ec| ec.c).collect();
+            if let Some(end) = rest.find(":]") {
+                ranges.push(ClassItem::Named(rest[..end].to_string()));
+                i += 2 + end + 2;
+                continue;
+            }
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some(dash), Some(hi)) if dash.c == '-' && hi.c != ']' => {
+                ranges.push(ClassItem::Range(c, hi.c));
+                i += 3;
+            }
+            _ => {
+                ranges.push(ClassItem::Range(c, c));
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+fn class_matches(ranges: &[ClassItem], c: char) -> bool {
+    ranges.iter().any(|item| match item {
+        ClassItem::Range(lo, hi) => (*lo..=*hi).contains(&c),
+        ClassItem::Named(name) => match name.as_str() {
+            "alpha" => c.is_alphabetic(),
+            "digit" => c.is_ascii_digit(),
+            "alnum" => c.is_alphanumeric(),
+            "upper" => c.is_uppercase(),
+            "lower" => c.is_lowercase(),
+            "space" => c.is_whitespace(),
+            "blank" => c == ' ' || c == '\t',
+            "punct" => c.is_ascii_punctuation(),
+            "xdigit" => c.is_ascii_hexdigit(),
+            "cntrl" => c.is_control(),
+            "print" => !c.is_control(),
+            "graph" => !c.is_control() && !c.is_whitespace(),
+            _ => false,
+        },
+    })
+}
+
+/// Whether `name` matches the pattern tokens.
+fn matches_tokens(tokens: &[Token], name: &[char]) -> bool {
+    // Backtracking over the last `*` seen is enough for these patterns
+    let (mut t, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let advance = match tokens.get(t) {
+            Some(Token::Any) => {
+                star = Some((t, n));
+                t += 1;
+                continue;
+            }
+            Some(Token::One) => true,
+            Some(Token::Char(c)) => *c == name[n],
+            Some(Token::Class { negated, ranges }) => class_matches(ranges, name[n]) != *negated,
+            None => false,
+        };
+        if advance {
+            t += 1;
+            n += 1;
+        } else if let Some((star_t, star_n)) = star {
+            // Let the last `*` take one more character and retry
+            t = star_t + 1;
+            n = star_n + 1;
+            star = Some((star_t, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    tokens[t..].iter().all(|token| matches!(token, Token::Any))
+}
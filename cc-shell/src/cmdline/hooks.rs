@@ -0,0 +1,30 @@
+use super::env::ShellEnv;
+use super::list;
+
+/// Runs the commands in the hook variable `name` (`chpwd`, `precmd` or
+/// `preexec`), a scalar or an array of commands, each run like `eval`.
+/// `args` are the positional parameters meanwhile. Hooks don't run from
+/// within hooks, and leave $? alone.
+pub fn run(name: &str, args: &[String], env: &mut ShellEnv) {
+    if env.in_hook {
+        return;
+    }
+    let commands = env.values(name);
+    if commands.is_empty() {
+        return;
+    }
+    env.in_hook = true;
+    let status = env.last_status;
+    let positional = std::mem::replace(&mut env.positional, args.to_vec());
+    for command in commands {
+        if env.exit_requested {
+            break;
+        }
+        if let Some(mut list) = list::parse_line(&command, None, env) {
+            list.execute(env);
+        }
+    }
+    env.positional = positional;
+    env.last_status = status;
+    env.in_hook = false;
+}
@@ -0,0 +1,71 @@
+/// A pipeline started in the background with `&`.
+pub struct Job {
+    // The number `%n` refers to
+    pub id: usize,
+    pub pid: libc::pid_t,
+    pub command: String,
+}
+
+/// The background jobs not yet waited for.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    /// Records a job and returns its number: one more than the highest
+    /// still running, as in other shells.
+    pub fn add(&mut self, pid: libc::pid_t, command: &str) -> usize {
+        let id = self.jobs.last().map_or(1, |job| job.id + 1);
+        self.jobs.push(Job {
+            id,
+            pid,
+            command: command.trim().to_string(),
+        });
+        id
+    }
+
+    /// The process of a job given as `%n`, `%%` or `%+` (the latest job),
+    /// or `%-` (the one before it).
+    pub fn find(&self, spec: &str) -> Option<libc::pid_t> {
+        let job = match spec.strip_prefix('%')? {
+            "" | "%" | "+" => self.jobs.last(),
+            "-" => self.jobs.iter().rev().nth(1),
+            n => {
+                let id = n.parse::<usize>().ok()?;
+                self.jobs.iter().find(|job| job.id == id)
+            }
+        };
+        job.map(|job| job.pid)
+    }
+
+    pub fn contains(&self, pid: libc::pid_t) -> bool {
+        self.jobs.iter().any(|job| job.pid == pid)
+    }
+
+    /// Forgets a job once its process has been waited for.
+    pub fn remove(&mut self, pid: libc::pid_t) -> Option<Job> {
+        let i = self.jobs.iter().position(|job| job.pid == pid)?;
+        Some(self.jobs.remove(i))
+    }
+
+    pub fn pids(&self) -> Vec<libc::pid_t> {
+        self.jobs.iter().map(|job| job.pid).collect()
+    }
+
+    /// Reaps the jobs that have finished without blocking, returning them
+    /// with their statuses.
+    pub fn reap_finished(&mut self) -> Vec<(Job, i32)> {
+        let mut finished = Vec::new();
+        for pid in self.pids() {
+            let mut status = 0;
+            if unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } != pid {
+                continue;
+            }
+            if let Some(job) = self.remove(pid) {
+                finished.push((job, super::exit_status(status)));
+            }
+        }
+        finished
+    }
+}
@@ -0,0 +1,325 @@
+use super::env::ShellEnv;
+use super::jobs::Jobs;
+use super::{strip_comments, unclosed, unclosed_select, unquoted_chars, Cmdline};
+use crate::error::ShellError;
+use serde::Serialize;
+use serde_json::json;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+
+/// A command line: pipelines separated by `;`, `&`, `&&`, `||` and
+/// newlines.
+pub struct CommandList {
+    pub items: Vec<(Connector, Cmdline)>,
+}
+
+/// How a pipeline depends on the one before it.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connector {
+    // The first pipeline, or one after `;`: always runs
+    Always,
+    // `&&`: runs if the previous pipeline succeeded
+    And,
+    // `||`: runs if the previous pipeline failed
+    Or,
+}
+
+// A pipeline's text with the connector before it and the position and
+// text of the separator after it
+struct Part<'a> {
+    connector: Connector,
+    text: &'a str,
+    separator: Option<(usize, &'static str)>,
+}
+
+impl CommandList {
+    pub fn new(input: &str) -> Result<Self, ShellError> {
+        let input = strip_comments(input);
+        Self::parse(&input, &input)
+    }
+
+    /// Parses `text`, the whole command line `input` or the inside of a
+    /// subshell in it. Syntax errors point into `input`, from which
+    /// comments have been removed.
+    pub fn parse(input: &str, text: &str) -> Result<Self, ShellError> {
+        let offset = |s: &str| s.as_ptr() as usize - input.as_ptr() as usize;
+        if let Some((pos, close)) = unclosed(text) {
+            let message = format!("unexpected EOF while looking for matching `{}'", close);
+            return Err(ShellError::syntax(message, input, offset(text) + pos));
+        }
+        if unclosed_select(text) {
+            let end = offset(text) + text.len();
+            return Err(ShellError::syntax(
+                "syntax error: unexpected end of file",
+                input,
+                end,
+            ));
+        }
+
+        let mut items = Vec::new();
+        for part in split_list(text) {
+            if part.text.trim().is_empty() {
+                match (part.separator, part.connector) {
+                    (Some((pos, separator)), _) => {
+                        let message = format!("syntax error near unexpected token `{}'", separator);
+                        return Err(ShellError::syntax(message, input, offset(text) + pos));
+                    }
+                    (None, Connector::And | Connector::Or) => {
+                        let end = offset(text) + text.len();
+                        return Err(ShellError::syntax(
+                            "syntax error: unexpected end of file",
+                            input,
+                            end,
+                        ));
+                    }
+                    // Nothing after a final `;`, or an empty line
+                    (None, Connector::Always) => continue,
+                }
+            }
+            let next = part.separator.map_or("newline", |(_, separator)| separator);
+            let mut pipeline = Cmdline::parse(input, part.text, next)?;
+            pipeline.background = next == "&";
+            items.push((part.connector, pipeline));
+        }
+        Ok(CommandList { items })
+    }
+
+    /// Runs the pipelines in turn, skipping those whose connector doesn't
+    /// match the status so far, until `exit` or `break`. An and-or list
+    /// followed by `&`, such as `sleep 1 && echo done &`, is started as a
+    /// whole in the background. Errors are reported and set the status.
+    pub fn execute(&mut self, env: &mut ShellEnv) {
+        let mut items = self.items.as_mut_slice();
+        while !items.is_empty() {
+            if env.exit_requested || env.breaking > 0 {
+                break;
+            }
+            // A pipeline and those joined to it by `&&` and `||`
+            let len = 1 + items[1..]
+                .iter()
+                .take_while(|(connector, _)| !matches!(connector, Connector::Always))
+                .count();
+            let (and_or, rest) = items.split_at_mut(len);
+            items = rest;
+            if and_or[len - 1].1.background {
+                if let Err(e) = spawn(and_or, env) {
+                    e.report();
+                    env.last_status = e.status();
+                }
+            } else {
+                run_and_or(and_or, env);
+            }
+        }
+    }
+
+    /// Describes the parsed and expanded pipelines, for `--dry-run`.
+    pub fn describe(&mut self, env: &mut ShellEnv) -> Result<serde_json::Value, ShellError> {
+        let mut pipelines = Vec::new();
+        for (connector, pipeline) in &mut self.items {
+            let mut description = pipeline.describe(env)?;
+            description["connector"] = json!(connector);
+            description["background"] = json!(pipeline.background);
+            pipelines.push(description);
+        }
+        Ok(json!({ "pipelines": pipelines }))
+    }
+}
+
+// Runs the pipelines of an and-or list in the shell
+fn run_and_or(and_or: &mut [(Connector, Cmdline)], env: &mut ShellEnv) {
+    for (connector, pipeline) in and_or {
+        if env.exit_requested || env.breaking > 0 {
+            break;
+        }
+        let run = match connector {
+            Connector::Always => true,
+            Connector::And => env.last_status == 0,
+            Connector::Or => env.last_status != 0,
+        };
+        if !run {
+            continue;
+        }
+        if let Err(e) = pipeline.execute(env) {
+            e.report();
+            env.last_status = e.status();
+        }
+    }
+}
+
+/// Starts an and-or list in a forked copy of the shell without waiting
+/// for it, recording it as a job and in `$!`. As in other shells without
+/// job control, it reads from /dev/null unless redirected and ignores the
+/// keyboard's SIGINT and SIGQUIT.
+fn spawn(and_or: &mut [(Connector, Cmdline)], env: &mut ShellEnv) -> Result<(), ShellError> {
+    let _ = std::io::stdout().flush();
+    match unsafe { libc::fork() } {
+        -1 => Err(ShellError::io("fork", std::io::Error::last_os_error())),
+        0 => {
+            unsafe {
+                libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+                libc::signal(libc::SIGINT, libc::SIG_IGN);
+                libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+            }
+            if let Ok(null) = std::fs::File::open("/dev/null") {
+                unsafe { libc::dup2(null.as_raw_fd(), 0) };
+            }
+            // The jobs are the parent's, not this process's children
+            env.jobs = Jobs::default();
+            run_and_or(and_or, env);
+            let _ = std::io::stdout().flush();
+            unsafe { libc::_exit(env.last_status) }
+        }
+        pid => {
+            let mut text = String::new();
+            for (connector, pipeline) in and_or.iter() {
+                match connector {
+                    Connector::Always => {}
+                    Connector::And => text.push_str(" && "),
+                    Connector::Or => text.push_str(" || "),
+                }
+                text.push_str(&pipeline.text);
+            }
+            let id = env.jobs.add(pid, &text);
+            if env.options.interactive {
+                errln!("[{}] {}", id, pid);
+            }
+            env.last_background = Some(pid);
+            env.last_status = 0;
+            Ok(())
+        }
+    }
+}
+
+/// Parses a line, reporting a syntax error, which names the line if its
+/// `number` is given, and setting the status.
+pub fn parse_line(line: &str, number: Option<usize>, env: &mut ShellEnv) -> Option<CommandList> {
+    match CommandList::new(line) {
+        Ok(list) => Some(list),
+        Err(e) => {
+            let e = match number {
+                Some(number) => e.at_line(number),
+                None => e,
+            };
+            e.report();
+            env.last_status = e.status();
+            None
+        }
+    }
+}
+
+/// The commands of a script with the line each starts on. The lines of a
+/// command left unfinished, as [`is_incomplete`] tells, are joined; blank
+/// lines are skipped.
+pub fn commands(source: &str) -> impl Iterator<Item = (usize, String)> + '_ {
+    let mut lines = source.lines().enumerate();
+    std::iter::from_fn(move || loop {
+        let (number, line) = lines.next()?;
+        let mut command = line.to_string();
+        while is_incomplete(&command) {
+            let Some((_, more)) = lines.next() else {
+                break;
+            };
+            command.push('\n');
+            command.push_str(more);
+        }
+        if !command.trim().is_empty() {
+            return Some((number + 1, command));
+        }
+    })
+}
+
+/// Runs the commands of a file in turn, for `source`, until one runs
+/// `exit` or `break`. The status is that of the last command, or 0 if
+/// none ran.
+pub fn run_lines(source: &str, env: &mut ShellEnv) -> i32 {
+    // $? is the status before the first command, as in other shells
+    let mut ran = false;
+    for (number, command) in commands(source) {
+        if env.exit_requested || env.breaking > 0 {
+            break;
+        }
+        ran = true;
+        if let Some(mut list) = parse_line(&command, Some(number), env) {
+            list.execute(env);
+        }
+    }
+    if ran {
+        env.last_status
+    } else {
+        0
+    }
+}
+
+/// Whether `input` leaves something open that another line would finish:
+/// a quote, a substitution, subshell or `select`, or a trailing `\`,
+/// `|`, `&&` or `||`.
+pub fn is_incomplete(input: &str) -> bool {
+    let text = strip_comments(input);
+    if unclosed(&text).is_some() || unclosed_select(&text) {
+        return true;
+    }
+    let trailing_backslashes = text.chars().rev().take_while(|&c| c == '\\').count();
+    if trailing_backslashes % 2 == 1 {
+        return true;
+    }
+    let text = text.trim_end();
+    (text.ends_with("&&") || !text.is_empty() && continues(text))
+        && unquoted_chars(text)
+            .last()
+            .is_some_and(|&(i, _)| i + 1 == text.len())
+}
+
+// Whether the pipeline text so far goes on past a newline: it is empty,
+// as after `&&`, or ends in a `|`
+fn continues(text: &str) -> bool {
+    let text = text.trim_end();
+    text.is_empty() || text.ends_with('|') && !text.ends_with(">|")
+}
+
+// Splits a command line on unquoted `;`, `&`, `&&`, `||` and newlines
+fn split_list(text: &str) -> Vec<Part<'_>> {
+    let chars = unquoted_chars(text);
+    let mut parts = Vec::new();
+    let mut connector = Connector::Always;
+    let mut start = 0;
+    let mut k = 0;
+    while k < chars.len() {
+        let (i, c) = chars[k];
+        let doubled = chars
+            .get(k + 1)
+            .is_some_and(|&(j, next)| j == i + 1 && next == c);
+        let separator = match c {
+            ';' => Some((";", Connector::Always)),
+            '&' if doubled => Some(("&&", Connector::And)),
+            // The `|` of the `>|` operator is not a separator
+            '|' if doubled && !text[..i].ends_with('>') => Some(("||", Connector::Or)),
+            // Nor is the `&` of `>&`, `<&`, `&>` and `&>>`
+            '&' if !text[..i].ends_with(['>', '<']) && !text[i + 1..].starts_with('>') => {
+                Some(("&", Connector::Always))
+            }
+            // A newline after an operator, or on its own, separates nothing
+            '\n' if !continues(&text[start..i]) => Some(("newline", Connector::Always)),
+            _ => None,
+        };
+        k += 1;
+        let Some((separator, next)) = separator else {
+            continue;
+        };
+        parts.push(Part {
+            connector,
+            text: &text[start..i],
+            separator: Some((i, separator)),
+        });
+        let len = if c == '\n' { 1 } else { separator.len() };
+        connector = next;
+        start = i + len;
+        k += len - 1;
+    }
+    parts.push(Part {
+        connector,
+        text: &text[start..],
+        separator: None,
+    });
+    parts
+}
@@ -0,0 +1,85 @@
+/// Shell options toggled with `set -o name` / `set +o name`, and those
+/// of `shopt -s name` / `shopt -u name`.
+#[derive(Clone, Default)]
+pub struct ShellOptions {
+    // shopt: load trusted .ccenv files of the directories entered
+    pub ccenv: bool,
+    // shopt: run the last stage of a pipeline in the shell itself
+    pub lastpipe: bool,
+    // -f: disable pathname expansion
+    pub noglob: bool,
+    // -n: read commands without running them
+    pub noexec: bool,
+    // vi-style line editing instead of emacs-style
+    pub vi: bool,
+    // -x: print each expanded command before running it
+    pub xtrace: bool,
+    // -r: a restricted shell, which can't be turned off once on
+    pub restricted: bool,
+    // -i: reading commands typed at a terminal
+    pub interactive: bool,
+}
+
+impl ShellOptions {
+    pub fn set(&mut self, name: &str, on: bool) -> Result<(), String> {
+        match name {
+            "emacs" => self.vi = !on,
+            "noexec" => self.noexec = on,
+            "noglob" => self.noglob = on,
+            "vi" => self.vi = on,
+            "xtrace" => self.xtrace = on,
+            _ => return Err(format!("{}: invalid option name", name)),
+        }
+        Ok(())
+    }
+
+    /// Maps a single-letter flag (as in `set -x`) to its option name.
+    pub fn flag_name(flag: char) -> Option<&'static str> {
+        match flag {
+            'f' => Some("noglob"),
+            'n' => Some("noexec"),
+            'x' => Some("xtrace"),
+            _ => None,
+        }
+    }
+
+    /// The single-letter flags of the options that are on, for `$-`.
+    pub fn flags(&self) -> String {
+        [
+            ('f', self.noglob),
+            ('i', self.interactive),
+            ('n', self.noexec),
+            ('r', self.restricted),
+            ('x', self.xtrace),
+        ]
+        .into_iter()
+        .filter_map(|(flag, on)| on.then_some(flag))
+        .collect()
+    }
+
+    /// All options with their current state, for `set -o`.
+    pub fn list(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("emacs", !self.vi),
+            ("noexec", self.noexec),
+            ("noglob", self.noglob),
+            ("vi", self.vi),
+            ("xtrace", self.xtrace),
+        ]
+    }
+
+    /// Sets an option of `shopt`.
+    pub fn set_shopt(&mut self, name: &str, on: bool) -> Result<(), String> {
+        match name {
+            "ccenv" => self.ccenv = on,
+            "lastpipe" => self.lastpipe = on,
+            _ => return Err(format!("{}: invalid shell option name", name)),
+        }
+        Ok(())
+    }
+
+    /// The options of `shopt` with their current state.
+    pub fn list_shopt(&self) -> Vec<(&'static str, bool)> {
+        vec![("ccenv", self.ccenv), ("lastpipe", self.lastpipe)]
+    }
+}
@@ -0,0 +1,278 @@
+use super::env::ShellEnv;
+use super::executable::Executable;
+use std::collections::{BTreeMap, HashSet};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Reserved words, which `type` reports although they are not commands.
+pub const KEYWORDS: &[&str] = &["time"];
+
+/// The search path of `command -p`, which finds the standard utilities
+/// whatever $PATH is.
+pub const DEFAULT_PATH: &str = "/bin:/usr/bin";
+
+/// Which external commands may be run, as read from a policy file given
+/// with `--policy`. Each line names a command, allowed wherever $PATH finds
+/// it, or gives the path of an allowed file. Blank lines and lines starting
+/// with `#` are ignored. The default policy allows everything.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    // None: no policy file, anything goes
+    allowed: Option<Allowed>,
+}
+
+#[derive(Clone, Debug)]
+struct Allowed {
+    names: HashSet<String>,
+    // Canonical paths
+    files: HashSet<PathBuf>,
+}
+
+impl Policy {
+    pub fn load(file: &Path) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(file)?))
+    }
+
+    /// Reads a policy from the contents of a policy file.
+    pub fn parse(text: &str) -> Self {
+        let mut allowed = Allowed {
+            names: HashSet::new(),
+            files: HashSet::new(),
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.contains('/') {
+                let file = Path::new(line);
+                allowed
+                    .files
+                    .insert(file.canonicalize().unwrap_or_else(|_| file.to_path_buf()));
+            } else {
+                allowed.names.insert(line.to_string());
+            }
+        }
+        Policy {
+            allowed: Some(allowed),
+        }
+    }
+
+    /// Whether the command `name`, found at `file`, may run. Names only
+    /// allow what a $PATH search finds, not a name given with a slash.
+    pub fn allows(&self, name: &str, file: &Path) -> bool {
+        let Some(allowed) = &self.allowed else {
+            return true;
+        };
+        (!name.contains('/') && allowed.names.contains(name))
+            || file
+                .canonicalize()
+                .is_ok_and(|file| allowed.files.contains(&file))
+    }
+}
+
+/// What a command name refers to.
+pub enum Resolved {
+    Keyword,
+    Builtin,
+    // An external command; `hashed` if its location was remembered
+    File { path: PathBuf, hashed: bool },
+}
+
+impl Resolved {
+    /// The kind word printed by `type -t`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Resolved::Keyword => "keyword",
+            Resolved::Builtin => "builtin",
+            Resolved::File { .. } => "file",
+        }
+    }
+
+    /// A sentence as printed by `type` and `command -V`.
+    pub fn describe(&self, name: &str) -> String {
+        match self {
+            Resolved::Keyword => format!("{} is a shell keyword", name),
+            Resolved::Builtin => format!("{} is a shell builtin", name),
+            Resolved::File { path, hashed: true } => {
+                format!("{} is hashed ({})", name, path.display())
+            }
+            Resolved::File { path, .. } => format!("{} is {}", name, path.display()),
+        }
+    }
+}
+
+/// Locations of commands found in $PATH, remembered so that later runs
+/// skip the search. Changing $PATH forgets them all.
+#[derive(Default)]
+pub struct CommandHash {
+    // The $PATH the entries were found with
+    path: String,
+    // name -> (location, times the command was run)
+    table: BTreeMap<String, (PathBuf, u32)>,
+}
+
+impl CommandHash {
+    // Drops the entries if $PATH changed since they were found
+    fn check_path(&mut self, path: &str) {
+        if self.path != path {
+            self.table.clear();
+            self.path = path.to_string();
+        }
+    }
+
+    /// The remembered location of `name`, if it is still valid. A relative
+    /// location is relative to `cwd`.
+    pub fn get(&self, name: &str, path: &str, cwd: &Path) -> Option<&Path> {
+        if self.path != path {
+            return None;
+        }
+        let (location, _) = self.table.get(name)?;
+        cwd.join(location).exists().then_some(location.as_path())
+    }
+
+    /// Searches $PATH for `name` and remembers it, as for `hash name`.
+    pub fn add(&mut self, name: &str, path: &str, cwd: &Path, policy: &Policy) -> Option<PathBuf> {
+        self.check_path(path);
+        let found = search_path(name, path, cwd, policy, false)
+            .into_iter()
+            .next()?;
+        self.table.insert(name.to_string(), (found.clone(), 0));
+        Some(found)
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.table.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    /// The entries as (times run, location), by name.
+    pub fn entries(&self) -> impl Iterator<Item = (u32, &Path)> {
+        self.table
+            .values()
+            .map(|(location, hits)| (*hits, location.as_path()))
+    }
+
+    // The location to run `name` from, counting the run
+    fn lookup(&mut self, name: &str, path: &str, cwd: &Path, policy: &Policy) -> Option<PathBuf> {
+        self.check_path(path);
+        if self.get(name, path, cwd).is_none() {
+            self.add(name, path, cwd, policy)?;
+        }
+        let (location, hits) = self.table.get_mut(name)?;
+        *hits += 1;
+        Some(location.clone())
+    }
+}
+
+/// Finds what `name` runs, as the shell would run it: a builtin, or else
+/// an external command from the hash table or $PATH. Keywords are only
+/// included for `type`.
+pub fn resolve(name: &str, env: &ShellEnv, keywords: bool) -> Option<Resolved> {
+    if keywords && KEYWORDS.contains(&name) {
+        return Some(Resolved::Keyword);
+    }
+    if Executable::is_builtin(name) {
+        return Some(Resolved::Builtin);
+    }
+    let path = env.get("PATH").unwrap_or_default();
+    if let Some(location) = env.hash.get(name, &path, &env.cwd) {
+        return Some(Resolved::File {
+            path: location.to_path_buf(),
+            hashed: true,
+        });
+    }
+    search_path(name, &path, &env.cwd, &env.policy, false)
+        .into_iter()
+        .next()
+        .map(|path| Resolved::File {
+            path,
+            hashed: false,
+        })
+}
+
+/// Everything `name` could refer to, in order of precedence, for `type -a`.
+/// The hash table is not consulted.
+pub fn resolve_all(name: &str, env: &ShellEnv) -> Vec<Resolved> {
+    let mut found = Vec::new();
+    if KEYWORDS.contains(&name) {
+        found.push(Resolved::Keyword);
+    }
+    if Executable::is_builtin(name) {
+        found.push(Resolved::Builtin);
+    }
+    let path = env.get("PATH").unwrap_or_default();
+    for path in search_path(name, &path, &env.cwd, &env.policy, true) {
+        found.push(Resolved::File {
+            path,
+            hashed: false,
+        });
+    }
+    found
+}
+
+/// The file to execute for external command `name`: a name with a slash
+/// is used as it is, others are looked up through the hash table in
+/// `search` ($PATH unless given). The result is resolved against the
+/// working directory.
+pub fn command_path(name: &str, env: &mut ShellEnv, search: Option<&str>) -> Option<PathBuf> {
+    let found = if name.contains('/') {
+        search_path(name, "", &env.cwd, &env.policy, false)
+            .into_iter()
+            .next()
+    } else if let Some(search) = search {
+        search_path(name, search, &env.cwd, &env.policy, false)
+            .into_iter()
+            .next()
+    } else {
+        let path = env.get("PATH").unwrap_or_default();
+        env.hash.lookup(name, &path, &env.cwd, &env.policy)
+    };
+    found.map(|found| env.path(found))
+}
+
+/// Searches the directories of `path` for `name`, taking the first
+/// executable file, or all of them with `all`. As a last resort the first
+/// file that isn't executable is returned, so that running it fails with
+/// "Permission denied" rather than "command not found". Relative
+/// directories are relative to `cwd` but are returned as they are. Files
+/// the policy doesn't allow are never found.
+pub fn search_path(name: &str, path: &str, cwd: &Path, policy: &Policy, all: bool) -> Vec<PathBuf> {
+    if name.is_empty() {
+        return Vec::new();
+    }
+    if name.contains('/') {
+        let file = PathBuf::from(name);
+        return if cwd.join(&file).exists() && policy.allows(name, &cwd.join(&file)) {
+            vec![file]
+        } else {
+            Vec::new()
+        };
+    }
+    let mut found = Vec::new();
+    let mut fallback = None;
+    for dir in path.split(':') {
+        // An empty entry means the current directory
+        let candidate = Path::new(if dir.is_empty() { "." } else { dir }).join(name);
+        let Ok(metadata) = cwd.join(&candidate).metadata() else {
+            continue;
+        };
+        if !metadata.is_file() || !policy.allows(name, &cwd.join(&candidate)) {
+            continue;
+        }
+        if metadata.permissions().mode() & 0o111 != 0 {
+            found.push(candidate);
+            if !all {
+                break;
+            }
+        } else if fallback.is_none() {
+            fallback = Some(candidate);
+        }
+    }
+    if found.is_empty() && !all {
+        found.extend(fallback);
+    }
+    found
+}
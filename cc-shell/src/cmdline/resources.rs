@@ -0,0 +1,228 @@
+use std::ops::{AddAssign, Sub};
+use std::time::Duration;
+
+/// User and system CPU time.
+#[derive(Clone, Copy, Default)]
+pub struct CpuTimes {
+    pub user: Duration,
+    pub sys: Duration,
+}
+
+impl CpuTimes {
+    pub fn from_rusage(usage: &libc::rusage) -> Self {
+        let to_duration = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+        };
+        CpuTimes {
+            user: to_duration(usage.ru_utime),
+            sys: to_duration(usage.ru_stime),
+        }
+    }
+
+    /// CPU time used by the shell process itself (RUSAGE_SELF) or by all
+    /// of its waited-for children (RUSAGE_CHILDREN).
+    pub fn of(who: libc::c_int) -> Self {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        unsafe { libc::getrusage(who, &mut usage) };
+        CpuTimes::from_rusage(&usage)
+    }
+}
+
+impl AddAssign for CpuTimes {
+    fn add_assign(&mut self, other: Self) {
+        self.user += other.user;
+        self.sys += other.sys;
+    }
+}
+
+impl Sub for CpuTimes {
+    type Output = CpuTimes;
+
+    fn sub(self, other: Self) -> Self {
+        CpuTimes {
+            user: self.user.saturating_sub(other.user),
+            sys: self.sys.saturating_sub(other.sys),
+        }
+    }
+}
+
+/// Formats a duration like bash's `time` and `times`: `0m1.250s`.
+pub fn format_minutes(d: Duration) -> String {
+    let millis = d.as_millis();
+    format!(
+        "{}m{}.{:03}s",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Reads the current file creation mask without changing it.
+pub fn current_umask() -> u32 {
+    unsafe {
+        let mask = libc::umask(0);
+        libc::umask(mask);
+        mask as u32
+    }
+}
+
+/// Applies a symbolic mode like `u=rwx,g+w,o-rx` to a umask. The mode
+/// describes permissions that are allowed, the mask those that are not.
+pub fn apply_symbolic_umask(mask: u32, mode: &str) -> Result<u32, String> {
+    let mut allowed = !mask & 0o777;
+    for clause in mode.split(',') {
+        let op_pos = clause
+            .find(['+', '-', '='])
+            .ok_or_else(|| format!("`{}': invalid symbolic mode operator", clause))?;
+        let (who, rest) = clause.split_at(op_pos);
+        let mut who_bits = 0;
+        for c in who.chars() {
+            who_bits |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => return Err(format!("`{}': invalid symbolic mode character", c)),
+            };
+        }
+        if who.is_empty() {
+            who_bits = 0o777;
+        }
+
+        // A clause may chain several operators, as in `u+r-w`
+        let mut chars = rest.chars().peekable();
+        while let Some(op) = chars.next() {
+            let mut perm = 0;
+            while let Some(&c) = chars.peek() {
+                perm |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    '+' | '-' | '=' => break,
+                    _ => return Err(format!("`{}': invalid symbolic mode character", c)),
+                };
+                chars.next();
+            }
+            match op {
+                '+' => allowed |= perm & who_bits,
+                '-' => allowed &= !(perm & who_bits),
+                _ => allowed = (allowed & !who_bits) | (perm & who_bits),
+            }
+        }
+    }
+    Ok(!allowed & 0o777)
+}
+
+/// Formats a umask as the permissions it allows: `u=rwx,g=rx,o=rx`.
+pub fn symbolic_umask(mask: u32) -> String {
+    let allowed = !mask & 0o777;
+    let part = |who: &str, shift: u32| {
+        let bits = (allowed >> shift) & 0o7;
+        let mut s = format!("{}=", who);
+        for (bit, c) in [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')] {
+            if bits & bit != 0 {
+                s.push(c);
+            }
+        }
+        s
+    };
+    format!("{},{},{}", part("u", 6), part("g", 3), part("o", 0))
+}
+
+// glibc gives the RLIMIT_* constants their own type; other libcs use c_int
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub type Resource = libc::c_int;
+
+/// A resource that `ulimit` can query and set.
+pub struct Limit {
+    pub flag: char,
+    pub description: &'static str,
+    pub unit: &'static str,
+    pub resource: Resource,
+    // Values are given and shown in multiples of this many bytes
+    pub scale: u64,
+}
+
+pub const LIMITS: &[Limit] = &[
+    Limit {
+        flag: 'c',
+        description: "core file size",
+        unit: "blocks",
+        resource: libc::RLIMIT_CORE,
+        scale: 1024,
+    },
+    Limit {
+        flag: 'f',
+        description: "file size",
+        unit: "blocks",
+        resource: libc::RLIMIT_FSIZE,
+        scale: 1024,
+    },
+    Limit {
+        flag: 'n',
+        description: "open files",
+        unit: "",
+        resource: libc::RLIMIT_NOFILE,
+        scale: 1,
+    },
+    Limit {
+        flag: 't',
+        description: "cpu time",
+        unit: "seconds",
+        resource: libc::RLIMIT_CPU,
+        scale: 1,
+    },
+    Limit {
+        flag: 'v',
+        description: "virtual memory",
+        unit: "kbytes",
+        resource: libc::RLIMIT_AS,
+        scale: 1024,
+    },
+];
+
+impl Limit {
+    pub fn find(flag: char) -> Option<&'static Limit> {
+        LIMITS.iter().find(|limit| limit.flag == flag)
+    }
+
+    /// The soft or hard limit, or None when unlimited.
+    pub fn get(&self, hard: bool) -> std::io::Result<Option<u64>> {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(self.resource, &mut rlim) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let value = if hard { rlim.rlim_max } else { rlim.rlim_cur };
+        Ok((value != libc::RLIM_INFINITY).then(|| value / self.scale))
+    }
+
+    /// Sets the soft and/or hard limit. Children inherit the new limits.
+    pub fn set(&self, value: Option<u64>, soft: bool, hard: bool) -> std::io::Result<()> {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(self.resource, &mut rlim) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let raw = match value {
+            Some(v) => v.saturating_mul(self.scale),
+            None => libc::RLIM_INFINITY,
+        };
+        if soft {
+            rlim.rlim_cur = raw;
+        }
+        if hard {
+            rlim.rlim_max = raw;
+        }
+        if unsafe { libc::setrlimit(self.resource, &rlim) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use super::env::{is_valid_name, ShellEnv};
+use super::expand::{self, Word};
+use super::list::CommandList;
+use super::{fds, is_delimiter, scan_unquoted, Cmd};
+use crate::columns;
+use crate::error::ShellError;
+use serde_json::json;
+
+/// `select name [in words ...]; do list; done`: a menu of the words, or
+/// of the positional parameters, from which each line read chooses one.
+pub struct Select {
+    // The variable set to the word chosen
+    name: String,
+    // The words after `in`, if there is an `in`
+    words: Option<Vec<Word>>,
+    body: CommandList,
+}
+
+impl Select {
+    /// Parses `text`, a `select` command up to and including its `done`,
+    /// in the command line `input` that syntax errors point into.
+    pub fn parse(input: &str, text: &str) -> Result<Self, ShellError> {
+        let offset = |s: &str| s.as_ptr() as usize - input.as_ptr() as usize;
+        let unexpected = |token: &str| {
+            let message = format!("syntax error near unexpected token `{}'", token);
+            ShellError::syntax(message, input, offset(token))
+        };
+        let (inner, done) = text.split_at(text.len() - "done".len());
+
+        // The name and words, up to the first `;` or newline
+        let Some(header_end) = scan_unquoted(inner)
+            .into_iter()
+            .find(|&(i, c)| i >= "select".len() && (c == ';' || c == '\n'))
+            .map(|(i, _)| i)
+        else {
+            return Err(unexpected(done));
+        };
+        let header = &inner["select".len()..header_end];
+        let Some(name) = header.split_whitespace().next() else {
+            return Err(unexpected(&inner[header_end..header_end + 1]));
+        };
+        let after_name = &header[offset(name) - offset(header) + name.len()..];
+        let mut words = Cmd::parse_args(after_name);
+        let words = match words.first().and_then(|word| word.as_literal()) {
+            Some("in") => Some(words.split_off(1)),
+            _ if words.is_empty() => None,
+            _ => {
+                return Err(unexpected(
+                    after_name.split_whitespace().next().unwrap_or(done),
+                ))
+            }
+        };
+
+        // Then `do`, and the commands up to `done`
+        let rest = inner[header_end + 1..].trim_start();
+        if !rest.starts_with("do") || !rest[2..].starts_with(is_delimiter) {
+            let token = match rest.find(is_delimiter) {
+                _ if rest.is_empty() => done,
+                Some(0) => &rest[..1],
+                Some(end) => &rest[..end],
+                None => rest,
+            };
+            return Err(unexpected(token));
+        }
+        let body = &rest[2..];
+        if body.trim().is_empty() {
+            return Err(unexpected(done));
+        }
+        Ok(Select {
+            name: name.to_string(),
+            words,
+            body: CommandList::parse(input, body)?,
+        })
+    }
+
+    /// Shows the menu and $PS3, then runs the body for each line read
+    /// with $REPLY set to it and the variable to the word it numbers, or
+    /// to nothing. An empty line shows the menu again. The loop ends at
+    /// the end of input or on a variable it may not set, with status 1,
+    /// or with `break`.
+    pub fn run(&mut self, env: &mut ShellEnv) -> Result<(), ShellError> {
+        if !is_valid_name(&self.name) {
+            errln!("cc-shell: `{}': not a valid identifier", self.name);
+            env.last_status = 1;
+            return Ok(());
+        }
+        let items = match &self.words {
+            Some(words) => expand::expand_words(words, env).map_err(ShellError::Expansion)?,
+            None => env.positional.clone(),
+        };
+        env.last_status = 0;
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        env.loops += 1;
+        let mut show_menu = true;
+        let result = loop {
+            if show_menu {
+                print_menu(&items, env);
+            }
+            err!("{}", env.get("PS3").unwrap_or_else(|| "#? ".into()));
+            let line = match fds::read_line_unbuffered() {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    // To stdout, as in bash
+                    outln!();
+                    env.last_status = 1;
+                    break Ok(());
+                }
+                Err(e) => break Err(ShellError::io("select", e)),
+            };
+            show_menu = line.trim().is_empty();
+            if show_menu {
+                continue;
+            }
+            let chosen = line
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| items.get(n.checked_sub(1)?));
+            let writable = env
+                .check_writable("REPLY")
+                .and_then(|()| env.check_writable(&self.name));
+            if let Err(e) = writable {
+                errln!("cc-shell: {}", e);
+                env.last_status = 1;
+                break Ok(());
+            }
+            env.set("REPLY", &line);
+            env.set(&self.name, chosen.map_or("", |word| word));
+            self.body.execute(env);
+            if env.exit_requested {
+                break Ok(());
+            }
+            if env.breaking > 0 {
+                env.breaking -= 1;
+                break Ok(());
+            }
+        };
+        env.loops -= 1;
+        result
+    }
+
+    /// Describes the loop with its words expanded, for `--dry-run`.
+    pub fn describe(&mut self, env: &mut ShellEnv) -> Result<serde_json::Value, ShellError> {
+        let words = match &self.words {
+            Some(words) => Some(expand::expand_words(words, env).map_err(ShellError::Expansion)?),
+            None => None,
+        };
+        Ok(json!({
+            "name": self.name,
+            "words": words,
+            "body": self.body.describe(env)?,
+        }))
+    }
+}
+
+// Prints the numbered words to stderr in columns as wide as $COLUMNS or
+// the terminal. A menu that would fit on one line gets a line per word.
+fn print_menu(items: &[String], env: &ShellEnv) {
+    let digits = items.len().to_string().len();
+    let entries: Vec<String> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| format!("{:>digits$}) {}", i + 1, item))
+        .collect();
+    let mut lines = columns::layout(&entries, columns::width(env.get("COLUMNS").as_deref(), 2));
+    if lines.len() == 1 {
+        lines = entries;
+    }
+    for line in lines {
+        errln!("{}", line);
+    }
+}
@@ -0,0 +1,139 @@
+use super::env::ShellEnv;
+use super::expand::{Word, WordPart};
+use super::list::CommandList;
+use super::{pipe, wait_pid};
+use crate::error::ShellError;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+
+/// A running `<(cmd)` or `>(cmd)` helper. Dropping it closes the shell's
+/// end of the pipe and reaps the helper.
+pub struct Substitution {
+    fd: Option<OwnedFd>,
+    pid: libc::pid_t,
+}
+
+impl Drop for Substitution {
+    fn drop(&mut self) {
+        // Closing our end first lets a `>(cmd)` helper see end of input
+        self.fd.take();
+        wait_pid(self.pid);
+    }
+}
+
+/// Starts the process substitutions in `words` and returns the words with
+/// each one replaced by the `/dev/fd/N` path of its pipe.
+pub fn start(
+    words: &[Word],
+    env: &mut ShellEnv,
+) -> Result<(Vec<Word>, Vec<Substitution>), ShellError> {
+    let mut running = Vec::new();
+    let mut result = Vec::with_capacity(words.len());
+    for word in words {
+        let mut parts = Vec::with_capacity(word.parts.len());
+        for part in &word.parts {
+            match part {
+                WordPart::ProcSubst { command, output } => {
+                    let sub = spawn(command, *output, env, &running)?;
+                    let fd = sub.fd.as_ref().map_or(-1, |fd| fd.as_raw_fd());
+                    parts.push(WordPart::Quoted(format!("/dev/fd/{}", fd)));
+                    running.push(sub);
+                }
+                other => parts.push(other.clone()),
+            }
+        }
+        result.push(Word { parts });
+    }
+    Ok((result, running))
+}
+
+// Forks a helper running `command` with its stdout (or, for `>(cmd)`, its
+// stdin) connected to a pipe. The shell keeps the other end open.
+fn spawn(
+    command: &str,
+    output: bool,
+    env: &mut ShellEnv,
+    running: &[Substitution],
+) -> Result<Substitution, ShellError> {
+    let (read, write) = pipe().map_err(|e| ShellError::io("pipe", e))?;
+    let (ours, theirs) = if output { (write, read) } else { (read, write) };
+    match unsafe { libc::fork() } {
+        -1 => Err(ShellError::io("fork", std::io::Error::last_os_error())),
+        0 => {
+            // Child: don't hold on to the pipes of other substitutions
+            drop(ours);
+            for sub in running {
+                if let Some(fd) = &sub.fd {
+                    unsafe { libc::close(fd.as_raw_fd()) };
+                }
+            }
+            unsafe {
+                libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+                libc::dup2(theirs.as_raw_fd(), if output { 0 } else { 1 });
+            }
+            drop(theirs);
+            run_subshell(command, env)
+        }
+        pid => {
+            drop(theirs);
+            // The command opens the pipe through /dev/fd/N, which must
+            // stay open across its exec
+            unsafe { libc::fcntl(ours.as_raw_fd(), libc::F_SETFD, 0) };
+            Ok(Substitution {
+                fd: Some(ours),
+                pid,
+            })
+        }
+    }
+}
+
+/// Command substitution: runs `command` in a subshell and returns its
+/// output without trailing newlines. Its exit status is kept in
+/// `env.subst_status`.
+pub fn command_output(command: &str, env: &mut ShellEnv) -> Result<String, String> {
+    let (read, write) = pipe().map_err(|e| e.to_string())?;
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().to_string()),
+        0 => {
+            drop(read);
+            unsafe {
+                libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+                libc::dup2(write.as_raw_fd(), 1);
+            }
+            drop(write);
+            run_subshell(command, env)
+        }
+        pid => {
+            drop(write);
+            let mut output = Vec::new();
+            let read_result = File::from(read).read_to_end(&mut output);
+            let (status, times) = wait_pid(pid);
+            env.child_times += times;
+            env.subst_status = Some(status);
+            read_result.map_err(|e| e.to_string())?;
+            let mut output = String::from_utf8_lossy(&output).into_owned();
+            output.truncate(output.trim_end_matches('\n').len());
+            Ok(output)
+        }
+    }
+}
+
+// Runs the lines of `command` in a forked child and exits with the status
+// of the last one
+fn run_subshell(command: &str, env: &mut ShellEnv) -> ! {
+    for line in command.lines().filter(|line| !line.trim().is_empty()) {
+        if env.exit_requested {
+            break;
+        }
+        match CommandList::new(line) {
+            Ok(mut list) => list.execute(env),
+            Err(e) => {
+                e.report();
+                env.last_status = e.status();
+            }
+        }
+    }
+    let _ = std::io::stdout().flush();
+    unsafe { libc::_exit(env.last_status) }
+}
@@ -0,0 +1,44 @@
+//! Lists laid out in columns, down then across as `ls` does, for
+//! completion candidates and `select` menus.
+
+use std::os::fd::RawFd;
+use unicode_width::UnicodeWidthStr;
+
+/// The width to lay lists out in: $COLUMNS if it is a number, else the
+/// width of the terminal on `fd`, else 80.
+pub fn width(columns: Option<&str>, fd: RawFd) -> usize {
+    if let Some(columns) = columns.and_then(|c| c.trim().parse::<usize>().ok()) {
+        if columns > 0 {
+            return columns;
+        }
+    }
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+        return size.ws_col as usize;
+    }
+    80
+}
+
+/// Lays `items` out in as many columns as fit in `width` terminal cells,
+/// two spaces apart, returning the lines. Items too wide for two columns
+/// get a line each. Wide characters such as CJK ones take two cells.
+pub fn layout(items: &[String], width: usize) -> Vec<String> {
+    let longest = items.iter().map(|item| item.width()).max();
+    let Some(longest) = longest else {
+        return Vec::new();
+    };
+    let column_width = longest + 2;
+    let columns = (width / column_width).max(1);
+    let rows = items.len().div_ceil(columns);
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+            for (column, item) in items.iter().skip(row).step_by(rows).enumerate() {
+                let padding = (column * column_width).saturating_sub(line.width());
+                line.extend(std::iter::repeat(' ').take(padding));
+                line.push_str(item);
+            }
+            line
+        })
+        .collect()
+}
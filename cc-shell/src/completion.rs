@@ -1,4 +1,5 @@
 use crate::cmdline::executable::Executable;
+use crate::columns;
 use rustyline::completion::{Completer, Pair};
 use rustyline::history::{History, SearchDirection};
 use std::cell::RefCell;
@@ -6,26 +7,121 @@ use std::env;
 use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
 
 // Global state for TAB completion
 thread_local! {
-    static LAST_WORD: RefCell<Option<String>> = RefCell::new(None);
-    static TAB_COUNT: RefCell<usize> = RefCell::new(0);
+    static LAST_WORD: RefCell<Option<String>> = const { RefCell::new(None) };
+    static TAB_COUNT: RefCell<usize> = const { RefCell::new(0) };
+}
+
+/// How candidates are compared with the word typed: exactly, or with
+/// `completion-ignore-case` ignoring the case of the letters that have
+/// one in the locale, only ASCII ones unless it is a UTF-8 one.
+#[derive(Clone, Copy)]
+pub enum CaseMatch {
+    Exact,
+    Ascii,
+    Unicode,
+}
+
+impl CaseMatch {
+    /// The comparison for `ignore_case` in `locale`, the first of $LC_ALL,
+    /// $LC_CTYPE and $LANG that is set.
+    pub fn new(ignore_case: bool, locale: Option<&str>) -> Self {
+        let utf8 = locale.is_some_and(|locale| {
+            let codeset = locale.split_once('.').map_or("", |(_, codeset)| codeset);
+            let codeset = codeset.split('@').next().unwrap_or_default();
+            codeset.eq_ignore_ascii_case("utf-8") || codeset.eq_ignore_ascii_case("utf8")
+        });
+        match (ignore_case, utf8) {
+            (false, _) => CaseMatch::Exact,
+            (true, false) => CaseMatch::Ascii,
+            (true, true) => CaseMatch::Unicode,
+        }
+    }
+
+    // Whether two strings are the same but for case
+    fn matches(self, a: &str, b: &str) -> bool {
+        match self {
+            CaseMatch::Exact => a == b,
+            CaseMatch::Ascii => a.eq_ignore_ascii_case(b),
+            CaseMatch::Unicode => a
+                .chars()
+                .flat_map(char::to_lowercase)
+                .eq(b.chars().flat_map(char::to_lowercase)),
+        }
+    }
+
+    // Whether `s` starts with the whole graphemes of `prefix`, so that
+    // `e` doesn't match the `e` of an `e` with a combining accent
+    fn starts_with(self, s: &str, prefix: &str) -> bool {
+        let mut graphemes = s.graphemes(true);
+        prefix
+            .graphemes(true)
+            .all(|g| graphemes.next().is_some_and(|h| self.matches(g, h)))
+    }
 }
 
 pub struct ShellCompleter {
     history: Option<Box<dyn History>>,
+    // `bind 'set completion-ignore-case on'`, in the shell's locale
+    case: CaseMatch,
+    // $COLUMNS, if set, for the width of listings
+    columns: Option<String>,
+    // `bind 'set completion-query-items N'`
+    query_items: i64,
 }
 
 impl ShellCompleter {
     pub fn new() -> Self {
-        ShellCompleter { history: None }
+        ShellCompleter {
+            history: None,
+            case: CaseMatch::Exact,
+            columns: None,
+            query_items: 100,
+        }
     }
 
     pub fn set_history(&mut self, history: Box<dyn History>) {
         self.history = Some(history);
     }
 
+    pub fn set_case(&mut self, case: CaseMatch) {
+        self.case = case;
+    }
+
+    /// Sets how candidates are listed: as wide as `columns`, else the
+    /// terminal, asking first if there are at least `query_items`.
+    pub fn set_listing(&mut self, columns: Option<String>, query_items: i64) {
+        self.columns = columns;
+        self.query_items = query_items;
+    }
+
+    // Prints the candidates in columns below the line, then the prompt
+    // and the line again. Many candidates are only listed if the user
+    // says so.
+    fn list_matches(&self, matches: &[String], line: &str) {
+        println!();
+        if self.query_items >= 0 && matches.len() as i64 >= self.query_items {
+            print!("Display all {} possibilities? (y/n)", matches.len());
+            io::stdout().flush().unwrap_or(());
+            let confirmed = confirm();
+            println!();
+            if !confirmed {
+                print!("$ {}", line);
+                io::stdout().flush().unwrap_or(());
+                return;
+            }
+        }
+        let width = columns::width(self.columns.as_deref(), 1);
+        for row in columns::layout(matches, width) {
+            println!("{}", row);
+        }
+        print!("$ {}", line);
+        io::stdout().flush().unwrap_or(());
+    }
+
     fn get_builtin_commands(&self) -> Vec<String> {
         Executable::get_builtin_str()
     }
@@ -93,7 +189,10 @@ impl Completer for ShellCompleter {
 
             // Add builtin commands
             let builtin_commands = self.get_builtin_commands();
-            for cmd in builtin_commands.iter().filter(|cmd| cmd.starts_with(word)) {
+            for cmd in builtin_commands
+                .iter()
+                .filter(|cmd| self.case.starts_with(cmd, word))
+            {
                 seen_commands.insert(cmd.clone());
                 matches.push(Pair {
                     display: cmd.clone(),
@@ -103,7 +202,10 @@ impl Completer for ShellCompleter {
 
             // Add executables from PATH
             let path_executables = self.get_executables_from_path();
-            for cmd in path_executables.iter().filter(|cmd| cmd.starts_with(word)) {
+            for cmd in path_executables
+                .iter()
+                .filter(|cmd| self.case.starts_with(cmd, word))
+            {
                 // Skip if already added as a builtin or already seen
                 if !seen_commands.contains(cmd) {
                     seen_commands.insert(cmd.clone());
@@ -113,21 +215,25 @@ impl Completer for ShellCompleter {
                     });
                 }
             }
-            
+
             // Sort matches alphabetically
             matches.sort_by(|a, b| a.display.cmp(&b.display));
 
             // When we have multiple matches, find the longest common prefix
             if matches.len() > 1 {
-                let display_values: Vec<&str> = matches.iter().map(|pair| pair.display.as_str()).collect();
-                if let Some(common_prefix) = find_longest_common_prefix(&display_values) {
-                    // If the common prefix is longer than what the user typed
-                    if common_prefix.len() > word.len() {
+                let display_values: Vec<&str> =
+                    matches.iter().map(|pair| pair.display.as_str()).collect();
+                if let Some(common_prefix) = find_longest_common_prefix(&display_values, self.case)
+                {
+                    // If the common prefix is longer than what the user typed,
+                    // or differs from it in case
+                    if common_prefix != word {
                         // Check if there are any further common prefixes by checking if
-                        // there exists a match that starts with the common prefix but is longer
-                        let has_further_common_prefix = display_values.iter()
-                            .any(|val| val.len() > common_prefix.len() && val.starts_with(&common_prefix));
-                        
+                        // there exists a match that is longer than the common prefix
+                        let has_further_common_prefix = display_values
+                            .iter()
+                            .any(|val| !self.case.matches(val, &common_prefix));
+
                         // Create match with appropriate replacement
                         let new_match = Pair {
                             display: common_prefix.clone(),
@@ -139,7 +245,7 @@ impl Completer for ShellCompleter {
                                 format!("{} ", common_prefix)
                             },
                         };
-                        
+
                         // Reset tab count if we've added a space (no more completions)
                         if !has_further_common_prefix {
                             LAST_WORD.with(|last_word_cell| {
@@ -149,7 +255,7 @@ impl Completer for ShellCompleter {
                                 });
                             });
                         }
-                        
+
                         return Ok((word_start, vec![new_match]));
                     }
                 }
@@ -200,12 +306,9 @@ impl Completer for ShellCompleter {
                 });
 
                 if should_list {
-                    println!();
                     let display_matches: Vec<String> =
                         matches.iter().map(|pair| pair.display.clone()).collect();
-                    println!("{}", display_matches.join("  "));
-                    print!("$ {}", line);
-                    io::stdout().flush().unwrap_or(());
+                    self.list_matches(&display_matches, line);
 
                     // Reset the tab count after displaying
                     LAST_WORD.with(|last_word_cell| {
@@ -272,7 +375,7 @@ impl Completer for ShellCompleter {
                     if matches {
                         // Extract the next argument from history as a suggestion
                         let next_arg = entry_words[words.len()];
-                        if next_arg.starts_with(word) && !seen_args.contains(next_arg) {
+                        if self.case.starts_with(next_arg, word) && !seen_args.contains(next_arg) {
                             seen_args.insert(next_arg.to_string());
                             suggestions.push(Pair {
                                 display: next_arg.to_string(),
@@ -293,55 +396,153 @@ impl Completer for ShellCompleter {
     }
 }
 
+// Reads keys from the terminal until one answers yes (`y` or space) or
+// no (`n` or DEL), ringing the bell at others
+fn confirm() -> bool {
+    let mut key = [0u8; 1];
+    loop {
+        if unsafe { libc::read(0, key.as_mut_ptr().cast(), 1) } != 1 {
+            return false;
+        }
+        match key[0] {
+            b'y' | b'Y' | b' ' => return true,
+            b'n' | b'N' | 0x7f => return false,
+            _ => {
+                print!("\x07");
+                io::stdout().flush().unwrap_or(());
+            }
+        }
+    }
+}
+
+// The word around the cursor at byte `pos` and where it starts. Words
+// are separated by whitespace and made of whole graphemes; a `pos` inside
+// one counts as its start.
 fn find_word_at_pos(line: &str, pos: usize) -> (usize, &str) {
-    let pos = std::cmp::min(pos, line.len());
+    let graphemes: Vec<(usize, &str)> = line.grapheme_indices(true).collect();
+    let is_space = |g: &str| g.chars().all(char::is_whitespace);
+    let cursor = graphemes.partition_point(|&(i, g)| i + g.len() <= pos);
+
+    let start = graphemes[..cursor]
+        .iter()
+        .rfind(|(_, g)| is_space(g))
+        .map_or(0, |(i, g)| i + g.len());
+    let end = graphemes[cursor..]
+        .iter()
+        .find(|(_, g)| is_space(g))
+        .map_or(line.len(), |&(i, _)| i);
 
-    let start = line[..pos]
-        .rfind(|c: char| c.is_whitespace())
-        .map(|i| i + 1)
-        .unwrap_or(0);
+    (start, &line[start..end])
+}
 
-    let end = line[pos..]
-        .find(|c: char| c.is_whitespace())
-        .map(|i| i + pos)
-        .unwrap_or(line.len());
+/// Finds the longest common prefix among a collection of strings, in
+/// whole graphemes compared as `case` says, as it is in the first one.
+/// Returns None if the collection is empty or the prefix would be.
+fn find_longest_common_prefix(strings: &[&str], case: CaseMatch) -> Option<String> {
+    let (first, rest) = strings.split_first()?;
+
+    // The length in bytes of the prefix of `first` common to all so far
+    let mut len = first.len();
+    for s in rest {
+        let common: usize = first[..len]
+            .graphemes(true)
+            .zip(s.graphemes(true))
+            .take_while(|(a, b)| case.matches(a, b))
+            .map(|(a, _)| a.len())
+            .sum();
+        len = common;
+        if len == 0 {
+            return None;
+        }
+    }
 
-    (start, &line[start..end])
+    Some(first[..len].to_string())
 }
 
-/// Finds the longest common prefix among a collection of strings.
-/// Returns None if the collection is empty.
-fn find_longest_common_prefix(strings: &[&str]) -> Option<String> {
-    if strings.is_empty() {
-        return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_at_the_cursor() {
+        assert_eq!(find_word_at_pos("", 0), (0, ""));
+        assert_eq!(find_word_at_pos("echo hello", 10), (5, "hello"));
+        // The whole word, whether the cursor is inside it or at its start
+        assert_eq!(find_word_at_pos("echo hello", 7), (5, "hello"));
+        assert_eq!(find_word_at_pos("echo hello", 5), (5, "hello"));
+        assert_eq!(find_word_at_pos("echo ", 5), (5, ""));
+        assert_eq!(find_word_at_pos("ls  -l", 3), (3, ""));
     }
-    if strings.len() == 1 {
-        return Some(strings[0].to_string());
+
+    #[test]
+    fn words_of_wide_and_combining_characters() {
+        let line = "cat cafe\u{301}.txt";
+        assert_eq!(find_word_at_pos(line, line.len()), (4, "cafe\u{301}.txt"));
+        // A cursor between a letter and its accent is inside the word
+        assert_eq!(find_word_at_pos(line, 8), (4, "cafe\u{301}.txt"));
+        assert_eq!(find_word_at_pos("ls 日本語", 6), (3, "日本語"));
+        // The ideographic space separates words too
+        assert_eq!(find_word_at_pos("日本\u{3000}語", 12), (9, "語"));
     }
 
-    // Start with the first string as the initial prefix
-    let mut prefix = strings[0].to_string();
-
-    // Compare with each remaining string
-    for s in &strings[1..] {
-        // Find the common characters between current prefix and this string
-        let mut common_chars = Vec::new();
-        
-        for (a, b) in prefix.chars().zip(s.chars()) {
-            if a != b {
-                break;
-            }
-            common_chars.push(a);
-        }
-        
-        // Update prefix to the common part only
-        prefix = common_chars.into_iter().collect();
-        
-        // If no common prefix, exit early
-        if prefix.is_empty() {
-            return None;
-        }
+    #[test]
+    fn common_prefixes() {
+        let lcp = |strings: &[&str]| find_longest_common_prefix(strings, CaseMatch::Exact);
+        assert_eq!(lcp(&[]), None);
+        assert_eq!(lcp(&["abc"]).as_deref(), Some("abc"));
+        assert_eq!(lcp(&["abc", "abd", "ab"]).as_deref(), Some("ab"));
+        assert_eq!(lcp(&["abc", "xyz"]), None);
+        assert_eq!(lcp(&["日本語", "日本人"]).as_deref(), Some("日本"));
+        // An accented letter is not its base letter
+        assert_eq!(lcp(&["cafe\u{301}", "cafe"]).as_deref(), Some("caf"));
+        assert_eq!(lcp(&["e\u{301}a", "e\u{300}a"]), None);
     }
 
-    Some(prefix)
+    #[test]
+    fn common_prefixes_ignoring_case() {
+        let lcp = find_longest_common_prefix;
+        assert_eq!(lcp(&["Makefile", "makedeps"], CaseMatch::Exact), None);
+        // The prefix is as it is in the first string
+        assert_eq!(
+            lcp(&["Makefile", "makedeps"], CaseMatch::Ascii).as_deref(),
+            Some("Make")
+        );
+        assert_eq!(lcp(&["Éclair", "éclat"], CaseMatch::Ascii), None);
+        assert_eq!(
+            lcp(&["Éclair", "éclat"], CaseMatch::Unicode).as_deref(),
+            Some("Écla")
+        );
+        assert_eq!(
+            lcp(&["ÉCOLE", "école"], CaseMatch::Unicode).as_deref(),
+            Some("ÉCOLE")
+        );
+    }
+
+    #[test]
+    fn case_matching() {
+        assert!(matches!(
+            CaseMatch::new(false, Some("en_US.UTF-8")),
+            CaseMatch::Exact
+        ));
+        assert!(matches!(CaseMatch::new(true, None), CaseMatch::Ascii));
+        assert!(matches!(CaseMatch::new(true, Some("C")), CaseMatch::Ascii));
+        assert!(matches!(
+            CaseMatch::new(true, Some("en_US.utf8")),
+            CaseMatch::Unicode
+        ));
+        assert!(matches!(
+            CaseMatch::new(true, Some("de_DE.UTF-8@euro")),
+            CaseMatch::Unicode
+        ));
+
+        assert!(CaseMatch::Exact.starts_with("README", "READ"));
+        assert!(!CaseMatch::Exact.starts_with("README", "read"));
+        assert!(CaseMatch::Ascii.starts_with("README", "read"));
+        assert!(!CaseMatch::Ascii.starts_with("Ärger", "är"));
+        assert!(CaseMatch::Unicode.starts_with("Ärger", "är"));
+        assert!(!CaseMatch::Unicode.starts_with("READ", "README"));
+        // Whole graphemes: `e` is not the start of an accented `e`
+        assert!(!CaseMatch::Exact.starts_with("e\u{301}t\u{e9}", "e"));
+        assert!(CaseMatch::Unicode.starts_with("日本語", "日本"));
+    }
 }
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::io;
+use thiserror::Error;
+use unicode_width::UnicodeWidthChar;
+
+/// Errors reported by the shell. They are printed bash-style, prefixed
+/// with the shell's name: `cc-shell: cmd: message`.
+#[derive(Debug, Error)]
+pub enum ShellError {
+    /// A command line that cannot be parsed. `column` is the byte offset
+    /// of the offending token in `text`, the line as written.
+    #[error("{}{message}", LinePrefix(*.line))]
+    Syntax {
+        message: String,
+        text: String,
+        line: Option<usize>,
+        column: usize,
+    },
+
+    #[error("{0}: command not found")]
+    CommandNotFound(String),
+
+    /// A command that was found but could not be run.
+    #[error("{command}: {}", os_message(.error))]
+    NotExecutable {
+        command: String,
+        #[source]
+        error: io::Error,
+    },
+
+    /// A redirection that could not be applied, e.g. `out: Permission denied`.
+    #[error("{0}")]
+    Redirect(String),
+
+    /// A failed expansion, such as a bad subscript or division by zero.
+    #[error("{0}")]
+    Expansion(String),
+
+    /// Something a restricted shell doesn't allow, e.g.
+    /// `/bin/sh: restricted: cannot specify `/' in command names`.
+    #[error("{what}: restricted{}", .reason.map(|r| format!(": {}", r)).unwrap_or_default())]
+    Restricted {
+        what: String,
+        reason: Option<&'static str>,
+    },
+
+    #[error("{context}: {}", os_message(.error))]
+    Io {
+        context: String,
+        #[source]
+        error: io::Error,
+    },
+}
+
+impl ShellError {
+    pub fn syntax(message: impl Into<String>, text: &str, column: usize) -> Self {
+        ShellError::Syntax {
+            message: message.into(),
+            text: text.to_string(),
+            line: None,
+            column,
+        }
+    }
+
+    pub fn restricted(what: impl Into<String>, reason: Option<&'static str>) -> Self {
+        ShellError::Restricted {
+            what: what.into(),
+            reason,
+        }
+    }
+
+    pub fn io(context: impl Into<String>, error: io::Error) -> Self {
+        ShellError::Io {
+            context: context.into(),
+            error,
+        }
+    }
+
+    /// Sets the script line a syntax error was found on, given the line
+    /// its command starts on.
+    pub fn at_line(mut self, number: usize) -> Self {
+        if let ShellError::Syntax {
+            line, text, column, ..
+        } = &mut self
+        {
+            let before = text.get(..*column).unwrap_or(text);
+            *line = Some(number + before.matches('\n').count());
+        }
+        self
+    }
+
+    /// The exit status the error causes: 2 for syntax errors, 127 for a
+    /// command that isn't found, 126 for one that can't be run, else 1.
+    pub fn status(&self) -> i32 {
+        match self {
+            ShellError::Syntax { .. } => 2,
+            ShellError::CommandNotFound(_) => 127,
+            ShellError::NotExecutable { .. } => 126,
+            _ => 1,
+        }
+    }
+
+    /// Prints the error to stderr. Syntax errors are followed by the line
+    /// and a caret under the offending token.
+    pub fn report(&self) {
+        errln!("cc-shell: {}", self);
+        if let ShellError::Syntax { text, column, .. } = self {
+            // Of a command spanning lines, only the one with the token
+            let before = text.get(..*column).unwrap_or(text);
+            let start = before.rfind('\n').map_or(0, |i| i + 1);
+            let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+            let line = &text[start..end];
+            errln!("{}", line);
+            errln!("{}^", caret_indent(line, before.len() - start));
+        }
+    }
+}
+
+struct LinePrefix(Option<usize>);
+
+impl fmt::Display for LinePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(line) => write!(f, "line {}: ", line),
+            None => Ok(()),
+        }
+    }
+}
+
+// Whitespace as wide as `text` up to `column`; tabs are kept so that
+// the caret lines up whatever the tab width, and wide characters such as
+// CJK ones take two columns while combining marks take none
+fn caret_indent(text: &str, column: usize) -> String {
+    text.get(..column)
+        .unwrap_or(text)
+        .chars()
+        .map(|c| match c {
+            '\t' => "\t".to_string(),
+            c => " ".repeat(c.width().unwrap_or(0)),
+        })
+        .collect()
+}
+
+/// The description of an I/O error without the ` (os error N)` suffix,
+/// as shells print it: `No such file or directory`.
+pub fn os_message(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.rfind(" (os error ") {
+        Some(end) if error.raw_os_error().is_some() => message[..end].to_string(),
+        _ => message,
+    }
+}
+
+/// Prints a diagnostic from builtin `cmd`: `cc-shell: cmd: message`.
+pub fn warn(cmd: &str, message: impl fmt::Display) {
+    errln!("cc-shell: {}: {}", cmd, message);
+}
@@ -0,0 +1,360 @@
+pub mod search;
+
+use crate::error::{os_message, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A command line that was run, with where, when and how it ended.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub cwd: String,
+    // Seconds since the epoch when the command started
+    pub timestamp: u64,
+    pub status: i32,
+}
+
+/// A previous command ranked for the Ctrl-R search.
+pub struct Ranked {
+    pub command: String,
+    pub score: f64,
+}
+
+/// How many entries are kept unless `$HISTFILESIZE` says otherwise.
+pub const DEFAULT_SIZE: usize = 10_000;
+
+/// The shell's command history, kept as JSON lines in `$HISTFILE`
+/// (by default ~/.ccshell_history) so that it survives across sessions.
+/// The file is readable by its owner only.
+pub struct ShellHistory {
+    entries: Vec<HistoryEntry>,
+    file: Option<PathBuf>,
+    // The most entries kept, in memory and in the file
+    size: usize,
+}
+
+impl Default for ShellHistory {
+    fn default() -> Self {
+        ShellHistory {
+            entries: Vec::new(),
+            file: None,
+            size: DEFAULT_SIZE,
+        }
+    }
+}
+
+impl ShellHistory {
+    /// Reads the history file, skipping lines that cannot be parsed. Only
+    /// the latest `size` entries are kept, and a longer file is cut down
+    /// to them.
+    pub fn load(file: Option<PathBuf>, size: usize) -> Self {
+        let mut entries: Vec<HistoryEntry> = Vec::new();
+        if let Some(text) = file.as_ref().and_then(|f| std::fs::read_to_string(f).ok()) {
+            entries = text
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect();
+        }
+        if entries.len() > size {
+            entries.drain(..entries.len() - size);
+            if let Some(file) = &file {
+                if let Err(e) = rewrite(file, &entries) {
+                    warn(
+                        "history",
+                        format_args!("{}: {}", file.display(), os_message(&e)),
+                    );
+                }
+            }
+        }
+        ShellHistory {
+            entries,
+            file,
+            size,
+        }
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Adds an entry and appends it to the history file.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        if let Some(file) = &self.file {
+            let appended = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .mode(0o600)
+                .open(file)
+                .and_then(|mut f| {
+                    let line = serde_json::to_string(&entry).map_err(std::io::Error::other)?;
+                    writeln!(f, "{}", line)
+                });
+            if let Err(e) = appended {
+                warn(
+                    "history",
+                    format_args!("{}: {}", file.display(), os_message(&e)),
+                );
+                // Don't complain about every command
+                self.file = None;
+            }
+        }
+        self.entries.push(entry);
+        if self.entries.len() > self.size {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Previous commands matching `query`, best first. Each distinct
+    /// command is ranked by how well it matches and by its frecency: how
+    /// often and how recently it was run. With `cwd`, only commands run
+    /// in that directory are considered.
+    pub fn search(&self, query: &str, cwd: Option<&str>) -> Vec<Ranked> {
+        struct Usage {
+            count: u32,
+            last: u64,
+            status: i32,
+        }
+        let mut usage: HashMap<&str, Usage> = HashMap::new();
+        for entry in &self.entries {
+            if cwd.is_some_and(|cwd| cwd != entry.cwd) {
+                continue;
+            }
+            let u = usage.entry(&entry.command).or_insert(Usage {
+                count: 0,
+                last: 0,
+                status: 0,
+            });
+            u.count += 1;
+            if entry.timestamp >= u.last {
+                u.last = entry.timestamp;
+                u.status = entry.status;
+            }
+        }
+
+        let now = now();
+        let mut ranked: Vec<Ranked> = usage
+            .into_iter()
+            .filter_map(|(command, u)| {
+                let matched = fuzzy_score(query, command)?;
+                let hours = now.saturating_sub(u.last) as f64 / 3600.0;
+                let mut frecency = 10.0 * (1.0 + u.count as f64).ln() + 20.0 / (1.0 + hours);
+                // Commands that failed last time are less likely wanted
+                if u.status != 0 {
+                    frecency /= 2.0;
+                }
+                Some(Ranked {
+                    command: command.to_string(),
+                    score: matched as f64 + frecency,
+                })
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.command.cmp(&b.command)));
+        ranked
+    }
+}
+
+// Replaces the history file with `entries`, by way of a new file so that
+// a failure leaves the old one alone
+fn rewrite(file: &Path, entries: &[HistoryEntry]) -> std::io::Result<()> {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".new");
+    let new = PathBuf::from(name);
+    let written = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&new)
+        .and_then(|mut f| {
+            for entry in entries {
+                let line = serde_json::to_string(entry).map_err(std::io::Error::other)?;
+                writeln!(f, "{}", line)?;
+            }
+            Ok(())
+        })
+        .and_then(|()| std::fs::rename(&new, file));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&new);
+    }
+    written
+}
+
+/// Seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// Scores `candidate` for containing the characters of `query` in order,
+// ignoring case. Runs of consecutive characters and matches at the start
+// of a word score higher; gaps cost a little. None if it doesn't match.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut prev: Option<usize> = None;
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_lowercase().next().unwrap_or(q);
+        let found =
+            (pos..candidate.len()).find(|&i| candidate[i].to_lowercase().next() == Some(q))?;
+        score += 16;
+        match prev {
+            Some(p) if p + 1 == found => score += 16,
+            Some(p) => score -= (found - p - 1).min(8) as i64,
+            None => score -= found.min(8) as i64,
+        }
+        let at_word_start = found == 0 || !candidate[found - 1].is_alphanumeric();
+        if at_word_start {
+            score += 8;
+        }
+        prev = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn entry(command: &str, cwd: &str, age: u64, status: i32) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            cwd: cwd.to_string(),
+            timestamp: now() - age,
+            status,
+        }
+    }
+
+    fn history(entries: Vec<HistoryEntry>) -> ShellHistory {
+        ShellHistory {
+            entries,
+            ..ShellHistory::default()
+        }
+    }
+
+    fn commands(ranked: Vec<Ranked>) -> Vec<String> {
+        ranked.into_iter().map(|r| r.command).collect()
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ccshell-history-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(name);
+        let _ = std::fs::remove_file(&file);
+        file
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        assert!(fuzzy_score("gco", "git checkout").is_some());
+        assert_eq!(fuzzy_score("GIT", "git"), fuzzy_score("git", "GIT"));
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("xyz", "git status"), None);
+        assert_eq!(fuzzy_score("tig", "git"), None);
+        // Whitespace in the query is ignored
+        assert_eq!(
+            fuzzy_score("g s", "git status"),
+            fuzzy_score("gs", "git status")
+        );
+    }
+
+    #[test]
+    fn fuzzy_prefers_runs_and_word_starts() {
+        let score = |q, c| fuzzy_score(q, c).unwrap();
+        assert!(score("make", "make test") > score("make", "mk a k e"));
+        assert!(score("st", "git status") > score("st", "git last"));
+        assert!(score("ls", "ls") > score("ls", "echo ls"));
+        // Gaps cost at most 8 each
+        assert_eq!(
+            score("ab", &format!("a{}b", "-".repeat(20))),
+            score("ab", &format!("a{}b", "-".repeat(8)))
+        );
+    }
+
+    #[test]
+    fn frecency_ranking() {
+        let day = 24 * 3600;
+        let h = history(vec![
+            entry("cargo build", "/src", 3 * day, 0),
+            entry("cargo build", "/src", 2 * day, 0),
+            entry("cargo build", "/src", day, 0),
+            entry("cargo test", "/src", day, 0),
+        ]);
+        assert_eq!(
+            commands(h.search("cargo", None)),
+            ["cargo build", "cargo test"]
+        );
+
+        // A command just run beats one run more often long ago
+        let h = history(vec![
+            entry("cargo build", "/src", 30 * day, 0),
+            entry("cargo build", "/src", 30 * day, 0),
+            entry("cargo test", "/src", 0, 0),
+        ]);
+        assert_eq!(
+            commands(h.search("cargo", None)),
+            ["cargo test", "cargo build"]
+        );
+    }
+
+    #[test]
+    fn failures_rank_lower() {
+        let h = history(vec![
+            entry("make all", "/src", 60, 2),
+            entry("make alt", "/src", 60, 0),
+        ]);
+        assert_eq!(commands(h.search("make", None)), ["make alt", "make all"]);
+
+        // Only the latest run's status counts
+        let h = history(vec![
+            entry("make all", "/src", 120, 2),
+            entry("make all", "/src", 60, 0),
+            entry("make alt", "/src", 60, 0),
+        ]);
+        assert_eq!(commands(h.search("make", None)), ["make all", "make alt"]);
+    }
+
+    #[test]
+    fn filters_and_ties() {
+        let h = history(vec![
+            entry("ls b", "/b", 60, 0),
+            entry("ls a", "/a", 60, 0),
+            entry("ls c", "/a", 60, 0),
+        ]);
+        assert_eq!(commands(h.search("ls", None)), ["ls a", "ls b", "ls c"]);
+        assert_eq!(commands(h.search("ls", Some("/a"))), ["ls a", "ls c"]);
+        assert!(h.search("ls", Some("/elsewhere")).is_empty());
+        assert!(h.search("pwd", None).is_empty());
+    }
+
+    #[test]
+    fn file_is_private_and_capped() {
+        let file = scratch("capped");
+        let mut h = ShellHistory::load(Some(file.clone()), 3);
+        for i in 0..5 {
+            h.record(entry(&format!("echo {}", i), "/", 0, 0));
+        }
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let kept: Vec<&str> = h.entries().iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(kept, ["echo 2", "echo 3", "echo 4"]);
+
+        // The file keeps growing until it is next loaded
+        assert_eq!(std::fs::read_to_string(&file).unwrap().lines().count(), 5);
+        let h = ShellHistory::load(Some(file.clone()), 2);
+        let kept: Vec<&str> = h.entries().iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(kept, ["echo 3", "echo 4"]);
+        assert_eq!(std::fs::read_to_string(&file).unwrap().lines().count(), 2);
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use super::ShellHistory;
+use rustyline::{Cmd, ConditionalEventHandler, Event, EventContext, Movement, RepeatCount};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Matches shown below the search line
+const MAX_SHOWN: usize = 8;
+
+/// Ctrl-R: searches the history for commands fuzzily matching a query,
+/// best first. The chosen command replaces the line being edited.
+///
+/// Keys: type to refine the query, Ctrl-R/Down and Ctrl-P/Up move
+/// through the matches, Ctrl-F toggles showing only commands run in the
+/// current directory, Enter or Tab picks a match and Esc/Ctrl-G cancels.
+pub struct FuzzySearch {
+    pub history: Arc<Mutex<ShellHistory>>,
+    // Initial state of the current-directory filter
+    pub cwd_only: Arc<AtomicBool>,
+    // The shell's working directory
+    pub cwd: Arc<Mutex<PathBuf>>,
+}
+
+impl ConditionalEventHandler for FuzzySearch {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        let history = self.history.lock().ok()?;
+        let cwd = self.cwd.lock().ok()?.to_string_lossy().into_owned();
+        let mut ui = SearchUi {
+            history: &history,
+            cwd,
+            cwd_only: self.cwd_only.load(Ordering::Relaxed),
+            query: ctx.line().to_string(),
+            selected: 0,
+            out: io::stdout(),
+        };
+        let chosen = ui.run();
+        match chosen {
+            Ok(Some(command)) => Some(Cmd::Replace(Movement::WholeLine, Some(command))),
+            _ => Some(Cmd::Repaint),
+        }
+    }
+}
+
+struct SearchUi<'a> {
+    history: &'a ShellHistory,
+    cwd: String,
+    cwd_only: bool,
+    query: String,
+    selected: usize,
+    out: io::Stdout,
+}
+
+// A key read from the terminal, which is in raw mode while editing
+enum Key {
+    Char(char),
+    Backspace,
+    Up,
+    Down,
+    Enter,
+    Cancel,
+    ToggleCwd,
+    ClearQuery,
+    Other,
+}
+
+impl SearchUi<'_> {
+    fn run(&mut self) -> io::Result<Option<String>> {
+        // Make room below the line being edited so drawing never scrolls,
+        // then remember where the cursor is
+        let mut setup = "\n".repeat(MAX_SHOWN + 1);
+        setup.push_str(&format!("\x1b[{}A\x1b7", MAX_SHOWN + 1));
+        self.write(&setup)?;
+
+        let result = loop {
+            let matches: Vec<String> = self
+                .history
+                .search(&self.query, self.cwd_only.then_some(self.cwd.as_str()))
+                .into_iter()
+                .take(MAX_SHOWN)
+                .map(|m| m.command)
+                .collect();
+            self.selected = self.selected.min(matches.len().saturating_sub(1));
+            self.draw(&matches)?;
+
+            match read_key()? {
+                Key::Char(c) => {
+                    self.query.push(c);
+                    self.selected = 0;
+                }
+                Key::Backspace => {
+                    self.query.pop();
+                    self.selected = 0;
+                }
+                Key::ClearQuery => {
+                    self.query.clear();
+                    self.selected = 0;
+                }
+                Key::Down => self.selected += 1,
+                Key::Up => self.selected = self.selected.saturating_sub(1),
+                Key::ToggleCwd => {
+                    self.cwd_only = !self.cwd_only;
+                    self.selected = 0;
+                }
+                Key::Enter => break matches.get(self.selected).cloned(),
+                Key::Cancel => break None,
+                Key::Other => {}
+            }
+        };
+
+        // Clear what was drawn and put the cursor back
+        self.write("\x1b8\x1b[1B\r\x1b[J\x1b8")?;
+        Ok(result)
+    }
+
+    fn draw(&mut self, matches: &[String]) -> io::Result<()> {
+        // Lines longer than the terminal would wrap and spoil the layout
+        let width = terminal_width().saturating_sub(3).max(10);
+        let clip = |s: &str| -> String { s.chars().take(width).collect() };
+
+        let mut screen = String::from("\x1b8\x1b[1B\r\x1b[J");
+        for (i, command) in matches.iter().enumerate() {
+            // Only the first line of a multi-line command is shown
+            let command = clip(command.lines().next().unwrap_or_default());
+            if i == self.selected {
+                screen.push_str(&format!("\x1b[7m> {}\x1b[0m\r\n", command));
+            } else {
+                screen.push_str(&format!("  {}\r\n", command));
+            }
+        }
+        let scope = if self.cwd_only { " [cwd]" } else { "" };
+        let prompt = clip(&format!("fuzzy-search{}: {}", scope, self.query));
+        if matches.is_empty() {
+            screen.push_str("  (no matches)\r\n");
+        }
+        screen.push_str(&prompt);
+        self.write(&screen)
+    }
+
+    fn write(&mut self, s: &str) -> io::Result<()> {
+        self.out.write_all(s.as_bytes())?;
+        self.out.flush()
+    }
+}
+
+fn terminal_width() -> usize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+        size.ws_col as usize
+    } else {
+        80
+    }
+}
+
+fn read_byte() -> io::Result<u8> {
+    let mut byte = 0u8;
+    loop {
+        let n = unsafe { libc::read(0, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        match n {
+            1 => return Ok(byte),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+// Whether more input arrives within a moment, to tell a lone Esc from
+// the start of an escape sequence
+fn input_pending() -> bool {
+    let mut pfd = libc::pollfd {
+        fd: 0,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pfd, 1, 50) > 0 }
+}
+
+fn read_key() -> io::Result<Key> {
+    let byte = read_byte()?;
+    Ok(match byte {
+        b'\r' | b'\n' | b'\t' => Key::Enter,
+        0x03 | 0x07 => Key::Cancel,
+        0x06 => Key::ToggleCwd,
+        0x0e | 0x12 => Key::Down,
+        0x10 => Key::Up,
+        0x15 => Key::ClearQuery,
+        0x08 | 0x7f => Key::Backspace,
+        0x1b if !input_pending() => Key::Cancel,
+        0x1b => {
+            // CSI or SS3 sequence: only the arrows mean anything here
+            let intro = read_byte()?;
+            if intro != b'[' && intro != b'O' {
+                return Ok(Key::Other);
+            }
+            let mut last = read_byte()?;
+            while !(0x40..=0x7e).contains(&last) {
+                last = read_byte()?;
+            }
+            match last {
+                b'A' => Key::Up,
+                b'B' => Key::Down,
+                _ => Key::Other,
+            }
+        }
+        b if b < 0x20 => Key::Other,
+        b => {
+            // The rest of a UTF-8 character
+            let len = match b {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            let mut bytes = vec![b];
+            for _ in 1..len {
+                bytes.push(read_byte()?);
+            }
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Other,
+            }
+        }
+    })
+}
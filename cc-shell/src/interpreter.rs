@@ -0,0 +1,335 @@
+use crate::cmdline::audit::AuditLog;
+use crate::cmdline::builtin_handlers::enterable_dir;
+use crate::cmdline::env::ShellEnv;
+use crate::cmdline::fds::SavedFds;
+use crate::cmdline::list;
+use crate::cmdline::resolve::Policy;
+use crate::cmdline::{RedirectInfo, RedirectMode};
+use crate::error::ShellError;
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+// Held while an interpreter has the standard descriptors
+static RUNNING: Mutex<()> = Mutex::new(());
+
+/// Where one of an interpreter's standard descriptors points.
+#[derive(Debug, Default)]
+pub enum Stdio {
+    /// The process's own descriptor.
+    #[default]
+    Inherit,
+    /// /dev/null.
+    Null,
+    /// Any open file, pipe or socket.
+    Fd(OwnedFd),
+}
+
+impl From<OwnedFd> for Stdio {
+    fn from(fd: OwnedFd) -> Self {
+        Stdio::Fd(fd)
+    }
+}
+
+impl From<File> for Stdio {
+    fn from(file: File) -> Self {
+        Stdio::Fd(file.into())
+    }
+}
+
+/// What [`Interpreter::output`] collected.
+#[derive(Debug)]
+pub struct Output {
+    pub status: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Runs shell code with its own variables, options and working directory.
+/// State persists from one [`run`](Interpreter::run) to the next, as in
+/// an interactive session.
+///
+/// Builtins and the commands the interpreter starts use descriptors 0, 1
+/// and 2: for the duration of each run, the configured [`Stdio`] are
+/// `dup2`ed over the process's own, and put back afterwards. Only one
+/// interpreter in a process can run at a time, so runs on other threads
+/// wait for it to finish, and anything else the program writes to its
+/// standard output meanwhile goes wherever the run's does.
+///
+/// `exec command` would replace the whole process, so here it runs the
+/// command as a child instead and then ends the run with its status, as
+/// [`exited`](Interpreter::exited) reports. `exec` with only redirections
+/// works as usual, though 0, 1 and 2 are put back when the run ends.
+pub struct Interpreter {
+    pub(crate) env: ShellEnv,
+    // Print the parsed commands as JSON instead of running them.
+    dry_run: bool,
+    stdin: Stdio,
+    stdout: Stdio,
+    stderr: Stdio,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter with the process's environment variables, working
+    /// directory and standard descriptors.
+    pub fn new() -> Self {
+        let mut env = ShellEnv::new();
+        env.embedded = true;
+        Interpreter {
+            env,
+            dry_run: false,
+            stdin: Stdio::Inherit,
+            stdout: Stdio::Inherit,
+            stderr: Stdio::Inherit,
+        }
+    }
+
+    // The shell program's own interpreter, which `exec` replaces
+    pub(crate) fn standalone() -> Self {
+        let mut interp = Self::new();
+        interp.env.embedded = false;
+        interp
+    }
+
+    /// Sets a variable and exports it to the commands run.
+    pub fn env(&mut self, name: &str, value: &str) -> &mut Self {
+        self.env.set(name, value);
+        self.env.export(name);
+        self
+    }
+
+    pub fn env_remove(&mut self, name: &str) -> &mut Self {
+        self.env.unset(name);
+        self
+    }
+
+    /// Removes all variables, those inherited from the process included.
+    pub fn env_clear(&mut self) -> &mut Self {
+        for name in self.env.names() {
+            self.env.unset(&name);
+        }
+        self
+    }
+
+    /// Sets the positional parameters, $1 onwards.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.env.positional = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets $0, the name of the script. It is `cc-shell` by default.
+    pub fn arg0(&mut self, name: impl Into<String>) -> &mut Self {
+        self.env.arg0 = name.into();
+        self
+    }
+
+    /// The value of a variable, as `$name` would expand to.
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.env.get(name)
+    }
+
+    /// Changes the working directory, as `cd` does. The process's own
+    /// working directory is left alone.
+    pub fn current_dir(&mut self, dir: impl AsRef<Path>) -> io::Result<&mut Self> {
+        let dir = enterable_dir(&self.env.path(dir))?;
+        self.env.set("PWD", &dir.to_string_lossy());
+        self.env.cwd = dir;
+        Ok(self)
+    }
+
+    pub fn cwd(&self) -> &Path {
+        &self.env.cwd
+    }
+
+    pub fn stdin(&mut self, stdin: impl Into<Stdio>) -> &mut Self {
+        self.stdin = stdin.into();
+        self
+    }
+
+    pub fn stdout(&mut self, stdout: impl Into<Stdio>) -> &mut Self {
+        self.stdout = stdout.into();
+        self
+    }
+
+    pub fn stderr(&mut self, stderr: impl Into<Stdio>) -> &mut Self {
+        self.stderr = stderr.into();
+        self
+    }
+
+    /// Makes this a restricted shell, for good: `cd` and `exec` are
+    /// refused, as are command names with a slash, output redirections to
+    /// files and changes to $PATH, $SHELL and $ENV.
+    pub fn restrict(&mut self) -> &mut Self {
+        self.env.options.restricted = true;
+        self
+    }
+
+    /// Limits the external commands that can be found and run.
+    pub fn policy(&mut self, policy: Policy) -> &mut Self {
+        self.env.policy = policy;
+        self
+    }
+
+    /// Appends a line of JSON to `path` for each command run: when and
+    /// where it started, its arguments and redirections, its status and
+    /// how long it took.
+    pub fn audit_log(&mut self, path: impl AsRef<Path>) -> io::Result<&mut Self> {
+        self.env.audit = Some(AuditLog::open(&self.env.path(path))?);
+        Ok(self)
+    }
+
+    /// Prints the parsed commands as JSON instead of running them.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+        // Command substitutions are shown rather than run
+        self.env.options.noexec = dry_run;
+    }
+
+    /// The status of the last command run.
+    pub fn status(&self) -> i32 {
+        self.env.last_status
+    }
+
+    /// Whether `exit` was run.
+    pub fn exited(&self) -> bool {
+        self.env.exit_requested
+    }
+
+    /// Runs the commands of `source` and returns the status of the last
+    /// command. `exit` stops the run; a later run starts afresh. Blocks
+    /// while another interpreter is running.
+    pub fn run(&mut self, source: &str) -> i32 {
+        let _running = RUNNING.lock().unwrap_or_else(PoisonError::into_inner);
+        self.env.exit_requested = false;
+        let redirects = [(0, &self.stdin), (1, &self.stdout), (2, &self.stderr)]
+            .into_iter()
+            .filter_map(|(fd, stdio)| redirect(fd, stdio))
+            .collect::<Vec<_>>();
+        let _saved = match SavedFds::apply(&redirects, &self.env.cwd) {
+            Ok(saved) => saved,
+            Err(e) => {
+                let e = ShellError::Redirect(e);
+                e.report();
+                self.env.last_status = e.status();
+                return self.env.last_status;
+            }
+        };
+        self.run_script(source);
+        let _ = io::stdout().flush();
+        self.env.last_status
+    }
+
+    /// Runs `source` with `input` as its standard input, collecting its
+    /// standard output and error. The configured [`Stdio`] are kept.
+    pub fn output(&mut self, source: &str, input: &[u8]) -> io::Result<Output> {
+        let mut stdin = memfd("stdin")?;
+        stdin.write_all(input)?;
+        stdin.rewind()?;
+        let stdout = memfd("stdout")?;
+        let stderr = memfd("stderr")?;
+
+        let saved = [
+            std::mem::replace(&mut self.stdin, stdin.into()),
+            std::mem::replace(&mut self.stdout, stdout.try_clone()?.into()),
+            std::mem::replace(&mut self.stderr, stderr.try_clone()?.into()),
+        ];
+        let status = self.run(source);
+        let [stdin, stdout_stdio, stderr_stdio] = saved;
+        self.stdin = stdin;
+        self.stdout = stdout_stdio;
+        self.stderr = stderr_stdio;
+
+        Ok(Output {
+            status,
+            stdout: read_all(stdout)?,
+            stderr: read_all(stderr)?,
+        })
+    }
+
+    /// Runs each command of a script, as given to `-c` or read from a
+    /// file, joining the lines of those that go on over several. Syntax
+    /// errors name the line they are on.
+    pub(crate) fn run_script(&mut self, source: &str) {
+        for (number, command) in list::commands(source) {
+            if self.env.exit_requested {
+                break;
+            }
+            self.execute_line(&command, Some(number));
+        }
+    }
+
+    // Runs a startup or logout file if it exists
+    pub(crate) fn source_file(&mut self, path: &Path) {
+        if let Ok(source) = std::fs::read_to_string(path) {
+            self.run_script(&source);
+        }
+    }
+
+    pub(crate) fn home_file(&self, name: &str) -> Option<PathBuf> {
+        self.env.get("HOME").map(|home| Path::new(&home).join(name))
+    }
+
+    pub(crate) fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Runs one line of input. Syntax errors in a script name the line
+    /// they are on.
+    pub(crate) fn execute_line(&mut self, line: &str, number: Option<usize>) {
+        let Some(mut list) = list::parse_line(line, number, &mut self.env) else {
+            return;
+        };
+        if self.dry_run {
+            match list.describe(&mut self.env) {
+                Ok(json) => outln!("{:#}", json),
+                Err(e) => e.report(),
+            }
+            return;
+        }
+        if self.env.options.noexec {
+            // `set -n`: commands are read and checked but not run
+            return;
+        }
+        list.execute(&mut self.env);
+    }
+}
+
+// The redirection pointing `fd` at `stdio`, unless it is inherited
+fn redirect(fd: u32, stdio: &Stdio) -> Option<RedirectInfo> {
+    let (path, mode) = match stdio {
+        Stdio::Inherit => return None,
+        Stdio::Null => ("/dev/null".to_string(), RedirectMode::ReadWrite),
+        Stdio::Fd(file) if fd == 0 => (file.as_raw_fd().to_string(), RedirectMode::DupInput),
+        Stdio::Fd(file) => (file.as_raw_fd().to_string(), RedirectMode::DupOutput),
+    };
+    Some(RedirectInfo { path, fd, mode })
+}
+
+// An anonymous in-memory file
+fn memfd(name: &str) -> io::Result<File> {
+    let name = std::ffi::CString::new(name).map_err(io::Error::other)?;
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn read_all(mut file: File) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    file.rewind()?;
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
@@ -0,0 +1,48 @@
+//! The shell's parser and executor as a library. [`Interpreter`] runs
+//! shell code with its own variables and working directory, and with the
+//! standard descriptors of your choice; the `codecrafters-shell` binary
+//! adds the line editor, history and startup files on top of it.
+//!
+//! ```
+//! let mut shell = cc_shell::Interpreter::new();
+//! shell.env("NAME", "world");
+//! let output = shell.output("echo hello $NAME", b"").unwrap();
+//! assert_eq!(output.stdout, b"hello world\n");
+//! assert_eq!(output.status, 0);
+//! ```
+
+// Like `println!`, `eprintln!` and `eprint!`, but always writing to
+// descriptors 1 and 2, even where a test harness captures the standard
+// macros. Write errors such as a closed pipe are ignored, as by other shells.
+macro_rules! outln {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        let _ = writeln!(std::io::stdout(), $($arg)*);
+    }};
+}
+
+macro_rules! errln {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        let _ = writeln!(std::io::stderr(), $($arg)*);
+    }};
+}
+
+macro_rules! err {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        let _ = write!(std::io::stderr(), $($arg)*);
+    }};
+}
+
+mod cmdline;
+mod columns;
+mod completion;
+pub mod error;
+mod history;
+mod interpreter;
+pub mod shell;
+
+pub use cmdline::resolve::Policy;
+pub use error::ShellError;
+pub use interpreter::{Interpreter, Output, Stdio};
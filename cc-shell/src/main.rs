@@ -1,9 +1,118 @@
-mod cmdline;
-mod completion;
-mod shell;
-use shell::Shell;
+use cc_shell::error::{os_message, warn};
+use cc_shell::shell::{Mode, Shell};
+use cc_shell::Policy;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut shell = Shell::new()?;
-    shell.run()
+fn main() {
+    // A login shell is started by login(1) and friends with a `-` in front
+    // of argv[0]
+    let mut mode = Mode {
+        login: std::env::args()
+            .next()
+            .is_some_and(|arg0| arg0.starts_with('-')),
+        interactive: false,
+    };
+
+    // cc-shell [-l | --login] [-r | --restricted] [--policy file]
+    //          [--audit-log file] [--dry-run | --parse-only] [-c command [name] | script] [arg ...]
+    let mut args = std::env::args().skip(1);
+    let mut command = None;
+    let mut script = None;
+    let mut dry_run = false;
+    let mut restricted = false;
+    let mut policy = None;
+    let mut audit_log = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" | "--parse-only" => dry_run = true,
+            "-l" | "--login" => mode.login = true,
+            "-r" | "--restricted" => restricted = true,
+            "--policy" => match args.next() {
+                Some(arg) => policy = Some(arg),
+                None => {
+                    warn("--policy", "option requires an argument");
+                    std::process::exit(2);
+                }
+            },
+            "--audit-log" => match args.next() {
+                Some(arg) => audit_log = Some(arg),
+                None => {
+                    warn("--audit-log", "option requires an argument");
+                    std::process::exit(2);
+                }
+            },
+            "-c" => match args.next() {
+                Some(arg) => {
+                    command = Some(arg);
+                    break;
+                }
+                None => {
+                    warn("-c", "option requires an argument");
+                    std::process::exit(2);
+                }
+            },
+            _ => {
+                script = Some(arg);
+                break;
+            }
+        }
+    }
+    // The rest are the positional parameters; after -c the first is $0
+    let mut positional: Vec<String> = args.collect();
+    let arg0 = match (&command, &script) {
+        (Some(_), _) if !positional.is_empty() => Some(positional.remove(0)),
+        (None, Some(script)) => Some(script.clone()),
+        _ => None,
+    };
+    mode.interactive = command.is_none() && script.is_none() && unsafe { libc::isatty(0) } == 1;
+
+    let mut shell = match Shell::new(mode) {
+        Ok(shell) => shell,
+        Err(e) => {
+            eprintln!("cc-shell: {}", e);
+            std::process::exit(1);
+        }
+    };
+    shell.set_dry_run(dry_run);
+    shell.interpreter().args(positional);
+    if let Some(arg0) = arg0 {
+        shell.interpreter().arg0(arg0);
+    }
+    if let Some(file) = policy {
+        match Policy::load(file.as_ref()) {
+            Ok(policy) => {
+                shell.interpreter().policy(policy);
+            }
+            Err(e) => {
+                warn(&file, os_message(&e));
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(file) = audit_log {
+        if let Err(e) = shell.interpreter().audit_log(&file) {
+            warn(&file, os_message(&e));
+            std::process::exit(2);
+        }
+    }
+    shell.startup();
+    // As with other shells, the startup files run unrestricted
+    if restricted {
+        shell.interpreter().restrict();
+    }
+    match (command, script) {
+        (Some(command), _) => shell.run_script(&command),
+        (None, Some(path)) => match std::fs::read_to_string(&path) {
+            Ok(source) => shell.run_script(&source),
+            Err(e) => {
+                warn(&path, os_message(&e));
+                std::process::exit(127);
+            }
+        },
+        (None, None) => {
+            if let Err(e) = shell.run() {
+                eprintln!("cc-shell: {}", e);
+            }
+        }
+    }
+    std::process::exit(shell.finish())
 }
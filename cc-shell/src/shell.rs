@@ -1,20 +1,30 @@
-use crate::cmdline::Cmd;
-use crate::completion::ShellCompleter;
+use crate::cmdline::list::is_incomplete;
+use crate::cmdline::{ccenv, fds, hooks};
+use crate::completion::{CaseMatch, ShellCompleter};
+use crate::history::search::FuzzySearch;
+use crate::history::{self, HistoryEntry, ShellHistory};
+use crate::interpreter::Interpreter;
 use rustyline::completion::{Completer, Pair};
 use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
-use rustyline::hint::Hinter;
+use rustyline::hint::{Hinter, HistoryHinter};
 use rustyline::history::DefaultHistory;
-use rustyline::validate::{MatchingBracketValidator, Validator};
-use rustyline::{CompletionType, Config, EditMode, Editor, Helper};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Config, EditMode, Editor, EventHandler, Helper, KeyEvent};
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::error::Error;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 struct ShellHelper {
     completer: Rc<RefCell<ShellCompleter>>,
-    validator: MatchingBracketValidator,
+    hinter: HistoryHinter,
+    // `bind 'set show-hints on'`
+    hints: bool,
 }
 
 impl Completer for ShellHelper {
@@ -33,42 +43,74 @@ impl Completer for ShellHelper {
 impl Hinter for ShellHelper {
     type Hint = String;
 
-    fn hint(&self, _line: &str, _pos: usize, _ctx: &rustyline::Context<'_>) -> Option<String> {
-        None
+    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<String> {
+        if !self.hints {
+            return None;
+        }
+        self.hinter.hint(line, pos, ctx)
     }
 }
 
-// Basic implementation of Highlighter
-impl Highlighter for ShellHelper {}
+impl Highlighter for ShellHelper {
+    // Hints are dimmed so they read as a suggestion
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
 
+// Enter on an unfinished command, such as an open quote or a trailing
+// `|`, starts a new row of the same buffer, which then goes into history
+// as one entry. This replaces rustyline's bracket matching, which doesn't
+// know that a `(` in quotes opens nothing.
 impl Validator for ShellHelper {
-    fn validate(
-        &self,
-        ctx: &mut rustyline::validate::ValidationContext,
-    ) -> std::result::Result<rustyline::validate::ValidationResult, ReadlineError> {
-        self.validator.validate(ctx)
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
     }
 }
 
 impl Helper for ShellHelper {} // No extra methods needed.
 
+/// How the shell was started, which decides the startup files it reads.
+#[derive(Clone, Copy, Default)]
+pub struct Mode {
+    // `-l`/`--login`, or argv[0] starting with `-`
+    pub login: bool,
+    // Reading commands from a terminal rather than `-c`, a script or a pipe
+    pub interactive: bool,
+}
+
 /// Shell handles user interaction and command execution.
 pub struct Shell {
     // Force the Editor to use our ShellHelper and DefaultHistory.
     editor: Editor<ShellHelper, DefaultHistory>,
     // Shared completer for updating history.
     completer: Rc<RefCell<ShellCompleter>>,
+    // Shell variables, options and the last exit status.
+    interp: Interpreter,
+    mode: Mode,
+    // Commands run interactively, with their directory, time and status.
+    history: Arc<Mutex<ShellHistory>>,
+    // Whether Ctrl-R starts out showing only commands run in the cwd.
+    search_cwd: Arc<AtomicBool>,
+    // The shell's working directory, for Ctrl-R.
+    cwd: Arc<Mutex<PathBuf>>,
 }
 
 impl Shell {
-    pub fn new() -> std::result::Result<Self, Box<dyn Error>> {
+    pub fn new(mode: Mode) -> std::result::Result<Self, Box<dyn Error>> {
         // Use DefaultHistory explicitly.
         let completer = Rc::new(RefCell::new(ShellCompleter::new()));
-        let validator = MatchingBracketValidator::new();
 
+        // The edit mode and completion type follow `set -o vi` and `bind`
+        let mut interp = Interpreter::standalone();
+        interp.env.options.interactive = mode.interactive;
         let config = Config::builder()
             .edit_mode(EditMode::Emacs)
-            .completion_type(CompletionType::List)
+            .completion_type(interp.env.bindings.completion)
             .build();
 
         // Force DefaultHistory as history type.
@@ -76,17 +118,184 @@ impl Shell {
 
         let helper = ShellHelper {
             completer: completer.clone(),
-            validator,
+            hinter: HistoryHinter::new(),
+            hints: false,
         };
         editor.set_helper(Some(helper));
 
         editor.set_history_ignore_dups(true)?;
 
-        Ok(Shell { editor, completer })
+        let history = Arc::new(Mutex::new(ShellHistory::default()));
+        let search_cwd = Arc::new(AtomicBool::new(false));
+        let cwd = Arc::new(Mutex::new(interp.env.cwd.clone()));
+        editor.bind_sequence(
+            KeyEvent::ctrl('R'),
+            EventHandler::Conditional(Box::new(FuzzySearch {
+                history: history.clone(),
+                cwd_only: search_cwd.clone(),
+                cwd: cwd.clone(),
+            })),
+        );
+
+        Ok(Shell {
+            editor,
+            completer,
+            interp,
+            mode,
+            history,
+            search_cwd,
+            cwd,
+        })
+    }
+
+    /// The interpreter that runs the commands read.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interp
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.interp.set_dry_run(dry_run);
+    }
+
+    /// Runs each command of a script, as given to `-c` or read from a file.
+    pub fn run_script(&mut self, source: &str) {
+        self.interp.run_script(source);
     }
 
+    /// Reads the startup files: /etc/ccshell_profile then ~/.ccshell_profile
+    /// for a login shell, ~/.ccshellrc for any other interactive shell.
+    /// /etc/profile is left alone, as it is written for sh and bash.
+    pub fn startup(&mut self) {
+        if self.interp.dry_run() {
+            return;
+        }
+        if self.mode.login {
+            self.interp
+                .source_file(std::path::Path::new("/etc/ccshell_profile"));
+            if let Some(profile) = self.interp.home_file(".ccshell_profile") {
+                self.interp.source_file(&profile);
+            }
+        } else if self.mode.interactive {
+            if let Some(rc) = self.interp.home_file(".ccshellrc") {
+                self.interp.source_file(&rc);
+            }
+        }
+    }
+
+    /// Called as the shell exits: a login shell runs ~/.ccshell_logout.
+    /// Returns the status to exit with, which the logout file doesn't change.
+    pub fn finish(&mut self) -> i32 {
+        let status = self.interp.env.last_status;
+        if self.mode.login && !self.interp.dry_run() {
+            self.interp.env.exit_requested = false;
+            if let Some(logout) = self.interp.home_file(".ccshell_logout") {
+                self.interp.source_file(&logout);
+            }
+        }
+        status
+    }
+
+    // Applies `set -o vi|emacs` and changes made with `bind` to the line
+    // editor
+    fn configure_editor(&mut self) {
+        let mode = if self.interp.env.options.vi {
+            EditMode::Vi
+        } else {
+            EditMode::Emacs
+        };
+        if self.editor.config_mut().edit_mode() != mode {
+            self.editor.set_edit_mode(mode);
+        }
+        self.editor
+            .set_completion_type(self.interp.env.bindings.completion);
+        for (key, cmd) in self.interp.env.bindings.take_pending() {
+            match cmd {
+                Some(cmd) => self.editor.bind_sequence(key, EventHandler::Simple(cmd)),
+                None => self.editor.unbind_sequence(key),
+            };
+        }
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.hints = self.interp.env.bindings.hints;
+        }
+        let env = &self.interp.env;
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|name| env.get(name).filter(|value| !value.is_empty()));
+        let mut completer = self.completer.borrow_mut();
+        completer.set_case(CaseMatch::new(env.bindings.ignore_case, locale.as_deref()));
+        completer.set_listing(env.get("COLUMNS"), env.bindings.query_items);
+        self.search_cwd
+            .store(self.interp.env.bindings.search_cwd, Ordering::Relaxed);
+        if let Ok(mut cwd) = self.cwd.lock() {
+            cwd.clone_from(&self.interp.env.cwd);
+        }
+    }
+
+    // Reports the background jobs that have finished since the last prompt
+    fn notify_jobs(&mut self) {
+        for (job, status) in self.interp.env.jobs.reap_finished() {
+            let state = match status {
+                0 => "Done".to_string(),
+                status => format!("Exit {}", status),
+            };
+            errln!("[{}]+  {:<24}{}", job.id, state, job.command);
+        }
+    }
+
+    // Loads $HISTFILE (default ~/.ccshell_history) for an interactive
+    // shell, making its commands available to the arrow keys and Ctrl-R.
+    // $HISTFILESIZE limits how many are kept.
+    fn load_history(&mut self) -> std::result::Result<(), Box<dyn Error>> {
+        let file = match self.interp.env.get("HISTFILE") {
+            Some(file) if file.is_empty() => None,
+            Some(file) => Some(file.into()),
+            None => self.interp.home_file(".ccshell_history"),
+        };
+        let size = self
+            .interp
+            .env
+            .get("HISTFILESIZE")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(history::DEFAULT_SIZE);
+        let loaded = ShellHistory::load(file, size);
+        for entry in loaded.entries() {
+            self.editor.add_history_entry(entry.command.as_str())?;
+        }
+        if let Ok(mut history) = self.history.lock() {
+            *history = loaded;
+        }
+        Ok(())
+    }
+
+    // Runs a line typed at the prompt and records it in the history
+    fn execute_recorded(&mut self, line: &str) {
+        let cwd = self.interp.env.cwd.to_string_lossy().into_owned();
+        let timestamp = history::now();
+        self.interp.execute_line(line, None);
+        let entry = HistoryEntry {
+            command: line.to_string(),
+            cwd,
+            timestamp,
+            status: self.interp.env.last_status,
+        };
+        if let Ok(mut history) = self.history.lock() {
+            history.record(entry);
+        }
+    }
+
+    /// Reads commands from standard input: with the line editor when
+    /// interactive, otherwise line by line.
     pub fn run(&mut self) -> std::result::Result<(), Box<dyn Error>> {
-        loop {
+        if !self.mode.interactive {
+            return self.run_non_interactive();
+        }
+        self.load_history()?;
+        // The startup files may have turned on `shopt -s ccenv`
+        ccenv::update(&mut self.interp.env);
+        while !self.interp.env.exit_requested {
+            self.configure_editor();
+            self.notify_jobs();
+            hooks::run("precmd", &[], &mut self.interp.env);
             // Create a new history instance to pass to the completer
             let history_box: Box<dyn rustyline::history::History> = Box::new(DefaultHistory::new());
             self.completer.borrow_mut().set_history(history_box);
@@ -97,19 +306,42 @@ impl Shell {
                         continue;
                     }
                     self.editor.add_history_entry(line.clone())?;
-                    let cmdline = Cmd::new(&line);
-                    if let Err(e) = cmdline.handle_execs() {
-                        eprintln!("Error: {}", e);
-                    }
+                    // Interactive shells ignore `set -n`
+                    self.interp.env.options.noexec = false;
+                    hooks::run("preexec", std::slice::from_ref(&line), &mut self.interp.env);
+                    self.execute_recorded(&line);
                 }
                 Err(ReadlineError::Interrupted) => continue,
                 Err(ReadlineError::Eof) => break,
                 Err(err) => {
-                    eprintln!("Error: {}", err);
+                    errln!("cc-shell: {}", err);
                     break;
                 }
             }
         }
         Ok(())
     }
+
+    // Reads commands from a pipe or file without the line editor, joining
+    // the lines of unfinished commands. Input is read unbuffered so that
+    // builtins like `read` see the following lines.
+    fn run_non_interactive(&mut self) -> std::result::Result<(), Box<dyn Error>> {
+        while !self.interp.env.exit_requested {
+            let Some(mut line) = fds::read_line_unbuffered()? else {
+                break;
+            };
+            while is_incomplete(&line) {
+                let Some(more) = fds::read_line_unbuffered()? else {
+                    break;
+                };
+                line.push('\n');
+                line.push_str(&more);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.interp.execute_line(&line, None);
+        }
+        Ok(())
+    }
 }
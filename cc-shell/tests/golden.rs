@@ -0,0 +1,222 @@
+//! Golden tests: every `tests/scripts/NAME.sh` is run through the shell
+//! non-interactively and its stdout, stderr and exit status are compared
+//! with `NAME.stdout`, `NAME.stderr` and `NAME.status`. A missing stderr
+//! file means no output is expected there, a missing status file means 0.
+//!
+//! Environment variables:
+//! - `CCSHELL_BLESS=1` rewrites the expected files from the actual output.
+//! - `CCSHELL_COMPARE_SH=1` also runs each script with /bin/sh and
+//!   compares stdout and exit status. Scripts that use features sh lacks
+//!   start with a `# sh: skip` line.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
+
+fn scripts_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts")
+}
+
+fn flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+// Runs a script in a fresh scratch directory, which is also $HOME, so that
+// scripts can create files freely
+fn run(shell: &Path, script: &Path) -> Outcome {
+    run_with(shell, &[], script, None)
+}
+
+// Runs a script with options before it, and $HOME set to `home` rather
+// than the scratch directory
+fn run_with(shell: &Path, options: &[&str], script: &Path, home: Option<&Path>) -> Outcome {
+    let name = script.file_stem().unwrap().to_string_lossy();
+    let dir = std::env::temp_dir().join(format!(
+        "cc-shell-golden-{}-{}-{}",
+        std::process::id(),
+        shell.file_name().unwrap().to_string_lossy(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let output = Command::new(shell)
+        .args(options)
+        .arg(script)
+        .current_dir(&dir)
+        .env_clear()
+        .env("PATH", std::env::var("PATH").unwrap_or_default())
+        .env("HOME", home.unwrap_or(&dir))
+        .env("LC_ALL", "C")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap_or_else(|e| panic!("{}: {}", shell.display(), e));
+    let _ = fs::remove_dir_all(&dir);
+
+    Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code().unwrap_or(-1),
+    }
+}
+
+fn read_expected(script: &Path, ext: &str) -> Option<String> {
+    fs::read_to_string(script.with_extension(ext)).ok()
+}
+
+fn bless(script: &Path, actual: &Outcome) {
+    fs::write(script.with_extension("stdout"), &actual.stdout).unwrap();
+    let stderr = script.with_extension("stderr");
+    let status = script.with_extension("status");
+    if actual.stderr.is_empty() {
+        let _ = fs::remove_file(stderr);
+    } else {
+        fs::write(stderr, &actual.stderr).unwrap();
+    }
+    if actual.status == 0 {
+        let _ = fs::remove_file(status);
+    } else {
+        fs::write(status, format!("{}\n", actual.status)).unwrap();
+    }
+}
+
+// Describes how `actual` differs from `expected` as a unified-style diff
+// of the whole output, based on the longest common subsequence of lines
+fn diff(what: &str, expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let e: Vec<&str> = expected.lines().collect();
+    let a: Vec<&str> = actual.lines().collect();
+    // common[i][j]: length of the LCS of e[i..] and a[j..]
+    let mut common = vec![vec![0usize; a.len() + 1]; e.len() + 1];
+    for i in (0..e.len()).rev() {
+        for j in (0..a.len()).rev() {
+            common[i][j] = if e[i] == a[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut report = format!("--- {} (expected)\n+++ {} (actual)\n", what, what);
+    let (mut i, mut j) = (0, 0);
+    while i < e.len() || j < a.len() {
+        if i < e.len() && j < a.len() && e[i] == a[j] {
+            report += &format!(" {}\n", e[i]);
+            i += 1;
+            j += 1;
+        } else if j < a.len() && (i == e.len() || common[i][j + 1] >= common[i + 1][j]) {
+            report += &format!("+{}\n", a[j]);
+            j += 1;
+        } else {
+            report += &format!("-{}\n", e[i]);
+            i += 1;
+        }
+    }
+    if expected.ends_with('\n') != actual.ends_with('\n') {
+        report += "(the outputs differ in their final newline)\n";
+    }
+    Some(report)
+}
+
+fn compare(script: &Path, actual: &Outcome) -> Vec<String> {
+    let expected_status = read_expected(script, "status")
+        .map(|s| s.trim().parse::<i32>().expect("status file holds a number"))
+        .unwrap_or(0);
+    let mut problems = Vec::new();
+    problems.extend(diff(
+        "stdout",
+        &read_expected(script, "stdout").unwrap_or_default(),
+        &actual.stdout,
+    ));
+    problems.extend(diff(
+        "stderr",
+        &read_expected(script, "stderr").unwrap_or_default(),
+        &actual.stderr,
+    ));
+    if actual.status != expected_status {
+        problems.push(format!(
+            "exit status: expected {}, got {}",
+            expected_status, actual.status
+        ));
+    }
+    problems
+}
+
+// Error messages differ between shells, so only stdout and status count
+fn compare_with_sh(script: &Path, actual: &Outcome) -> Vec<String> {
+    let source = fs::read_to_string(script).unwrap();
+    if source.lines().next() == Some("# sh: skip") {
+        return Vec::new();
+    }
+    let sh = run(Path::new("/bin/sh"), script);
+    let mut problems = Vec::new();
+    problems.extend(diff("stdout vs /bin/sh", &sh.stdout, &actual.stdout));
+    if sh.status != actual.status {
+        problems.push(format!(
+            "exit status: /bin/sh gave {}, cc-shell {}",
+            sh.status, actual.status
+        ));
+    }
+    problems
+}
+
+#[test]
+fn golden_scripts() {
+    let shell = PathBuf::from(env!("CARGO_BIN_EXE_codecrafters-shell"));
+    let mut scripts: Vec<PathBuf> = fs::read_dir(scripts_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sh"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", scripts_dir().display());
+
+    let mut failures = Vec::new();
+    for script in &scripts {
+        let actual = run(&shell, script);
+        if flag("CCSHELL_BLESS") {
+            bless(script, &actual);
+            continue;
+        }
+        let mut problems = compare(script, &actual);
+        if flag("CCSHELL_COMPARE_SH") {
+            problems.extend(compare_with_sh(script, &actual));
+        }
+        if !problems.is_empty() {
+            let name = script.file_name().unwrap().to_string_lossy();
+            failures.push(format!("{}:\n{}", name, problems.join("\n")));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} scripts failed\n\n{}",
+        failures.len(),
+        scripts.len(),
+        failures.join("\n")
+    );
+}
+
+// A login shell reads ~/.ccshell_profile before the script and
+// ~/.ccshell_logout after it, from the fixture home in tests/login/home
+#[test]
+fn login_shell() {
+    let shell = PathBuf::from(env!("CARGO_BIN_EXE_codecrafters-shell"));
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/login");
+    let script = dir.join("login.sh");
+    let actual = run_with(&shell, &["-l"], &script, Some(&dir.join("home")));
+    if flag("CCSHELL_BLESS") {
+        bless(&script, &actual);
+        return;
+    }
+    let problems = compare(&script, &actual);
+    assert!(problems.is_empty(), "login.sh:\n{}", problems.join("\n"));
+}
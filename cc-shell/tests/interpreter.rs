@@ -0,0 +1,336 @@
+//! The library API: running shell code with captured output, its own
+//! environment and working directory.
+
+use cc_shell::{Interpreter, Policy, Stdio};
+use std::fs;
+use std::sync::Mutex;
+
+// An interpreter borrows descriptors 0-2 of the process while it runs, so
+// the tests take turns
+static STDIO: Mutex<()> = Mutex::new(());
+
+fn scratch_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("cc-shell-lib-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn output_is_captured() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shell = Interpreter::new();
+    let output = shell
+        .output("echo out; echo err >&2; sh -c 'echo external'; nosuch", b"")
+        .unwrap();
+    assert_eq!(output.stdout, b"out\nexternal\n");
+    assert_eq!(output.stderr, b"err\ncc-shell: nosuch: command not found\n");
+    assert_eq!(output.status, 127);
+}
+
+#[test]
+fn lookup_errors_follow_the_commands_redirections() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("lookup");
+    fs::write(dir.join("plain"), "").unwrap();
+    let mut shell = Interpreter::new();
+    shell.current_dir(&dir).unwrap();
+    let output = shell
+        .output(
+            "nosuch 2>/dev/null; echo $?; ./plain 2>/dev/null; echo $?",
+            b"",
+        )
+        .unwrap();
+    assert_eq!(output.stdout, b"127\n126\n");
+    assert_eq!(output.stderr, b"");
+    let output = shell.output("nosuch 2>&1", b"").unwrap();
+    assert_eq!(output.stdout, b"cc-shell: nosuch: command not found\n");
+    assert_eq!(output.stderr, b"");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn input_and_state_persist_between_runs() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shell = Interpreter::new();
+    let output = shell
+        .output("read first; read second", b"one\ntwo\n")
+        .unwrap();
+    assert_eq!(output.status, 0);
+    assert_eq!(shell.var("first").as_deref(), Some("one"));
+    let output = shell.output("echo $first $second", b"").unwrap();
+    assert_eq!(output.stdout, b"one two\n");
+
+    // `exit` ends a run, not the interpreter
+    let output = shell.output("exit 3\necho unreachable", b"").unwrap();
+    assert_eq!((output.status, output.stdout.as_slice()), (3, &b""[..]));
+    assert!(shell.exited());
+    assert_eq!(shell.output("echo again", b"").unwrap().stdout, b"again\n");
+}
+
+#[test]
+fn exec_does_not_replace_the_program() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shell = Interpreter::new();
+    let output = shell
+        .output(
+            "echo before\nexec sh -c 'echo in child; exit 4'\necho after",
+            b"",
+        )
+        .unwrap();
+    assert_eq!(output.stdout, b"before\nin child\n");
+    assert_eq!(output.status, 4);
+    assert!(shell.exited());
+
+    // Redirections given with the command are for the command alone
+    let output = shell.output("exec echo to stderr >&2", b"").unwrap();
+    assert_eq!(output.stdout, b"");
+    assert_eq!(output.stderr, b"to stderr\n");
+
+    // A command that can't be run leaves the run going
+    let output = shell.output("exec nosuch; echo still here", b"").unwrap();
+    assert_eq!(output.stdout, b"still here\n");
+    assert_eq!(output.stderr, b"cc-shell: exec: nosuch: not found\n");
+    assert!(!shell.exited());
+}
+
+#[test]
+fn runs_on_other_threads_take_turns() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let mut shell = Interpreter::new();
+                let output = shell
+                    .output(&format!("echo {i}.1; sleep 0.01; echo {i}.2"), b"")
+                    .unwrap();
+                (i, output.stdout)
+            })
+        })
+        .collect();
+    for thread in threads {
+        let (i, stdout) = thread.join().unwrap();
+        assert_eq!(stdout, format!("{i}.1\n{i}.2\n").into_bytes());
+    }
+}
+
+#[test]
+fn environment_is_injected() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shell = Interpreter::new();
+    shell
+        .env_clear()
+        .env("PATH", "/bin:/usr/bin")
+        .env("GREETING", "hello");
+    let output = shell
+        .output("echo $GREETING [$HOME]; sh -c 'echo $GREETING'", b"")
+        .unwrap();
+    assert_eq!(output.stdout, b"hello []\nhello\n");
+}
+
+#[test]
+fn arguments_are_positional_parameters() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shell = Interpreter::new();
+    shell.args(["first", "second word"]);
+    let output = shell.output("echo $# \"$2\"; shift; echo $1", b"").unwrap();
+    assert_eq!(output.stdout, b"2 second word\nsecond word\n");
+}
+
+#[test]
+fn working_directory_is_the_interpreters_own() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("cwd");
+    let process_cwd = std::env::current_dir().unwrap();
+    let mut shell = Interpreter::new();
+    shell.current_dir(&dir).unwrap();
+    let output = shell
+        .output("mkdir sub; cd sub; echo x > file; pwd", b"")
+        .unwrap();
+
+    let sub = dir.canonicalize().unwrap().join("sub");
+    assert_eq!(output.stdout, format!("{}\n", sub.display()).into_bytes());
+    assert_eq!(shell.cwd(), sub);
+    assert_eq!(fs::read_to_string(sub.join("file")).unwrap(), "x\n");
+    assert_eq!(std::env::current_dir().unwrap(), process_cwd);
+    assert!(shell.current_dir("no/such/dir").is_err());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn stdio_can_be_files() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("stdio");
+    fs::write(dir.join("in"), "from a file\n").unwrap();
+    let mut shell = Interpreter::new();
+    shell
+        .stdin(fs::File::open(dir.join("in")).unwrap())
+        .stdout(fs::File::create(dir.join("out")).unwrap())
+        .stderr(Stdio::Null);
+    let status = shell.run("read line; echo \"[$line]\"; echo hidden >&2; false");
+    assert_eq!(status, 1);
+    assert_eq!(
+        fs::read_to_string(dir.join("out")).unwrap(),
+        "[from a file]\n"
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn restricted_shell_refuses_escapes() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("restricted");
+    let mut shell = Interpreter::new();
+    shell.current_dir(&dir).unwrap().restrict();
+    let script = "cd /\n/bin/echo x\nPATH=/tmp\necho x > file\nexec true\necho kept >&2";
+    let output = shell.output(script, b"").unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.lines().collect::<Vec<_>>(),
+        [
+            "cc-shell: cd: restricted",
+            "cc-shell: /bin/echo: restricted: cannot specify `/' in command names",
+            "cc-shell: PATH: readonly variable",
+            "cc-shell: file: restricted: cannot redirect output",
+            "cc-shell: exec: restricted",
+            "kept",
+        ]
+    );
+    let script = "select PATH in /tmp; do break; done; echo $?";
+    let output = shell.output(script, b"1\n").unwrap();
+    assert_eq!(output.stdout, b"1\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("cc-shell: PATH: readonly variable\n"));
+    assert_eq!(shell.cwd(), dir.canonicalize().unwrap());
+    assert_ne!(shell.var("PATH").as_deref(), Some("/tmp"));
+    assert!(!dir.join("file").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn policy_limits_the_commands_found() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shell = Interpreter::new();
+    shell.policy(Policy::parse("# allowed commands\nsh\n"));
+    let output = shell
+        .output("sh -c 'echo from sh'; cat /dev/null; type -t cat echo", b"")
+        .unwrap();
+    assert_eq!(output.stdout, b"from sh\nbuiltin\n");
+    assert_eq!(output.stderr, b"cc-shell: cat: command not found\n");
+}
+
+#[test]
+fn audit_log_records_each_command() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("audit");
+    let mut shell = Interpreter::new();
+    shell
+        .current_dir(&dir)
+        .unwrap()
+        .audit_log("audit.jsonl")
+        .unwrap();
+    let output = shell
+        .output(
+            "echo hi > out; x=1; sh -c 'exit 3'; nosuch\n\
+             cat < missing; nosuch | cat; (true) > out",
+            b"",
+        )
+        .unwrap();
+    assert_eq!(output.status, 0);
+
+    let log = fs::read_to_string(dir.join("audit.jsonl")).unwrap();
+    let entries: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let recorded: Vec<(String, serde_json::Value)> = entries
+        .iter()
+        .map(|entry| (entry["argv"].to_string(), entry["status"].clone()))
+        .collect();
+    let expected = |argv: serde_json::Value, status: i32| (argv.to_string(), status.into());
+    assert_eq!(
+        recorded[..4],
+        [
+            expected(serde_json::json!(["echo", "hi"]), 0),
+            expected(serde_json::json!(["sh", "-c", "exit 3"]), 3),
+            expected(serde_json::json!(["nosuch"]), 127),
+            // Its redirection failed
+            expected(serde_json::json!(["cat"]), 1),
+        ]
+    );
+    // The stages of the pipeline are recorded by the processes that ran
+    // them, in either order, then the command in the subshell
+    let mut stages = recorded[4..6].to_vec();
+    stages.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        stages,
+        [
+            expected(serde_json::json!(["cat"]), 0),
+            expected(serde_json::json!(["nosuch"]), 127),
+        ]
+    );
+    assert_eq!(recorded[6..], [expected(serde_json::json!(["true"]), 0)]);
+    assert_eq!(entries[3]["redirections"][0]["path"], "missing");
+    assert_eq!(entries[0]["redirections"][0]["path"], "out");
+    assert_eq!(
+        entries[0]["cwd"],
+        dir.canonicalize().unwrap().to_str().unwrap()
+    );
+    assert!(entries
+        .iter()
+        .all(|entry| entry["duration"].as_f64().is_some()));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn dry_run_prints_the_plan_as_json() {
+    let _lock = STDIO.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("dry-run");
+    let mut shell = Interpreter::new();
+    shell.current_dir(&dir).unwrap().set_dry_run(true);
+    let output = shell
+        .output(
+            "x=1 echo $(nosuch) \"a  b\" 2> err | wc -l && (cd /) &\necho $x",
+            b"",
+        )
+        .unwrap();
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stderr, b"");
+
+    // One document per line run
+    let plans: Vec<serde_json::Value> = serde_json::Deserializer::from_slice(&output.stdout)
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(plans.len(), 2);
+    let pipelines = &plans[0]["pipelines"];
+    let stages = &pipelines[0]["stages"];
+    assert_eq!(
+        stages[0]["args"],
+        serde_json::json!(["echo", "$(nosuch)", "a  b"])
+    );
+    assert_eq!(
+        stages[0]["assignments"],
+        serde_json::json!([{ "name": "x", "value": "1" }])
+    );
+    assert_eq!(stages[0]["builtin"], true);
+    assert_eq!(
+        stages[0]["redirections"],
+        serde_json::json!([{ "fd": 2, "mode": "write", "path": "err" }])
+    );
+    assert_eq!(stages[1]["args"], serde_json::json!(["wc", "-l"]));
+    assert_eq!(stages[1]["builtin"], false);
+    assert_eq!(pipelines[1]["connector"], "and");
+    assert_eq!(pipelines[1]["background"], true);
+    assert_eq!(
+        pipelines[1]["stages"][0]["subshell"]["pipelines"][0]["stages"][0]["args"],
+        serde_json::json!(["cd", "/"])
+    );
+    // Nothing ran: the assignment was only for echo, and no file was made
+    assert_eq!(
+        plans[1]["pipelines"][0]["stages"][0]["args"],
+        serde_json::json!(["echo"])
+    );
+    assert!(!dir.join("err").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,5 @@
+# Run with -l and HOME set to tests/login/home
+echo "$GREETING"
+echo "$PATH" | cut -d: -f1 | sed "s|^$HOME|~|"
+sh -c 'echo "exported: $GREETING"'
+false
@@ -0,0 +1,41 @@
+# sh: skip
+# Builtins
+echo hello world
+echo
+pwd > /dev/null
+mkdir sub
+cd sub
+pwd | sed 's|.*/||'
+cd ..
+cd nonexistent
+cd - > /dev/null
+pwd | sed 's|.*/||'
+type echo
+type cd
+type exit
+type sh | sed 's| is .*| is found|'
+type nosuchcommand
+nosuchcommand
+echo $?
+x=1
+declare -p x
+unset x
+echo x=$x
+declare -a arr=(a b c)
+echo ${arr[1]} ${#arr[@]}
+declare sub=$(echo expanded once >&2; echo v)
+i=0; declare n=$((i+=1))
+echo $sub $i $n
+name=z; declare $name=from-expansion
+echo $z
+command declare -a words=(a "b c")
+declare -p words
+declare 1bad
+umask 022
+umask
+umask -S
+umask -S u=rwx,g=rx,o=
+umask
+set -o | grep -E '^(noglob|xtrace)'
+exit 7
+echo not reached
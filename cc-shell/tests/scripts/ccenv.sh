@@ -0,0 +1,24 @@
+# sh: skip
+# Trusted .ccenv files load on cd with shopt -s ccenv, and chpwd hooks
+mkdir -p proj/sub
+echo 'declare -x GREETING=hi; PROJ=1; unset GONE' > proj/.ccenv
+shopt -s ccenv
+GONE=here
+chpwd='pwd | sed "s|$HOME|chpwd: HOME|"'
+cd proj 2>&1 | sed "s|$HOME|HOME|"
+cd proj 2>/dev/null
+echo "[$GREETING] [$PROJ] [$GONE]"
+ccenv allow
+echo "[$GREETING] [$PROJ] [$GONE]"
+sh -c 'echo child $GREETING'
+cd sub
+ccenv status | sed "s|$HOME|HOME|"
+cd ../..
+echo "[$GREETING] [$PROJ] [$GONE]"
+echo 'PROJ=changed' >> proj/.ccenv
+cd proj 2>/dev/null; echo "[$PROJ]"
+ccenv status | sed "s|$HOME|HOME|"
+cd ..
+chpwd=('echo one' 'echo two; cd /')
+cd proj 2>/dev/null; pwd
+ccenv frob; echo $?
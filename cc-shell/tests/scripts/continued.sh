@@ -0,0 +1,23 @@
+# Commands go on over lines while a quote, subshell or pipeline is open
+echo "first
+second"
+echo 'single
+quoted'
+echo one |
+  tr a-z A-Z
+true &&
+  echo and
+false ||
+  echo or
+echo back\
+slash
+(
+  echo in
+  echo subshell
+)
+echo $(echo command
+  echo substitution)
+echo "$(printf 'a\nb')" | wc -l
+printf 'echo sourced \\\n  over lines\necho "quote\nin source"\n' > more.sh
+. ./more.sh
+echo done
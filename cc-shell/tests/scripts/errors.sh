@@ -0,0 +1,30 @@
+# sh: skip
+# Syntax errors name the line and point at the token; the script goes on
+echo start
+ls | | wc
+echo $?
+echo > > out
+echo $?
+echo 日本語 café | | wc
+echo $?
+echo continued |
+  | wc
+echo $?
+# A sourced file ends where its command is left open
+echo 'cat <(echo' > open.sh
+. ./open.sh
+echo $?
+# Runtime errors are reported as cc-shell: cmd: message
+nosuchcommand
+echo $?
+cd missing-dir
+echo $?
+cat < missing-file
+echo $?
+echo x > not-executable
+./not-executable
+echo $?
+# An unterminated quote takes the rest of the script with it
+echo "unterminated
+echo $?
+exit 3
@@ -0,0 +1,32 @@
+# sh: skip
+# Parameter, brace, tilde, command, arithmetic and pathname expansion
+name=world
+echo hello $name ${name}s ${#name} $unset.
+echo {a,b}{1..3} x{,y}z {3..1} {a..c}
+echo ~ | sed "s|$HOME|HOME|"
+echo "$(echo command substitution)" `echo backquotes`
+echo $((1 + 2 * 3)) $((10 / 3)) $((2 ** 8)) $(( (1 + 2) * 3 ))
+i=5
+echo $((i++)) $i $((i += 10))
+touch b.txt a.txt c.log
+echo *.txt ?.log [ab]*
+echo nomatch*
+set -f
+echo *.txt
+set +f
+arr=(x y z)
+echo ${arr[@]} ${arr[2]} ${#arr[@]}
+declare -A map=([k]=v)
+echo ${map[k]}
+unset u; e=; v=val
+printf '[%s]\n' "${u:-def}" "${e:-def}" "${v:-def}" "${u-def}" "${e-def}"
+printf '[%s]\n' ${u:-two  words} "${u:-two  words}" ${u:-"quoted  words"}
+printf '[%s]\n' "${u:+alt}" "${e:+alt}" "${e+alt}" "${v:+alt $v}"
+printf '[%s]\n' ${u:=assigned} "$u" "${e:=filled}" "$e"
+printf '[%s]\n' "${nope:-~}" "${nope:-$(echo sub)}" "${nope:-$((1 + 2))}" "${nope:-${v}x}"
+(echo "${v:?never}"; echo "${u2:?is required}"; echo not reached)
+echo status $?
+(echo "${e:?}")
+echo ${1:=x}
+echo "${v%l}"
+echo done
@@ -0,0 +1,27 @@
+# sh: skip
+# Background jobs, wait, eval, source and the special parameters
+sh -c 'exit 5' & pid=$!
+wait $pid; echo "waited $?"
+(exit 3) & wait %1; echo "job $?"
+sleep 0 & sleep 0 & wait; echo "all $?"
+wait 1; echo $?
+wait %4; echo $?
+echo 2>&1 >/dev/null & wait
+# The whole and-or list runs in the background, not only its last pipeline
+sleep 3 >/dev/null 2>&1 && echo "not reached" & kill $!; wait $!; echo "list $?"
+false || echo "or ran" & wait
+echo foo bar; echo $_
+[ "$$" = "$(echo $$)" ] && echo same pid in substitution
+cmd='echo "two  spaces"'
+eval $cmd
+eval "$cmd"
+false; eval 'echo status $?; y=5'; echo y=$y
+eval "echo first; echo second"
+eval '('; echo $?
+echo 'echo sourced $# "$1"' > args.sh
+set -- outer
+. ./args.sh inner; echo after "$1"
+echo 'set -- replaced' > set.sh
+source ./set.sh x; echo kept "$1"
+source; echo $?
+. ./missing.sh; echo $?
@@ -0,0 +1,31 @@
+# sh: skip
+# Command lookup as shown by type, command, builtin and hash
+mkdir -p bin other
+printf '#!/bin/sh\necho mytool from bin\n' > bin/mytool
+printf '#!/bin/sh\necho mytool from other\n' > other/mytool
+echo 'echo not executable' > bin/plain
+chmod +x bin/mytool other/mytool
+PATH=bin:other:$PATH
+type mytool
+type -t mytool echo time
+type -a mytool
+type -p mytool echo
+type -P mytool
+type nosuch
+echo $?
+mytool
+hash -t mytool
+type mytool
+hash
+hash -r
+type mytool
+command -v echo mytool
+command -V echo mytool
+command -v nosuch
+echo $?
+command echo via command
+builtin echo via builtin
+builtin mytool
+echo $?
+plain
+echo $?
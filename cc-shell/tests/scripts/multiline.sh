@@ -0,0 +1,9 @@
+# sh: skip
+# Commands spanning lines, as typed at a continuation prompt or given to eval
+eval "$(printf 'echo "one\ntwo" | cat')"
+eval "$(printf 'echo a |\n  tr a b')"
+eval "$(printf 'true &&\n  echo and # it'"'"'s a comment\necho next')"
+eval "$(printf 'echo back\\\nslash')"
+eval "$(printf '(echo sub\n echo shell) | tr -d e')"
+eval "$(printf 'echo first\n\necho ;')"; echo $?
+eval "$(printf 'echo ok\necho | | x')"; echo $?
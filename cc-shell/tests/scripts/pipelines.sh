@@ -0,0 +1,12 @@
+# Pipelines and exit status
+echo one two three | tr ' ' '\n' | sort -r
+printf 'b\na\n' | sort | head -1
+false
+echo $?
+true
+echo $?
+false | true
+echo $?
+true | false
+echo $?
+echo end
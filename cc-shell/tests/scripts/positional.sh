@@ -0,0 +1,32 @@
+# sh: skip
+# Positional parameters with set --, shift and getopts
+echo $# "$@"
+set -- one "two words" three
+echo $# "$2" $*
+shift; echo $# "$1"
+shift 5; echo $? $#
+shift x; echo $?
+set --; echo $#
+set -- -a -bval -c x -- rest
+getopts ab:c: opt; echo "opt=$opt arg=$OPTARG ind=$OPTIND"
+getopts ab:c: opt; echo "opt=$opt arg=$OPTARG ind=$OPTIND"
+getopts ab:c: opt; echo "opt=$opt arg=$OPTARG ind=$OPTIND"
+getopts ab:c: opt; echo "status=$? opt=$opt ind=$OPTIND"
+shift $((OPTIND - 1)); echo left "$@"
+OPTIND=1
+set -- -xz -qy -c
+getopts :x:q opt; echo "opt=$opt arg=$OPTARG"
+getopts :x:q opt; echo "opt=$opt arg=$OPTARG"
+getopts :x:q opt; echo "opt=$opt arg=$OPTARG"
+getopts :x:qc: opt; echo "opt=$opt arg=$OPTARG"
+OPTIND=1
+getopts x:qc: opt; echo "opt=$opt arg=[$OPTARG]"
+getopts x:qc: opt; echo "opt=$opt arg=[$OPTARG]"
+getopts x:qc: opt; echo "opt=$opt arg=[$OPTARG]"
+getopts x:qc: opt; echo "opt=$opt arg=[$OPTARG]"
+getopts x:qc: opt; echo "s=$? opt=$opt arg=[$OPTARG]"
+OPTIND=1
+getopts ab opt -ab file; getopts ab opt -ab file; getopts ab opt -ab file; echo "$? $opt $OPTIND"
+OPTIND=1
+getopts; echo $?
+getopts a bad-name; echo $?
@@ -0,0 +1,15 @@
+# sh: skip
+# Process substitution: <(cmd) and >(cmd) as /dev/fd paths of pipes
+diff <(echo a) <(echo a) && echo same
+diff <(echo a) <(echo b) > /dev/null
+echo differ $?
+cat <(printf 'one\ntwo\n') <(echo three)
+paste <(printf '1\n2\n') <(printf 'x\ny\n')
+read line < <(echo read from a substitution)
+echo $line
+echo to a substitution > >(tr a-z A-Z > upper.txt)
+cat upper.txt
+echo fed > fed.txt
+tee >(sed 's/^/copy: /' > copy.txt) < fed.txt > /dev/null
+cat copy.txt
+echo <(true) | sed 's|/dev/fd/[0-9]*|/dev/fd/N|'
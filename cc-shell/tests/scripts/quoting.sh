@@ -0,0 +1,20 @@
+# Quoting rules of Cmd::parse_args; each argument is shown in brackets
+printf '[%s]\n' 'single   quoted'   "double   quoted"
+printf '[%s]\n' 'it''s' "a"'b'c
+printf '[%s]\n' "it's" 'say "hi"'
+printf '[%s]\n' \$HOME \"x\" \'y\' a\ b
+printf '[%s]\n' "back\\slash" "dollar \$x" "quote \"q\"" "keep \n and \a"
+printf '[%s]\n' 'no $expansion or \escapes here'
+printf '[%s]\n' "" '' x
+printf '[%s]\n' a"b c"d 'e f'g
+x='one  two'
+printf '[%s]\n' $x
+printf '[%s]\n' "$x"
+printf '[%s]\n' "${x}s" '${x}'
+printf '[%s]\n' "tab	inside"
+printf '[%s]\n' \\ \# "#" '#' a#b
+printf '[%s]\n' ">" "<" '2>' \>\> "&>" x'<'y
+op='>'
+printf '[%s]\n' $op file "$op"
+cat file 2>/dev/null || echo no file was written
+echo $op file
@@ -0,0 +1,35 @@
+# sh: skip
+# read with IFS splitting and its -r, -d, -n, -t and -a options
+printf 'one  two three four\n' > words.txt
+read first rest < words.txt
+echo "[$first] [$rest]"
+read -a words < words.txt
+echo ${#words[@]} "${words[0]}" "${words[3]}"
+printf 'a:b::c\n' > colons.txt
+IFS=: read -a fields < colons.txt
+echo ${#fields[@]} "[${fields[2]}]" "[${fields[3]}]"
+printf 'back\\slash\n' > escaped.txt
+read line < escaped.txt
+echo "$line"
+read -r line < escaped.txt
+echo "$line"
+printf 'first,second,third' > commas.txt
+read -d , field < commas.txt
+echo "[$field]"
+read -n 3 part < commas.txt
+echo "[$part]"
+read -n 3 -d , part < commas.txt
+echo "[$part] $?"
+read -d , field < /dev/null
+echo "[$field] $?"
+sleep 1 | read -t 0.1 late
+echo timed out $?
+# End of input within the time given is not a timeout
+read -t 5 late < /dev/null
+echo end of input $?
+read -t 0 ready < words.txt
+echo ready $?
+read -t soon late < words.txt
+echo $?
+read -n many part < words.txt
+echo $?
@@ -0,0 +1,35 @@
+# sh: skip
+# Every redirection mode
+echo first > out.txt
+echo second >> out.txt
+cat < out.txt
+echo to-stderr >&2
+ls missing-file 2> err.txt
+cat err.txt | wc -l
+ls missing-file 2>/dev/null
+echo status $?
+echo both-out &> both.txt
+ls missing-file &>> both.txt
+wc -l < both.txt
+echo hi 2&> digit.txt
+cat digit.txt
+echo again 1&>> digit.txt
+cat digit.txt
+ls missing-file 2>&1 | wc -l
+echo appended 1>> out.txt
+cat out.txt
+echo rw <> rw.txt
+cat rw.txt
+exec 3> fd3.txt
+echo via-fd3 >&3
+exec 3>&-
+cat fd3.txt
+echo closed >&3
+exec 4< out.txt
+read line <&4
+echo read: $line
+exec 4<&-
+echo nested > dir/sub/file.txt
+cat dir/sub/file.txt
+cat < missing-input
+echo after
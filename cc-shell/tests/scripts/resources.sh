@@ -0,0 +1,11 @@
+# sh: skip
+# time -p, times and ulimit; timings vary, so only their format is shown
+(time -p sleep 0) 2> time.txt
+sed 's/[0-9]/N/g' time.txt
+(time sleep 0) 2> time.txt
+sed 's/[0-9]/N/g' time.txt
+times | sed 's/[0-9]/N/g'
+(ulimit -n 64; ulimit -n; ulimit -Hn)
+(ulimit -Sn 32; ulimit -n; ulimit -Sn 48; ulimit -Sn)
+ulimit -n lots
+echo status $?
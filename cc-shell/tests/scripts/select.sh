@@ -0,0 +1,17 @@
+# sh: skip
+# select menus, read from a pipe; the menu and prompt go to stderr
+COLUMNS=24
+printf '2\n\n9\nx\n' | select w in aa bb cc dd ee ff gg hh ii jj kk; do echo "$w ($REPLY)"; done; echo $?
+printf '1\n2\n' | select w in "one word" two; do echo "$w"; break; echo not here; done; echo $?
+set -- p q r
+PS3='pick: '
+echo 3 | select w; do echo "$w"; done
+printf '1\n' > one
+select w in a; do echo outer $w; select v in b; do echo inner $v; break 2; done < one; echo not here; done < one
+select w in; do echo nothing; done; echo $?
+eval "$(printf 'echo 1 | select w in a b\ndo\n  echo "$w"\ndone')"
+select 1w in a; do :; done; echo $?
+break; echo $?
+eval 'select w in a; do echo'; echo $?
+eval 'select w in a; do done'; echo $?
+eval 'select w in a; do :; done x'; echo $?
@@ -0,0 +1,22 @@
+# sh: skip
+# Subshells run in a copy of the shell; lists chain pipelines by status
+mkdir sub
+(cd sub; pwd | sed "s|$HOME|HOME|"); pwd | sed "s|$HOME|HOME|"
+x=outer; (x=inner; echo $x); echo $x
+(exit 3); echo $?
+( echo nested; (echo deeper) ) | cat
+(echo out; echo err >&2) > both 2>&1; cat both
+true && echo and; false && echo not run
+false || echo or; true || echo not run
+false || (echo rescued; exit 4); echo $?
+echo "a;b" 'c&&d' e\;f; echo last;
+# Pipeline stages run in children unless lastpipe is set
+unset v; echo piped | read v; echo "v=[$v]"
+shopt lastpipe; echo $?
+shopt -s lastpipe
+echo piped | read v; echo "v=[$v]"
+shopt -p lastpipe
+shopt -q nosuch
+echo $?
+(echo x) y
+echo ok &&
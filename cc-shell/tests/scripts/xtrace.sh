@@ -0,0 +1,16 @@
+# sh: skip
+# set -x traces each command to stderr as expanded, after $PS4
+set -x
+name='two words'
+echo $name "$name"
+greeting=hi sh -c 'echo $greeting'
+echo "it's" '$HOME' > /dev/null
+arr=(a "b c")
+n=$((1 + 2))
+set +x
+echo untraced
+PS4='trace> '
+set -x
+echo $n
+set -o xtrace
+true
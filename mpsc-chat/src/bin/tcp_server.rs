@@ -0,0 +1,16 @@
+use mpsc_chat::serve;
+use tokio::net::TcpListener;
+
+// Chat over TCP: `cargo run --bin tcp_server [ADDR]`, then connect with
+// e.g. `nc 127.0.0.1 7878` and type /help
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    // localhost only unless told otherwise
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    let listener = TcpListener::bind(&addr).await?;
+    println!("[LOG]: Listening on {}", addr);
+    serve(listener).await;
+    Ok(())
+}
@@ -2,16 +2,29 @@ use std::collections::HashMap;
 // TODO: did not use mpsc since we need to broadcast msgs to all chatroom subscriber
 use crate::error::{ChatError, ChatResult};
 use crate::message::ChatMessage;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot};
 
 pub struct ChatRoom {
-    users: HashMap<String, broadcast::Sender<ChatMessage>>,
+    // each user with the sender whose dropping ends their RoomReceiver
+    users: HashMap<String, oneshot::Sender<()>>,
     tx: broadcast::Sender<ChatMessage>,
 }
 
+/// What joining a room gives a user: the messages sent to it from then on,
+/// except their own unless `set_echo(true)`. Leaving the room ends it, and
+/// dropping it leaves the room.
+pub struct RoomReceiver {
+    user: String,
+    rx: broadcast::Receiver<ChatMessage>,
+    // resolves once the user is removed from the room
+    left: Option<oneshot::Receiver<()>>,
+    echo: bool,
+}
+
 impl ChatRoom {
+    // users who dropped their receiver are gone
     pub fn is_empty(&self) -> bool {
-        self.users.is_empty()
+        self.users.values().all(|user| user.is_closed())
     }
 
     pub async fn new() -> ChatRoom {
@@ -22,8 +35,17 @@ impl ChatRoom {
         }
     }
 
-    pub async fn add_user(&mut self, name: String) {
-        self.users.insert(name, self.tx.clone());
+    // joining again under the same name ends the previous receiver
+    pub async fn add_user(&mut self, name: String) -> RoomReceiver {
+        self.users.retain(|_, user| !user.is_closed());
+        let (membership, left) = oneshot::channel();
+        self.users.insert(name.clone(), membership);
+        RoomReceiver {
+            user: name,
+            rx: self.tx.subscribe(),
+            left: Some(left),
+            echo: false,
+        }
     }
 
     pub async fn broadcast(&self, message: ChatMessage) -> ChatResult<()> {
@@ -41,3 +63,137 @@ impl ChatRoom {
         self.tx.subscribe()
     }
 }
+
+impl RoomReceiver {
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// The next message for the user. `ChatError::Lagged` tells how many
+    /// were missed by falling behind, and receiving can go on;
+    /// `ChatError::ReceiveError` means the user left or the room is gone.
+    pub async fn recv(&mut self) -> ChatResult<ChatMessage> {
+        loop {
+            let Some(left) = &mut self.left else {
+                return Err(ChatError::ReceiveError);
+            };
+            let message = tokio::select! {
+                // nothing is delivered once the user has left
+                biased;
+                _ = left => None,
+                message = self.rx.recv() => Some(message),
+            };
+            match message {
+                None => {
+                    self.left = None;
+                    return Err(ChatError::ReceiveError);
+                }
+                Some(Ok(message)) if message.sender == self.user && !self.echo => continue,
+                Some(Ok(message)) => return Ok(message),
+                Some(Err(broadcast::error::RecvError::Lagged(missed))) => {
+                    return Err(ChatError::Lagged(missed))
+                }
+                Some(Err(broadcast::error::RecvError::Closed)) => {
+                    return Err(ChatError::ReceiveError)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(sender: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            sender: sender.to_string(),
+            room: "rust".to_string(),
+            content: content.to_string(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    async fn recv_content(rx: &mut RoomReceiver) -> String {
+        rx.recv().await.unwrap().content
+    }
+
+    #[tokio::test]
+    async fn own_messages_only_with_echo() {
+        let mut room = ChatRoom::new().await;
+        let mut alice = room.add_user("alice".to_string()).await;
+        let mut bob = room.add_user("bob".to_string()).await;
+
+        room.broadcast(message("alice", "from alice"))
+            .await
+            .unwrap();
+        room.broadcast(message("bob", "from bob")).await.unwrap();
+        assert_eq!(recv_content(&mut bob).await, "from alice");
+        // alice's own message is skipped
+        assert_eq!(recv_content(&mut alice).await, "from bob");
+
+        alice.set_echo(true);
+        room.broadcast(message("alice", "echoed")).await.unwrap();
+        assert_eq!(recv_content(&mut alice).await, "echoed");
+    }
+
+    #[tokio::test]
+    async fn leaving_ends_delivery() {
+        let mut room = ChatRoom::new().await;
+        let mut alice = room.add_user("alice".to_string()).await;
+        room.broadcast(message("bob", "before leaving"))
+            .await
+            .unwrap();
+        room.remove_user("alice");
+        // what was sent before isn't delivered either
+        assert!(matches!(alice.recv().await, Err(ChatError::ReceiveError)));
+        room.broadcast(message("bob", "after leaving"))
+            .await
+            .unwrap();
+        assert!(matches!(alice.recv().await, Err(ChatError::ReceiveError)));
+
+        // joining again under the same name ends the earlier receiver
+        let mut first = room.add_user("bob".to_string()).await;
+        let mut second = room.add_user("bob".to_string()).await;
+        room.broadcast(message("carol", "hi")).await.unwrap();
+        assert!(matches!(first.recv().await, Err(ChatError::ReceiveError)));
+        assert_eq!(recv_content(&mut second).await, "hi");
+    }
+
+    #[tokio::test]
+    async fn dropped_receivers_leave_the_room() {
+        let mut room = ChatRoom::new().await;
+        assert!(room.is_empty());
+        let alice = room.add_user("alice".to_string()).await;
+        let bob = room.add_user("bob".to_string()).await;
+        assert!(!room.is_empty());
+
+        drop(alice);
+        assert!(!room.is_empty());
+        drop(bob);
+        assert!(room.is_empty());
+
+        let _carol = room.add_user("carol".to_string()).await;
+        assert!(!room.is_empty());
+        room.remove_user("carol");
+        assert!(room.is_empty());
+    }
+
+    #[tokio::test]
+    async fn falling_behind_is_reported() {
+        let mut room = ChatRoom::new().await;
+        let mut alice = room.add_user("alice".to_string()).await;
+        // the channel holds 16 messages, so the first 4 are lost
+        for i in 0..20 {
+            room.broadcast(message("bob", &i.to_string()))
+                .await
+                .unwrap();
+        }
+        assert!(matches!(alice.recv().await, Err(ChatError::Lagged(4))));
+        // and receiving goes on with the oldest still there
+        assert_eq!(recv_content(&mut alice).await, "4");
+        for i in 5..20 {
+            assert_eq!(recv_content(&mut alice).await, i.to_string());
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::chat_room::ChatRoom;
+use crate::chat_room::{ChatRoom, RoomReceiver};
 use crate::error::{ChatError, ChatResult};
 use crate::message::ChatMessage;
 use std::collections::HashMap;
@@ -45,22 +45,11 @@ impl ChatServer {
         Ok(())
     }
 
-    // joining means that you're listening to the chatroom.
-    // I suspect this function is the source cause of printing
-    // duplicated message
-    pub async fn join_room(&mut self, room: &str, user: String) -> ChatResult<()> {
+    // joining means that you're listening to the chatroom: the user gets
+    // their own receiver for it, until they leave or drop it
+    pub async fn join_room(&mut self, room: &str, user: String) -> ChatResult<RoomReceiver> {
         let chat_room = self.rooms.get_mut(room).ok_or(ChatError::RoomNotFound)?;
-
-        chat_room.add_user(user.clone()).await;
-
-        // let mut broadcast_rx = chat_room.subscribe();
-        //
-        // tokio::spawn(async move {
-        //     while let Ok(message) = broadcast_rx.recv().await {
-        //         println!("{}", message);
-        //     }
-        // });
-        Ok(())
+        Ok(chat_room.add_user(user).await)
     }
 
     pub async fn leave_room(&mut self, room: &str, user: &str) -> ChatResult<()> {
@@ -5,6 +5,22 @@ pub enum ChatError {
     SendError,
     ReceiveError,
     RoomNotEmpty,
+    // a receiver fell behind and missed this many messages
+    Lagged(u64),
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ChatError::Lagged(missed) => return write!(f, "missed {} messages", missed),
+            ChatError::RoomExists => "room already exists",
+            ChatError::RoomNotFound => "no such room",
+            ChatError::SendError => "message could not be sent",
+            ChatError::ReceiveError => "message could not be received",
+            ChatError::RoomNotEmpty => "room is not empty",
+        };
+        f.write_str(description)
+    }
 }
 
 pub type ChatResult<T> = std::result::Result<T, ChatError>;
@@ -3,9 +3,12 @@ mod chat_server;
 mod error;
 mod message;
 mod simulation;
+mod tcp_server;
 
 // Re-export the public interface
+pub use chat_room::RoomReceiver;
 pub use chat_server::ChatServer;
 pub use error::{ChatError, ChatResult};
 pub use message::ChatMessage;
 pub use simulation::run_simulation;
+pub use tcp_server::{serve, MAX_LINE};
@@ -5,6 +5,7 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
 
+use crate::chat_room::RoomReceiver;
 use crate::chat_server::ChatServer;
 use crate::error::ChatError;
 use crate::message::ChatMessage;
@@ -35,6 +36,14 @@ pub fn random_message() -> String {
     messages.choose(&mut rng).unwrap().to_string()
 }
 
+/// Keeps a user in the room they joined: they read what's sent to it
+/// until they leave
+fn listen(mut receiver: RoomReceiver) {
+    tokio::spawn(
+        async move { while let Ok(_) | Err(ChatError::Lagged(_)) = receiver.recv().await {} },
+    );
+}
+
 /// Manages room creation and deletion
 async fn manage_rooms(
     server: Arc<Mutex<ChatServer>>,
@@ -64,10 +73,15 @@ async fn manage_rooms(
 
                         let bot_name = format!("{}-bot", &room);
                         let mut srv2 = server.lock().await;
-                        if let Err(e) = srv2.join_room(&room, bot_name.clone()).await {
-                            eprintln!("[ERROR]: adding bot '{}' to '{}': {:?}", bot_name, room, e);
-                        } else {
-                            println!("[LOG]: Created room '{}' + bot '{}'", room, bot_name);
+                        match srv2.join_room(&room, bot_name.clone()).await {
+                            Ok(receiver) => {
+                                listen(receiver);
+                                println!("[LOG]: Created room '{}' + bot '{}'", room, bot_name);
+                            }
+                            Err(e) => eprintln!(
+                                "[ERROR]: adding bot '{}' to '{}': {:?}",
+                                bot_name, room, e
+                            ),
                         }
                     }
                     Err(ChatError::RoomExists) => {
@@ -159,18 +173,20 @@ async fn manage_users(
                     let result = srv.join_room(&chosen_room, new_user.clone()).await;
                     drop(srv);
 
-                    if let Err(e) = result {
-                        eprintln!(
+                    match result {
+                        Ok(receiver) => {
+                            listen(receiver);
+                            println!("[LOG]: User '{}' joined room '{}'", new_user, chosen_room);
+                            users_in_room
+                                .lock()
+                                .await
+                                .entry(chosen_room)
+                                .and_modify(|list| list.push(new_user));
+                        }
+                        Err(e) => eprintln!(
                             "[ERROR]: Adding '{}' to '{}': {:?}",
                             new_user, chosen_room, e
-                        );
-                    } else {
-                        println!("[LOG]: User '{}' joined room '{}'", new_user, chosen_room);
-                        users_in_room
-                            .lock()
-                            .await
-                            .entry(chosen_room)
-                            .and_modify(|list| list.push(new_user));
+                        ),
                     }
                 }
             }
@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::chat_room::RoomReceiver;
+use crate::chat_server::ChatServer;
+use crate::error::ChatError;
+use crate::message::ChatMessage;
+
+const HELP: &str = "commands: /nick NAME, /join ROOM, /leave ROOM, /rooms, /msg ROOM TEXT, /quit";
+
+/// The longest line a client may send, newline included; a client sending
+/// a longer one is disconnected.
+pub const MAX_LINE: usize = 4096;
+
+// lines waiting to be written to a client; once full, room messages wait
+// in the room's channel, and a client too slow to catch up misses some
+const OUT_CAPACITY: usize = 64;
+
+// what every connection shares
+struct Shared {
+    server: Mutex<ChatServer>,
+    // nicks in use, so that two clients can't talk as the same user
+    nicks: Mutex<HashSet<String>>,
+}
+
+/// Accepts clients on `listener` forever, each connection being a session
+/// speaking a line protocol: one command per line, see `HELP`. Replies
+/// start with `* `, errors with `! `, and messages from the joined rooms
+/// arrive as `[room] nick: text`.
+pub async fn serve(listener: TcpListener) {
+    let shared = Arc::new(Shared {
+        server: Mutex::new(ChatServer::new().await),
+        nicks: Mutex::new(HashSet::new()),
+    });
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("[ERROR]: accepting a client: {}", e);
+                continue;
+            }
+        };
+        println!("[LOG]: Client {} connected", addr);
+        let shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            if let Err(e) = run_session(stream, shared).await {
+                eprintln!("[ERROR]: client {}: {}", addr, e);
+            }
+            println!("[LOG]: Client {} disconnected", addr);
+        });
+    }
+}
+
+struct Session {
+    shared: Arc<Shared>,
+    nick: Option<String>,
+    // joined rooms, with the task forwarding each one's messages
+    rooms: HashMap<String, JoinHandle<()>>,
+    // lines for the client
+    out: mpsc::Sender<String>,
+}
+
+async fn run_session(stream: TcpStream, shared: Arc<Shared>) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+
+    // a single writer, so that replies and room messages never interleave
+    let (out, mut out_rx) = mpsc::channel::<String>(OUT_CAPACITY);
+    let write_task = tokio::spawn(async move {
+        while let Some(mut line) = out_rx.recv().await {
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session {
+        shared,
+        nick: None,
+        rooms: HashMap::new(),
+        out,
+    };
+    session.reply(format!("* welcome! {}", HELP)).await;
+
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    let result = loop {
+        line.clear();
+        let mut limited = (&mut reader).take(MAX_LINE as u64);
+        match limited.read_until(b'\n', &mut line).await {
+            Ok(0) => break Ok(()),
+            Ok(_) if line.len() == MAX_LINE && !line.ends_with(b"\n") => {
+                session
+                    .reply(format!("! lines are limited to {} bytes", MAX_LINE))
+                    .await;
+                break Ok(());
+            }
+            Ok(_) => {
+                // nc users may not type UTF-8
+                let text = String::from_utf8_lossy(&line);
+                if !session.handle(text.trim()).await {
+                    break Ok(());
+                }
+            }
+            Err(e) => break Err(e),
+        }
+    };
+
+    session.close().await;
+    // the writer stops once every sender is gone, after writing what's left
+    drop(session);
+    let _ = write_task.await;
+    result
+}
+
+impl Session {
+    async fn reply(&self, line: impl Into<String>) {
+        let _ = self.out.send(line.into()).await;
+    }
+
+    // runs one line from the client; false once it asks to quit
+    async fn handle(&mut self, line: &str) -> bool {
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let result = match command {
+            "" => Ok(()),
+            "/nick" => self.nick(rest).await,
+            "/join" => self.join(rest).await,
+            "/leave" => self.leave(rest).await,
+            "/rooms" => self.list_rooms().await,
+            "/msg" => self.msg(rest).await,
+            "/help" => {
+                self.reply(format!("* {}", HELP)).await;
+                Ok(())
+            }
+            "/quit" => return false,
+            _ if !command.starts_with('/') => Err("send messages with /msg ROOM TEXT".to_string()),
+            _ => Err(format!("unknown command {}; {}", command, HELP)),
+        };
+        if let Err(message) = result {
+            self.reply(format!("! {}", message)).await;
+        }
+        true
+    }
+
+    async fn nick(&mut self, name: &str) -> Result<(), String> {
+        if !is_one_word(name) {
+            return Err("usage: /nick NAME".to_string());
+        }
+        if !self.rooms.is_empty() {
+            return Err("leave your rooms before changing nick".to_string());
+        }
+        {
+            let mut nicks = self.shared.nicks.lock().await;
+            if self.nick.as_deref() != Some(name) && !nicks.insert(name.to_string()) {
+                return Err(format!("nick {} is taken", name));
+            }
+            if let Some(old) = self.nick.take() {
+                if old != name {
+                    nicks.remove(&old);
+                }
+            }
+        }
+        self.nick = Some(name.to_string());
+        self.reply(format!("* you are now {}", name)).await;
+        Ok(())
+    }
+
+    async fn join(&mut self, room: &str) -> Result<(), String> {
+        if !is_one_word(room) {
+            return Err("usage: /join ROOM".to_string());
+        }
+        let nick = self
+            .nick
+            .clone()
+            .ok_or("pick a nick first with /nick NAME")?;
+        if self.rooms.contains_key(room) {
+            return Err(format!("already in {}", room));
+        }
+
+        // rooms come into being when someone joins them
+        let rx = {
+            let mut srv = self.shared.server.lock().await;
+            match srv.create_room(room.to_string()).await {
+                Ok(_) | Err(ChatError::RoomExists) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+            srv.join_room(room, nick).await.map_err(|e| e.to_string())?
+        };
+        let forwarder = tokio::spawn(forward(rx, self.out.clone()));
+        self.rooms.insert(room.to_string(), forwarder);
+        self.reply(format!("* joined {}", room)).await;
+        Ok(())
+    }
+
+    async fn leave(&mut self, room: &str) -> Result<(), String> {
+        if !self.part(room).await {
+            return Err(format!("not in {}", room));
+        }
+        self.reply(format!("* left {}", room)).await;
+        Ok(())
+    }
+
+    // leaves the room and stops its forwarder, which may be waiting for
+    // room in `out`, removing the room once empty; false if the session
+    // wasn't in it
+    async fn part(&mut self, room: &str) -> bool {
+        let Some(forwarder) = self.rooms.remove(room) else {
+            return false;
+        };
+        if let Some(nick) = &self.nick {
+            let mut srv = self.shared.server.lock().await;
+            let _ = srv.leave_room(room, nick).await;
+            match srv.remove_room(room).await {
+                Ok(_) => println!("[LOG]: Removed room '{}'", room),
+                Err(ChatError::RoomNotEmpty) => {}
+                Err(e) => eprintln!("[ERROR]: removing room '{}': {:?}", room, e),
+            }
+        }
+        forwarder.abort();
+        let _ = forwarder.await;
+        true
+    }
+
+    async fn list_rooms(&self) -> Result<(), String> {
+        let mut rooms = self.shared.server.lock().await.list_rooms();
+        rooms.sort();
+        if rooms.is_empty() {
+            self.reply("* no rooms yet; /join ROOM creates one").await;
+        } else {
+            self.reply(format!("* rooms: {}", rooms.join(", "))).await;
+        }
+        Ok(())
+    }
+
+    async fn msg(&self, rest: &str) -> Result<(), String> {
+        let Some((room, text)) = rest.split_once(char::is_whitespace) else {
+            return Err("usage: /msg ROOM TEXT".to_string());
+        };
+        if !self.rooms.contains_key(room) {
+            return Err(format!("join {} first", room));
+        }
+        let Some(nick) = &self.nick else {
+            return Err("pick a nick first with /nick NAME".to_string());
+        };
+        let message = ChatMessage {
+            sender: nick.clone(),
+            room: room.to_string(),
+            content: text.trim().to_string(),
+            timestamp: chrono::Utc::now(),
+        };
+        let srv = self.shared.server.lock().await;
+        srv.send_message(room, message)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    // leaves every room and gives up the nick, once the client is gone
+    async fn close(&mut self) {
+        let rooms: Vec<String> = self.rooms.keys().cloned().collect();
+        for room in rooms {
+            self.part(&room).await;
+        }
+        if let Some(nick) = self.nick.take() {
+            self.shared.nicks.lock().await.remove(&nick);
+        }
+    }
+}
+
+// writes each message the user gets from a room to the client, until they
+// leave it or the client goes away
+async fn forward(mut rx: RoomReceiver, out: mpsc::Sender<String>) {
+    loop {
+        let line = match rx.recv().await {
+            Ok(message) => format!("[{}] {}: {}", message.room, message.sender, message.content),
+            // a slow client misses what didn't fit in the room's channel
+            Err(e @ ChatError::Lagged(_)) => format!("! {}", e),
+            Err(_) => break,
+        };
+        if out.send(line).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn is_one_word(s: &str) -> bool {
+    !s.is_empty() && !s.contains(char::is_whitespace)
+}
@@ -0,0 +1,166 @@
+use mpsc_chat::{serve, MAX_LINE};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+// a server on a free port of localhost, for the rest of the test
+async fn start() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(listener));
+    addr
+}
+
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Client {
+    // connects and reads the welcome line
+    async fn connect(addr: SocketAddr) -> Client {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        stream.set_nodelay(true).unwrap();
+        let (reader, writer) = stream.into_split();
+        let mut client = Client {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        };
+        assert!(client.line().await.unwrap().starts_with("* welcome!"));
+        client
+    }
+
+    // connects and takes `nick`
+    async fn named(addr: SocketAddr, nick: &str) -> Client {
+        let mut client = Client::connect(addr).await;
+        client.send(&format!("/nick {}", nick)).await;
+        client.expect(&format!("* you are now {}", nick)).await;
+        client
+    }
+
+    async fn send(&mut self, line: &str) {
+        let line = format!("{}\n", line);
+        self.writer.write_all(line.as_bytes()).await.unwrap();
+    }
+
+    // the next line from the server, None once it hangs up
+    async fn line(&mut self) -> Option<String> {
+        timeout(Duration::from_secs(5), self.lines.next_line())
+            .await
+            .expect("no reply from the server")
+            .unwrap()
+    }
+
+    async fn expect(&mut self, expected: &str) {
+        assert_eq!(self.line().await.as_deref(), Some(expected));
+    }
+}
+
+#[tokio::test]
+async fn join_talk_and_leave() {
+    let addr = start().await;
+    let mut alice = Client::named(addr, "alice").await;
+    let mut bob = Client::named(addr, "bob").await;
+
+    alice.send("/join rust").await;
+    alice.expect("* joined rust").await;
+    bob.send("/join rust").await;
+    bob.expect("* joined rust").await;
+    bob.send("/rooms").await;
+    bob.expect("* rooms: rust").await;
+
+    alice.send("/msg rust hello there").await;
+    bob.expect("[rust] alice: hello there").await;
+    bob.send("/msg rust hi").await;
+    alice.expect("[rust] bob: hi").await;
+    // no echo of their own messages: the next line is the reply to /rooms
+    alice.send("/rooms").await;
+    alice.expect("* rooms: rust").await;
+
+    bob.send("/leave rust").await;
+    bob.expect("* left rust").await;
+    alice.send("/msg rust anyone?").await;
+    bob.send("/msg rust back").await;
+    bob.expect("! join rust first").await;
+
+    // the last one out removes the room
+    alice.send("/leave rust").await;
+    alice.expect("* left rust").await;
+    alice.send("/rooms").await;
+    alice.expect("* no rooms yet; /join ROOM creates one").await;
+
+    bob.send("/quit").await;
+    assert_eq!(bob.line().await, None);
+}
+
+#[tokio::test]
+async fn mistakes_are_reported() {
+    let addr = start().await;
+    let mut alice = Client::connect(addr).await;
+    alice.send("/join rust").await;
+    alice.expect("! pick a nick first with /nick NAME").await;
+    alice.send("hello").await;
+    alice.expect("! send messages with /msg ROOM TEXT").await;
+    alice.send("/nick alice").await;
+    alice.expect("* you are now alice").await;
+    alice.send("/leave rust").await;
+    alice.expect("! not in rust").await;
+
+    let mut other = Client::connect(addr).await;
+    other.send("/nick alice").await;
+    other.expect("! nick alice is taken").await;
+    // a nick is free again once its user is gone
+    alice.send("/quit").await;
+    assert_eq!(alice.line().await, None);
+    other.send("/nick alice").await;
+    other.expect("* you are now alice").await;
+}
+
+#[tokio::test]
+async fn long_lines_are_refused() {
+    let addr = start().await;
+    let mut alice = Client::named(addr, "alice").await;
+    // the limit counts the newline
+    alice.send(&"x".repeat(MAX_LINE - 1)).await;
+    alice.expect("! send messages with /msg ROOM TEXT").await;
+    alice.send(&"x".repeat(MAX_LINE)).await;
+    alice
+        .expect(&format!("! lines are limited to {} bytes", MAX_LINE))
+        .await;
+    assert_eq!(alice.line().await, None);
+}
+
+#[tokio::test]
+async fn slow_readers_miss_messages() {
+    let addr = start().await;
+    let mut alice = Client::named(addr, "alice").await;
+    let mut bob = Client::named(addr, "bob").await;
+    alice.send("/join rust").await;
+    alice.expect("* joined rust").await;
+    bob.send("/join rust").await;
+    bob.expect("* joined rust").await;
+
+    // alice reads nothing while bob sends more than the socket buffers
+    // and her queue hold. Each message waits for a reply, so that every
+    // receiver keeps up with the room but hers, which doesn't hold bob up.
+    let text = "x".repeat(4000);
+    for _ in 0..4000 {
+        bob.send(&format!("/msg rust {}", text)).await;
+        bob.send("/rooms").await;
+        bob.expect("* rooms: rust").await;
+    }
+
+    let mut received = 0;
+    loop {
+        let line = alice.line().await.unwrap();
+        if line.starts_with("! missed ") {
+            break;
+        }
+        assert_eq!(line, format!("[rust] bob: {}", text));
+        received += 1;
+    }
+    assert!(received < 4000);
+}